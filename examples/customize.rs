@@ -1,4 +1,4 @@
-use rqr::{StringRenderer, QrBuilder, ECLevel, Version, Mask, Mode};
+use rqr::{Renderer, StringRenderer, QrBuilder, ECLevel, Version, Mask, Mode};
 
 fn main() {
     // You can specify more in detail if you interface against the builder.
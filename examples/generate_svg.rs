@@ -1,4 +1,4 @@
-use rqr::{Qr, SvgRenderer, Color};
+use rqr::{Qr, Renderer, SvgRenderer, Color};
 
 fn main() {
     let qr = Qr::new("HELLO WORLD").unwrap();
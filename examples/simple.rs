@@ -1,4 +1,4 @@
-use rqr::{Qr, StringRenderer};
+use rqr::{Qr, Renderer, StringRenderer};
 
 fn main() {
     let qr = Qr::new("HELLO WORLD").unwrap();
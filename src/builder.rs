@@ -2,21 +2,43 @@
 //!
 //! Separated from the Qr struct to keep a simple and safe API.
 //! But it's still possible to interface with the builder directly.
-use crate::data;
+use crate::data::{self, Fnc1, Segment, StructuredAppend};
+use crate::decode;
 use crate::ec::ECLevel;
 use crate::ec;
+use crate::eci::Eci;
 use crate::info;
 use crate::mask::Mask;
 use crate::mask;
 use crate::matrix::{Matrix, Module};
-use crate::mode::Mode;
-use crate::qr::Qr;
+use crate::mode::{self, Mode};
+use crate::qr::{Qr, MicroQr, Rmqr};
 use crate::render;
-use crate::version::Version;
+use crate::version::{Version, MicroVersion, RmqrVersion};
 
 use bitvec::*;
+use std::cmp;
+
+/// Returns the centers of the alignment patterns in a version,
+/// except those overlapping the finders.
+pub fn alignment_centers(v: Version) -> Vec<(usize, usize)> {
+    let locations = ALIGNMENT_LOCATIONS[v.index()];
+    let last = v.size() - 7;
+    let mut res = Vec::new();
+    for y in locations.iter() {
+        for x in locations.iter() {
+            // Corners with a finder.
+            let overlaps = (*x == 6 && (*y == 6 || *y == last)) || (*x == last && *y == 6);
+            if !overlaps {
+                res.push((*x, *y));
+            }
+        }
+    }
+    res
+}
 
 /// Builder for a QR code.
+#[derive(Clone)]
 pub struct QrBuilder {
     /// Version to use.
     /// If not set a minimal version will be calculated.
@@ -28,8 +50,24 @@ pub struct QrBuilder {
     /// If not set ECLevel::Q, which recovers 25% of data, will be used.
     pub ecl: ECLevel,
     /// Encoding mode to use.
-    /// If not set will be inferred from input data.
+    /// If not set the input data will be split into segments of different modes,
+    /// chosen to minimize the size.
     pub mode: Option<Mode>,
+    /// Character set of byte mode data.
+    /// If not set UTF-8 will be used for data outside of ISO-8859-1.
+    pub eci: Option<Eci>,
+    /// Structured Append header, placed before the data segments.
+    /// If not set the QR code holds the whole message.
+    pub structured_append: Option<StructuredAppend>,
+    /// FNC1 mode, marks the data as formatted per GS1 or another industry standard.
+    /// If not set the data isn't given any special interpretation.
+    pub fnc1: Option<Fnc1>,
+    /// Decode the finished matrix and compare it against the input.
+    /// If not set the matrix isn't checked.
+    pub verify: bool,
+
+    /// Encoded data segments.
+    pub segments: Vec<Segment>,
 
     /// Resulting matrix.
     ///
@@ -46,6 +84,23 @@ pub enum Error {
     MessageTooLong,
     /// The builder was in an incomplete state when trying to create a QR.
     IncompleteBuilder,
+    /// The ECI character set can't encode the supplied message.
+    UnsupportedEci,
+    /// The error correction level isn't available for the version.
+    UnsupportedEcl,
+    /// The finished matrix doesn't decode back to the input.
+    VerificationFailed,
+    /// No segments were added before building.
+    NoData,
+    /// The Structured Append header doesn't have 1 to 16 symbols,
+    /// or the index is out of range.
+    InvalidStructuredAppend,
+}
+
+impl Default for QrBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl QrBuilder {
@@ -56,7 +111,12 @@ impl QrBuilder {
             mask: None,
             ecl: ECLevel::Q,
             mode: None,
+            eci: None,
+            structured_append: None,
+            fnc1: None,
+            verify: false,
 
+            segments: Vec::new(),
             matrix: Matrix::new(0),
         }
     }
@@ -87,39 +147,204 @@ impl QrBuilder {
         self
     }
 
+    /// Set the ECI character set to use for byte mode data.
+    /// Will otherwise be UTF-8 if the data is outside of ISO-8859-1.
+    pub fn eci(mut self, eci: Eci) -> Self {
+        self.eci = Some(eci);
+        self
+    }
+
+    /// Set the Structured Append header, marking the QR code as one
+    /// of several which together holds the message.
+    pub fn structured_append(mut self, header: StructuredAppend) -> Self {
+        self.structured_append = Some(header);
+        self
+    }
+
+    /// Set FNC1 mode. GS separators in the data are then encoded as %
+    /// in alphanumeric mode, and % as %%.
+    /// Must be set before pushing alphanumeric segments.
+    pub fn fnc1(mut self, fnc1: Fnc1) -> Self {
+        self.fnc1 = Some(fnc1);
+        self
+    }
+
+    /// Decode the finished matrix and fail with `Error::VerificationFailed`
+    /// if the data, error correction, mask or version doesn't match.
+    /// The data is compared against the input string or bytes, or against
+    /// the segments when building from pushed segments.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Add a segment.
+    pub fn push(mut self, segment: Segment) -> Self {
+        self.segments.push(segment);
+        self
+    }
+
+    /// Add a numeric segment.
+    /// Fails if the string contains anything other than digits.
+    pub fn push_numeric(self, s: &str) -> Result<Self, Error> {
+        self.push_checked(s, Mode::Numeric)
+    }
+
+    /// Add an alphanumeric segment.
+    /// Fails if the string contains characters outside of the alphanumeric set.
+    /// In FNC1 mode GS separators are allowed as well.
+    pub fn push_alphanumeric(self, s: &str) -> Result<Self, Error> {
+        self.push_checked(s, Mode::Alphanumeric)
+    }
+
+    /// Add a kanji segment.
+    /// Fails if the string contains characters which aren't double-byte Shift JIS.
+    pub fn push_kanji(self, s: &str) -> Result<Self, Error> {
+        self.push_checked(s, Mode::Kanji)
+    }
+
+    /// Add a byte segment.
+    /// The bytes are interpreted in the character set of the latest ECI,
+    /// or ISO-8859-1 if there is none.
+    pub fn push_bytes(self, v: &[u8]) -> Self {
+        self.push(Segment::bytes(v))
+    }
+
+    /// Add an ECI segment, specifying the character set of the byte segments
+    /// that follows. Unlike `eci` it doesn't apply to strings added later.
+    pub fn push_eci(self, eci: Eci) -> Self {
+        self.push(Segment::eci(eci))
+    }
+
     /// Build all elements and generate a QR code.
     pub fn into(mut self, s: &str) -> Result<Qr, Error> {
         self.add_all(s)?;
         self.into_qr()
     }
 
+    /// Build all elements from binary data and generate a QR code.
+    pub fn into_bytes(mut self, v: &[u8]) -> Result<Qr, Error> {
+        self.add_all_bytes(v)?;
+        self.into_qr()
+    }
+
+    /// Build all elements and generate QR codes, splitting the string
+    /// over up to 16 codes using Structured Append if it doesn't fit in one.
+    ///
+    /// As few codes as possible are used, with the data split evenly between them.
+    /// If everything fits in a single code it's returned without a header.
+    /// Byte mode data is in the same character set in all codes.
+    pub fn into_structured_append(self, s: &str) -> Result<Vec<Qr>, Error> {
+        // Byte offsets of the characters, and of the end.
+        let offsets: Vec<usize> = s.char_indices().map(|(i, _)| i)
+            .chain(std::iter::once(s.len()))
+            .collect();
+        let split = |builder: QrBuilder| {
+            builder.split_structured_append(offsets.len() - 1, |builder, from, to| {
+                let chunk = &s[offsets[from]..offsets[to]];
+                builder.ensure_settings(chunk)?;
+                Ok(Input::Text(chunk))
+            })
+        };
+        let qrs = split(self.clone())?;
+        // Without an ECI each code chooses its own character set,
+        // which a reader would carry over to the following codes.
+        if qrs.iter().any(|qr| qr.eci != qrs[0].eci) {
+            return split(self.eci(Eci::UTF8));
+        }
+        Ok(qrs)
+    }
+
+    /// Build all elements from binary data and generate QR codes, splitting
+    /// the data over up to 16 codes using Structured Append if it doesn't fit in one.
+    pub fn into_structured_append_bytes(self, v: &[u8]) -> Result<Vec<Qr>, Error> {
+        self.split_structured_append(v.len(), |builder, from, to| {
+            builder.ensure_byte_settings(&v[from..to])?;
+            Ok(Input::Bytes(&v[from..to]))
+        })
+    }
+
+    /// Build all elements from the added segments and generate a QR code.
+    /// Fails with `Error::NoData` if no segments were added.
+    pub fn build(mut self) -> Result<Qr, Error> {
+        if self.segments.is_empty() {
+            return Err(Error::NoData);
+        }
+        self.add_all_segments()?;
+        self.into_qr()
+    }
+
     /// Convert the builder into a QR code.
     pub fn into_qr(self) -> Result<Qr, Error> {
         if !self.complete() {
             return Err(Error::IncompleteBuilder);
         }
 
+        let eci = self.eci.or_else(|| first_eci(&self.segments));
         Ok(Qr {
+            mode: self.main_mode(),
             matrix: self.matrix,
 
             version: self.version.unwrap(),
             ecl: self.ecl,
-            mode: self.mode.unwrap(),
+            eci,
+            structured_append: self.structured_append,
+            fnc1: self.fnc1,
             mask: self.mask.unwrap(),
         })
     }
 
+    /// Returns the mode of the data segment holding the most characters.
+    fn main_mode(&self) -> Mode {
+        self.segments.iter()
+            .filter(|x| matches!(x.mode, Mode::Numeric | Mode::Alphanumeric |
+                                         Mode::Byte | Mode::Kanji))
+            .max_by_key(|x| x.char_count)
+            .map(|x| x.mode)
+            .unwrap_or(Mode::Byte)
+    }
+
     /// Add all elements of a QR code.
+    /// Replaces any added segments with segments from the string.
+    /// If verification is enabled the decoded text is compared against the string.
     pub fn add_all(&mut self, s: &str) -> Result<(), Error> {
         self.ensure_settings(s)?;
-        self.add_fun_patterns();
-        self.add_data(s)?;
-        self.mask_data();
-        self.add_info();
+        self.add_elements()?;
+        self.verify_input(Input::Text(s)).map(|_| ())
+    }
+
+    /// Add all elements of a QR code from binary data, encoded in byte mode.
+    /// Replaces any added segments.
+    /// If verification is enabled the decoded data is compared against the data.
+    pub fn add_all_bytes(&mut self, v: &[u8]) -> Result<(), Error> {
+        self.ensure_byte_settings(v)?;
+        self.add_elements()?;
+        self.verify_input(Input::Bytes(v)).map(|_| ())
+    }
+
+    /// Add all elements of a QR code from the added segments.
+    /// If verification is enabled the decoded segments are compared against them.
+    pub fn add_all_segments(&mut self) -> Result<(), Error> {
+        self.add_elements()?;
+        if self.verify {
+            self.verify_matrix()?;
+        }
 
         Ok(())
     }
 
+    /// Decode the matrix and compare it against the segments and settings.
+    /// Done by `add_all_segments` if verification is enabled, call it after
+    /// the last step when building step by step.
+    pub fn verify_matrix(&self) -> Result<(), Error> {
+        let decoded = self.decode_matrix()?;
+        if decoded.segments == self.segments {
+            Ok(())
+        } else {
+            Err(Error::VerificationFailed)
+        }
+    }
+
     /// Add function patterns.
     pub fn add_fun_patterns(&mut self) {
         self.add_finders();
@@ -130,20 +355,24 @@ impl QrBuilder {
     }
 
     /// Add data.
+    /// Replaces any added segments with segments from the string.
     pub fn add_data(&mut self, s: &str) -> Result<(), Error> {
         self.ensure_settings(s)?;
+        self.add_segment_data()
+    }
+
+    /// Add data from the added segments.
+    ///
+    /// The segments are concatenated and followed by a terminator and padding
+    /// up to the capacity, before error correction is added.
+    pub fn add_segment_data(&mut self) -> Result<(), Error> {
+        self.ensure_segment_settings()?;
 
         let version = self.version.unwrap();
-        let mode = self.mode.unwrap();
         let ecl = self.ecl;
 
-        let v = data::encode_with_mode(
-            s,
-            mode,
-            version,
-            ecl
-        );
-        let v = ec::add(v, self.version.unwrap(), self.ecl);
+        let v = data::encode_segments(&self.segments, version, ecl);
+        let v = ec::add(v, version, ecl);
         self.add_raw_data(&v);
 
         Ok(())
@@ -152,7 +381,7 @@ impl QrBuilder {
     /// Add raw data.
     pub fn add_raw_data(&mut self, v: &BitVec) {
         let mut vi = 0;
-        for (x, y) in ZigZagIt::new(self.matrix.size) {
+        for (x, y) in ZigZagIt::new(self.matrix.width) {
             if self.matrix.is_fun(x, y) { continue; }
             self.matrix.set_data(x, y, v[vi]);
             vi += 1;
@@ -202,12 +431,54 @@ impl QrBuilder {
         }
     }
 
+    // Add all elements from the segments, without verification.
+    fn add_elements(&mut self) -> Result<(), Error> {
+        self.ensure_segment_settings()?;
+        self.add_fun_patterns();
+        self.add_segment_data()?;
+        self.mask_data();
+        self.add_info();
+        Ok(())
+    }
+
+    // Decode the matrix and compare it against the settings.
+    fn decode_matrix(&self) -> Result<decode::Decoded, Error> {
+        let decoded = decode::decode(&self.matrix).map_err(|_| Error::VerificationFailed)?;
+        if Some(decoded.version) == self.version &&
+           decoded.ecl == self.ecl &&
+           Some(decoded.mask) == self.mask &&
+           decoded.structured_append == self.structured_append &&
+           decoded.fnc1 == self.fnc1 {
+            Ok(decoded)
+        } else {
+            Err(Error::VerificationFailed)
+        }
+    }
+
+    // Decode the matrix and compare it against the settings and the input,
+    // if verification is enabled. Returns the decoded data.
+    fn verify_input(&self, input: Input) -> Result<Option<Vec<u8>>, Error> {
+        if !self.verify {
+            return Ok(None);
+        }
+        let decoded = self.decode_matrix()?;
+        let matches = match input {
+            Input::Text(s) => decoded.text.as_deref() == Some(s),
+            Input::Bytes(v) => decoded.data == v,
+        };
+        if matches {
+            Ok(Some(decoded.data))
+        } else {
+            Err(Error::VerificationFailed)
+        }
+    }
+
     /// Return true if the build is complete.
     fn complete(&self) -> bool {
-        if self.version.is_none() || self.mode.is_none() || self.mask.is_none() {
+        if self.version.is_none() || self.mask.is_none() {
             return false;
         }
-        if self.matrix.size == 0 {
+        if self.matrix.modules.is_empty() {
             return false;
         }
         self.matrix.complete()
@@ -215,185 +486,970 @@ impl QrBuilder {
 
     // Ensure we have required settings, otherwise decide from string.
     fn ensure_settings(&mut self, s: &str) -> Result<(), Error> {
-        match self.mode {
-            Some(m) if !m.matches(s) => return Err(Error::UnsupportedMode),
-            Some(_) => (),
-            None => self.mode = Some(Mode::from_str(s)),
+        // In FNC1 mode the GS separator is written as % in alphanumeric mode.
+        let escaped;
+        let s = if self.fnc1.is_some() && self.mode == Some(Mode::Alphanumeric) {
+            escaped = data::fnc1_escape(s);
+            escaped.as_str()
+        } else {
+            s
+        };
+
+        if let Some(m) = self.mode {
+            if !m.matches(s) {
+                return Err(Error::UnsupportedMode);
+            }
+        }
+
+        // Byte mode data must be representable in the character set.
+        if let Some(eci) = self.eci {
+            let byte_mode = self.mode.is_none_or(|m| m == Mode::Byte);
+            if byte_mode && eci.encode(s).is_none() {
+                return Err(Error::UnsupportedEci);
+            }
+        }
+
+        // Split into optimal segments unless a mode is specified.
+        // The segments depends on the char count lengths of the version.
+        let (mode, eci, fnc1) = (self.mode, self.eci, self.fnc1.is_some());
+        let segments = |v: Version| match mode {
+            // The mode is checked against the string above.
+            Some(mode) => data::segments_with_mode(s, mode, eci).unwrap(),
+            None if fnc1 => data::optimize_fnc1_segments(s, eci, v),
+            None => data::optimize_segments(s, eci, v),
+        };
+
+        // Try to calculate a minimal version if nothing is provided.
+        if self.version.is_none() {
+            self.version = Version::minimal_by(self.ecl, |v| {
+                let mut segments = segments(v);
+                self.add_headers(&mut segments);
+                data::segments_bit_len(&segments, v)
+            });
+        }
+        // We can either fail to find a minimum or a faulty version was provided before.
+        let version = self.version.ok_or(Error::MessageTooLong)?;
+        let segments = segments(version);
+
+        // Data outside of ISO-8859-1 is encoded in UTF-8, which needs an ECI.
+        if self.eci.is_none() && segments.iter().any(|x| x.mode == Mode::Eci) {
+            self.eci = Some(Eci::UTF8);
+        }
+        self.segments = segments;
+
+        self.ensure_segment_settings()
+    }
+
+    // Ensure we have required settings, otherwise decide from binary data.
+    fn ensure_byte_settings(&mut self, v: &[u8]) -> Result<(), Error> {
+        if self.mode.is_some_and(|m| m != Mode::Byte) {
+            return Err(Error::UnsupportedMode);
+        }
+
+        self.segments.clear();
+        if let Some(eci) = self.eci {
+            self.segments.push(Segment::eci(eci));
         }
+        self.segments.push(Segment::bytes(v));
+
+        self.ensure_segment_settings()
+    }
+
+    // Ensure we have required settings, otherwise decide from the segments.
+    fn ensure_segment_settings(&mut self) -> Result<(), Error> {
+        let mut segments = std::mem::take(&mut self.segments);
+        self.add_headers(&mut segments);
+        self.segments = segments;
 
         // Try to calculate a minimal version if nothing is provided.
         if self.version.is_none() {
-            self.version = Version::minimal(s, self.mode.unwrap(), self.ecl);
+            self.version = Version::minimal_for_segments(&self.segments, self.ecl);
+        }
+        // We can either fail to find a minimum or a faulty version was provided before.
+        let version = self.version.ok_or(Error::MessageTooLong)?;
+        if data::segments_bit_len(&self.segments, version) > info::total_bits(version, self.ecl) {
+            return Err(Error::MessageTooLong);
+        }
+
+        // Ensure the matrix is intialized.
+        if self.matrix.modules.is_empty() {
+            self.matrix = Matrix::new(version.size());
+        }
+
+        Ok(())
+    }
+
+    // Add Structured Append and FNC1 headers unless they're already added.
+    // Structured Append comes before everything else and FNC1 after any ECI.
+    fn add_headers(&self, segments: &mut Vec<Segment>) {
+        if let Some(header) = self.structured_append {
+            if segments.first().is_none_or(|x| x.mode != Mode::StructuredAppend) {
+                segments.insert(0, Segment::structured_append(header));
+            }
+        }
+        if let Some(fnc1) = self.fnc1 {
+            if !segments.iter().any(|x| matches!(x.mode, Mode::Fnc1First | Mode::Fnc1Second)) {
+                data::add_fnc1(segments, fnc1);
+            }
+        }
+    }
+
+    // Split data of a len over as few QR codes as possible.
+    // prepare should setup a builder for the data in [from, to)
+    // and return that data.
+    fn split_structured_append<'a, F>(self, len: usize, prepare: F) -> Result<Vec<Qr>, Error>
+        where F: Fn(&mut QrBuilder, usize, usize) -> Result<Input<'a>, Error>
+    {
+        for total in 1..17 {
+            let chunk_len = cmp::max(len.div_ceil(total), 1);
+            let mut builders = Vec::with_capacity(total);
+            let mut inputs = Vec::with_capacity(total);
+            for index in 0..total {
+                let mut builder = self.clone();
+                // A single code doesn't need a header.
+                // The parity is filled in when all the data is known.
+                if total > 1 {
+                    builder.structured_append = StructuredAppend::new(index, total, 0).ok();
+                }
+                let from = cmp::min(index * chunk_len, len);
+                let to = cmp::min(from + chunk_len, len);
+                match prepare(&mut builder, from, to) {
+                    Ok(input) => {
+                        builders.push(builder);
+                        inputs.push(input);
+                    }
+                    Err(Error::MessageTooLong) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+
+            if builders.len() == total {
+                if total > 1 {
+                    set_parity(&mut builders);
+                }
+                let mut data = Vec::new();
+                let mut qrs = Vec::with_capacity(total);
+                for (mut builder, input) in builders.into_iter().zip(inputs) {
+                    builder.add_elements()?;
+                    if let Some(v) = builder.verify_input(input)? {
+                        data.extend(v);
+                    }
+                    qrs.push(builder.into_qr()?);
+                }
+                // The parity must match the data of all codes.
+                if self.verify && total > 1 &&
+                   qrs[0].structured_append.map(|x| x.parity()) != Some(data::parity(&data)) {
+                    return Err(Error::VerificationFailed);
+                }
+                return Ok(qrs);
+            }
+        }
+        Err(Error::MessageTooLong)
+    }
+
+    fn push_checked(self, s: &str, mode: Mode) -> Result<Self, Error> {
+        let s = if self.fnc1.is_some() && mode == Mode::Alphanumeric {
+            data::fnc1_escape(s)
+        } else {
+            s.to_string()
+        };
+        let segment = Segment::new(&s, mode)?;
+        Ok(self.push(segment))
+    }
+
+    fn add_finders(&mut self) {
+        let size = self.matrix.width;
+
+        self.add_finder(0, 0);
+        self.add_separator(0, 7, 7, 7);
+        self.add_separator(7, 0, 7, 7);
+
+        self.add_finder(size - 7, 0);
+        self.add_separator(size - 8, 7, size - 1, 7);
+        self.add_separator(size - 8, 0, size - 8, 7);
+
+        self.add_finder(0, size - 7);
+        self.add_separator(0, size - 8, 7, size - 8);
+        self.add_separator(7, size - 8, 7, size - 1);
+    }
+
+    // x and y specifies the top left corner
+    fn add_finder(&mut self, x: usize, y: usize) {
+        self.matrix.set_square(x, y, 7, Module::Function(true));
+        self.matrix.set_square_outline(x + 1, y + 1, 5, Module::Function(false));
+    }
+
+    fn add_separator(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        for a in x0..x1 + 1 {
+            for b in y0..y1 + 1 {
+                self.matrix.set(a, b, Module::Function(false));
+            }
+        }
+    }
+
+    fn add_alignments(&mut self) {
+        for (x, y) in alignment_centers(self.version.unwrap()) {
+            self.try_add_alignment(x, y);
+        }
+    }
+
+    // x and y specifies the center point
+    fn try_add_alignment(&mut self, cx: usize, cy: usize) {
+        let x = cx - 2;
+        let y = cy - 2;
+        if !self.matrix.any_in_square(x, y, 4) {
+            self.matrix.set_square(x, y, 5, Module::Function(true));
+            self.matrix.set_square_outline(x + 1, y + 1, 3, Module::Function(false));
+        }
+    }
+
+    fn add_timing_patterns(&mut self) {
+        let offset = 6;
+        for i in offset..self.matrix.width - offset {
+            let v = i % 2 == 0;
+            self.set_timing(i, offset, v);
+            self.set_timing(offset, i, v);
+        }
+    }
+
+    fn set_timing(&mut self, x: usize, y: usize, v: bool) {
+        // Timing patterns should always overlap with finders and alignment modules.
+        if self.matrix.is_fun(x, y) {
+            assert_eq!(self.matrix.is_dark(x, y), v, "timing overlap {},{}", x, y);
+        }
+
+        self.matrix.set(x, y, Module::Function(v));
+    }
+
+    fn add_dark_module(&mut self) {
+        let (x, y) = self.version.unwrap().dark_module_pos();
+        self.matrix.set(x, y, Module::Function(true));
+    }
+
+    fn add_reserved_areas(&mut self) {
+        let size = self.matrix.width;
+
+        // Around top left finder.
+        // Avoid timing pattern.
+        self.reserve_rect(0, 8, 5, 8);
+        self.reserve_rect(7, 8, 8, 8);
+        self.reserve_rect(8, 0, 8, 5);
+        self.reserve_rect(8, 7, 8, 7);
+
+        // Top right.
+        self.reserve_rect(size - 8, 8, size - 1, 8);
+
+        // Bottom left.
+        self.reserve_rect(8, size - 7, 8, size - 1);
+
+        //// Larger versions needs two areas for version information.
+        if self.version.unwrap().extra_version_areas() {
+            self.reserve_rect(0, size - 11, 5, size - 9);
+            self.reserve_rect(size - 11, 0, size - 9, 5);
+        }
+    }
+
+    fn reserve_rect(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        assert!(!self.matrix.any_in_rect(x0, y0, x1, y1));
+        self.matrix.set_rect(x0, y0, x1, y1, Module::Reserved);
+    }
+
+    fn add_format(&mut self, bv: &BitVec) {
+        assert_eq!(bv.len(), 15);
+        let size = self.matrix.width;
+
+        // Info surrounding the top left finder.
+        let mut iter = bv.iter();
+        for x in 0..8 {
+            // Avoid timing pattern.
+            if x == 6 { continue; }
+            self.matrix.set_fun(x, 8, iter.next().unwrap());
+        }
+        for y in (0..9).rev() {
+            // Avoid timing pattern.
+            if y == 6 { continue; }
+            self.matrix.set_fun(8, y, iter.next().unwrap());
+        }
+        assert_eq!(iter.next(), None);
+
+        // Half to the right of the bottom left finder.
+        iter = bv.iter();
+        for y in (size - 7..size).rev() {
+            self.matrix.set_fun(8, y, iter.next().unwrap());
+        }
+        // Rest bottom of the top left finder.
+        for x in (size - 8)..size {
+            self.matrix.set_fun(x, 8, iter.next().unwrap());
+        }
+        assert_eq!(iter.next(), None);
+    }
+
+    fn add_version(&mut self, bv: &BitVec) {
+        assert_eq!(bv.len(), 18);
+        let size = self.matrix.width;
+
+        // Bottom left version block.
+        let mut iter = bv.iter();
+        for x in 0..6 {
+            for y in (size - 11)..(size - 8) {
+                self.matrix.set_fun(x, y, iter.next().unwrap());
+            }
+        }
+        assert_eq!(iter.next(), None);
+
+        // Top right version block.
+        iter = bv.iter();
+        for y in 0..6 {
+            for x in (size - 11)..(size - 8) {
+                self.matrix.set_fun(x, y, iter.next().unwrap());
+            }
+        }
+        assert_eq!(iter.next(), None);
+    }
+
+    /// Convert to debug string.
+    pub fn to_dbg_string(&self) -> String {
+        render::to_dbg_string(&self.matrix)
+    }
+}
+
+// The input of a QR code, which verification compares the decoded data against.
+enum Input<'a> {
+    Text(&'a str),
+    Bytes(&'a [u8]),
+}
+
+// The character set of the first ECI segment, if any.
+fn first_eci(segments: &[Segment]) -> Option<Eci> {
+    segments.iter().find(|x| x.mode == Mode::Eci).map(|x| Eci::from_bitvec(&x.data))
+}
+
+// Set the Structured Append parity of prepared builders, over the data
+// as a reader sees it. Kanji mode data counts as Shift JIS and byte mode
+// data in the character set it's encoded in.
+fn set_parity(builders: &mut [QrBuilder]) {
+    let mut v = Vec::new();
+    for builder in builders.iter() {
+        let version = builder.version.unwrap();
+        let bits = data::encode_segments(&builder.segments, version, builder.ecl);
+        // The segments were just encoded, so they can be parsed back.
+        v.extend(decode::parse(&bits, version).unwrap().data);
+    }
+    let parity = data::parity(&v);
+    for builder in builders.iter_mut() {
+        let header = builder.structured_append.unwrap();
+        let header = StructuredAppend::new(header.index(), header.total(), parity).unwrap();
+        builder.structured_append = Some(header);
+        builder.segments[0] = Segment::structured_append(header);
+    }
+}
+
+/// Builder for a Micro QR code.
+#[derive(Clone)]
+pub struct MicroQrBuilder {
+    /// Version to use.
+    /// If not set a minimal version will be calculated.
+    pub version: Option<MicroVersion>,
+    /// Micro QR mask to use, 0 to 3.
+    /// If not set the optimal mask will be chosen per the QR specification.
+    pub mask: Option<Mask>,
+    /// Error correction level to use, H isn't available.
+    /// If not set ECLevel::L will be used, as it's the only level M1 supports.
+    pub ecl: ECLevel,
+    /// Encoding mode to use.
+    /// If not set the input data will be split into segments of different modes,
+    /// chosen to minimize the size.
+    pub mode: Option<Mode>,
+
+    /// Encoded data segments.
+    pub segments: Vec<Segment>,
+
+    /// Resulting matrix.
+    ///
+    /// Note that even though the matrix is not an Option it might still be invalid.
+    /// This to simplify the implementation.
+    pub matrix: Matrix,
+}
+
+impl Default for MicroQrBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MicroQrBuilder {
+    /// Create a new builder.
+    pub fn new() -> MicroQrBuilder {
+        MicroQrBuilder {
+            version: None,
+            mask: None,
+            ecl: ECLevel::L,
+            mode: None,
+
+            segments: Vec::new(),
+            matrix: Matrix::new(0),
+        }
+    }
+
+    /// Set version. If not set the smallest applicable version will be used.
+    pub fn version(mut self, v: MicroVersion) -> Self {
+        // Override old tmp matrix.
+        self.matrix = Matrix::new(v.size());
+        self.version = Some(v);
+        self
+    }
+
+    /// Set Micro QR mask to use, 0 to 3.
+    /// If not set the best mask will be chosen according to the QR spec.
+    pub fn mask(mut self, mask: Mask) -> Self {
+        assert!(mask.0 <= 3);
+        self.mask = Some(mask);
+        self
+    }
+
+    /// Set error correction. Will default to ECLevel::L.
+    pub fn ecl(mut self, ecl: ECLevel) -> Self {
+        self.ecl = ecl;
+        self
+    }
+
+    /// Set the mode to use, will otherwise be calculated from input.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Add a segment.
+    pub fn push(mut self, segment: Segment) -> Self {
+        self.segments.push(segment);
+        self
+    }
+
+    /// Build all elements and generate a Micro QR code.
+    pub fn into(mut self, s: &str) -> Result<MicroQr, Error> {
+        self.add_all(s)?;
+        self.into_qr()
+    }
+
+    /// Build all elements from the added segments and generate a Micro QR code.
+    /// Fails with `Error::NoData` if no segments were added.
+    pub fn build(mut self) -> Result<MicroQr, Error> {
+        if self.segments.is_empty() {
+            return Err(Error::NoData);
+        }
+        self.add_all_segments()?;
+        self.into_qr()
+    }
+
+    /// Convert the builder into a Micro QR code.
+    pub fn into_qr(self) -> Result<MicroQr, Error> {
+        if self.version.is_none() || self.mask.is_none() || !self.matrix.complete() {
+            return Err(Error::IncompleteBuilder);
+        }
+
+        Ok(MicroQr {
+            mode: self.segments.iter()
+                .max_by_key(|x| x.char_count)
+                .map(|x| x.mode)
+                .unwrap_or(Mode::Numeric),
+            matrix: self.matrix,
+
+            version: self.version.unwrap(),
+            ecl: self.ecl,
+            mask: self.mask.unwrap(),
+        })
+    }
+
+    /// Add all elements of a Micro QR code.
+    /// Replaces any added segments with segments from the string.
+    pub fn add_all(&mut self, s: &str) -> Result<(), Error> {
+        self.ensure_settings(s)?;
+        self.add_all_segments()
+    }
+
+    /// Add all elements of a Micro QR code from the added segments.
+    pub fn add_all_segments(&mut self) -> Result<(), Error> {
+        self.ensure_segment_settings()?;
+        self.add_fun_patterns();
+        self.add_segment_data()?;
+        self.mask_data();
+        self.add_format_info();
+
+        Ok(())
+    }
+
+    /// Add function patterns.
+    /// A single finder in the top left corner with timing patterns
+    /// along the top and left edges.
+    pub fn add_fun_patterns(&mut self) {
+        let size = self.matrix.width;
+
+        self.matrix.set_square(0, 0, 7, Module::Function(true));
+        self.matrix.set_square_outline(1, 1, 5, Module::Function(false));
+        self.matrix.set_rect(0, 7, 7, 7, Module::Function(false));
+        self.matrix.set_rect(7, 0, 7, 6, Module::Function(false));
+
+        for i in 8..size {
+            let v = i % 2 == 0;
+            self.matrix.set(i, 0, Module::Function(v));
+            self.matrix.set(0, i, Module::Function(v));
+        }
+
+        // Format info.
+        self.matrix.set_rect(1, 8, 8, 8, Module::Reserved);
+        self.matrix.set_rect(8, 1, 8, 7, Module::Reserved);
+    }
+
+    /// Add data from the added segments.
+    pub fn add_segment_data(&mut self) -> Result<(), Error> {
+        self.ensure_segment_settings()?;
+
+        let version = self.version.unwrap();
+        let ecl = self.ecl;
+
+        let v = data::encode_micro_segments(&self.segments, version, ecl);
+        let v = ec::add_micro(v, version, ecl);
+        self.add_raw_data(&v);
+
+        Ok(())
+    }
+
+    /// Add raw data.
+    pub fn add_raw_data(&mut self, v: &BitVec) {
+        let mut vi = 0;
+        for (x, y) in ZigZagIt::micro(self.matrix.width) {
+            if self.matrix.is_fun(x, y) { continue; }
+            self.matrix.set_data(x, y, v[vi]);
+            vi += 1;
+        }
+        assert_eq!(vi, v.len());
+    }
+
+    /// Mask data, evaluating the available masks unless one is specified.
+    pub fn mask_data(&mut self) {
+        let (mask, masked) = match self.mask {
+            Some(mask) => (mask, mask::apply_micro_mask(mask, &self.matrix)),
+            None => mask::micro_mask(&self.matrix),
+        };
+        self.mask = Some(mask);
+        self.matrix = masked;
+    }
+
+    /// Add format info.
+    pub fn add_format_info(&mut self) {
+        let bv = info::micro_format_info(self.version.unwrap(), self.ecl, self.mask.unwrap());
+        assert_eq!(bv.len(), 15);
+
+        // Below the finder, then upwards to the right of it.
+        for x in 1..9 {
+            self.matrix.set_fun(x, 8, bv[x - 1]);
+        }
+        for y in (1..8).rev() {
+            self.matrix.set_fun(8, y, bv[15 - y]);
+        }
+    }
+
+    /// Convert to debug string.
+    pub fn to_dbg_string(&self) -> String {
+        render::to_dbg_string(&self.matrix)
+    }
+
+    // Ensure we have required settings, otherwise decide from string.
+    fn ensure_settings(&mut self, s: &str) -> Result<(), Error> {
+        // M4 supports every error correction level available to Micro QR codes.
+        if !MicroVersion::new(4).supports_ecl(self.ecl) {
+            return Err(Error::UnsupportedEcl);
+        }
+        // There's no ECI, so byte mode is limited to ISO-8859-1.
+        if let Some(m) = self.mode {
+            if !m.matches(s) || (m == Mode::Byte && mode::to_latin1(s).is_none()) {
+                return Err(Error::UnsupportedMode);
+            }
+        }
+
+        let mode = self.mode;
+        let segments = |v: MicroVersion| match mode {
+            Some(mode) if v.supports_mode(mode) => Segment::new(s, mode).ok().map(|x| vec![x]),
+            Some(_) => None,
+            None => data::optimize_micro_segments(s, v),
+        };
+
+        // Try to calculate a minimal version if nothing is provided.
+        if self.version.is_none() {
+            self.version = MicroVersion::minimal_by(self.ecl, |v| {
+                segments(v).map(|x| data::micro_segments_bit_len(&x, v))
+            });
+        }
+        // If not even M4 can encode the string it's the characters, not the length.
+        if self.version.is_none() && segments(MicroVersion::new(4)).is_none() {
+            return Err(Error::UnsupportedMode);
+        }
+        // We can either fail to find a minimum or a faulty version was provided before.
+        let version = self.version.ok_or(Error::MessageTooLong)?;
+        self.segments = segments(version).ok_or(Error::UnsupportedMode)?;
+
+        self.ensure_segment_settings()
+    }
+
+    // Ensure we have required settings, otherwise decide from the segments.
+    fn ensure_segment_settings(&mut self) -> Result<(), Error> {
+        if !MicroVersion::new(4).supports_ecl(self.ecl) {
+            return Err(Error::UnsupportedEcl);
+        }
+        // Try to calculate a minimal version if nothing is provided.
+        if self.version.is_none() {
+            self.version = MicroVersion::minimal_for_segments(&self.segments, self.ecl);
+        }
+        // We can either fail to find a minimum or a faulty version was provided before.
+        let version = self.version.ok_or(Error::MessageTooLong)?;
+        if !version.supports_ecl(self.ecl) {
+            return Err(Error::UnsupportedEcl);
+        }
+        if !self.segments.iter().all(|x| version.supports_mode(x.mode)) {
+            return Err(Error::UnsupportedMode);
+        }
+        let bit_len = data::micro_segments_bit_len(&self.segments, version);
+        if bit_len > info::micro_data_bits(version, self.ecl) {
+            return Err(Error::MessageTooLong);
+        }
+
+        // Ensure the matrix is intialized.
+        if self.matrix.modules.is_empty() {
+            self.matrix = Matrix::new(version.size());
+        }
+
+        Ok(())
+    }
+}
+
+/// Builder for a rMQR code.
+#[derive(Clone)]
+pub struct RmqrBuilder {
+    /// Version to use.
+    /// If not set the version with the smallest area will be calculated.
+    pub version: Option<RmqrVersion>,
+    /// Height to restrict the version calculation to, 7 to 17.
+    /// If not set any height may be chosen.
+    pub height: Option<usize>,
+    /// Error correction level to use, only M and H are available.
+    /// If not set ECLevel::M will be used.
+    pub ecl: ECLevel,
+    /// Encoding mode to use.
+    /// If not set the input data will be split into segments of different modes,
+    /// chosen to minimize the size.
+    pub mode: Option<Mode>,
+    /// Character set of byte mode data.
+    /// If not set UTF-8 will be used for data outside of ISO-8859-1.
+    pub eci: Option<Eci>,
+
+    /// Encoded data segments.
+    pub segments: Vec<Segment>,
+
+    /// Resulting matrix.
+    ///
+    /// Note that even though the matrix is not an Option it might still be invalid.
+    /// This to simplify the implementation.
+    pub matrix: Matrix,
+}
+
+impl Default for RmqrBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RmqrBuilder {
+    /// Create a new builder.
+    pub fn new() -> RmqrBuilder {
+        RmqrBuilder {
+            version: None,
+            height: None,
+            ecl: ECLevel::M,
+            mode: None,
+            eci: None,
+
+            segments: Vec::new(),
+            matrix: Matrix::new(0),
+        }
+    }
+
+    /// Set version. If not set the smallest applicable version will be used.
+    pub fn version(mut self, v: RmqrVersion) -> Self {
+        // Override old tmp matrix.
+        self.matrix = Matrix::new_rect(v.width(), v.height());
+        self.version = Some(v);
+        self
+    }
+
+    /// Restrict the calculated version to a height, 7 to 17.
+    pub fn height(mut self, height: usize) -> Self {
+        self.height = Some(height);
+        self
+    }
+
+    /// Set error correction, only M and H are available. Will default to ECLevel::M.
+    pub fn ecl(mut self, ecl: ECLevel) -> Self {
+        self.ecl = ecl;
+        self
+    }
+
+    /// Set the mode to use, will otherwise be calculated from input.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Set the character set of byte mode data.
+    pub fn eci(mut self, eci: Eci) -> Self {
+        self.eci = Some(eci);
+        self
+    }
+
+    /// Add a segment.
+    pub fn push(mut self, segment: Segment) -> Self {
+        self.segments.push(segment);
+        self
+    }
+
+    /// Build all elements and generate a rMQR code.
+    pub fn into(mut self, s: &str) -> Result<Rmqr, Error> {
+        self.add_all(s)?;
+        self.into_qr()
+    }
+
+    /// Build all elements from the added segments and generate a rMQR code.
+    /// Fails with `Error::NoData` if no segments were added.
+    pub fn build(mut self) -> Result<Rmqr, Error> {
+        if self.segments.is_empty() {
+            return Err(Error::NoData);
+        }
+        self.add_all_segments()?;
+        self.into_qr()
+    }
+
+    /// Convert the builder into a rMQR code.
+    pub fn into_qr(self) -> Result<Rmqr, Error> {
+        if self.version.is_none() || self.matrix.modules.is_empty() || !self.matrix.complete() {
+            return Err(Error::IncompleteBuilder);
+        }
+
+        let eci = self.eci.or_else(|| first_eci(&self.segments));
+        Ok(Rmqr {
+            mode: self.segments.iter()
+                .filter(|x| matches!(x.mode, Mode::Numeric | Mode::Alphanumeric |
+                                             Mode::Byte | Mode::Kanji))
+                .max_by_key(|x| x.char_count)
+                .map(|x| x.mode)
+                .unwrap_or(Mode::Byte),
+            matrix: self.matrix,
+
+            version: self.version.unwrap(),
+            ecl: self.ecl,
+            eci,
+        })
+    }
+
+    /// Add all elements of a rMQR code.
+    /// Replaces any added segments with segments from the string.
+    pub fn add_all(&mut self, s: &str) -> Result<(), Error> {
+        self.ensure_settings(s)?;
+        self.add_all_segments()
+    }
+
+    /// Add all elements of a rMQR code from the added segments.
+    pub fn add_all_segments(&mut self) -> Result<(), Error> {
+        self.ensure_segment_settings()?;
+        self.add_fun_patterns();
+        self.add_segment_data()?;
+        self.mask_data();
+        self.add_format_info();
+
+        Ok(())
+    }
+
+    /// Add function patterns.
+    /// A finder in the top left corner and a smaller sub-finder in the bottom right,
+    /// with timing patterns along all edges and alignment patterns between them.
+    pub fn add_fun_patterns(&mut self) {
+        let version = self.version.unwrap();
+        let (w, h) = (version.width(), version.height());
+
+        for x in 0..w {
+            let v = x % 2 == 0;
+            self.matrix.set(x, 0, Module::Function(v));
+            self.matrix.set(x, h - 1, Module::Function(v));
         }
-        // We can either fail to find a minimum or a faulty version was provided before.
-        if self.version.is_none() {
-            return Err(Error::MessageTooLong);
+        for y in 0..h {
+            let v = y % 2 == 0;
+            self.matrix.set(0, y, Module::Function(v));
+            self.matrix.set(w - 1, y, Module::Function(v));
         }
 
-        // Ensure the matrix is intialized.
-        if self.matrix.size == 0 {
-            self.matrix = Matrix::new(self.version.unwrap().size());
+        // Alignment patterns along the top and bottom edges, over the timing
+        // patterns, with vertical timing patterns between them.
+        for &c in RMQR_ALIGNMENT_COLUMNS.iter().find(|x| x.0 == w).unwrap().1 {
+            for y in 3..h - 3 {
+                self.matrix.set(c, y, Module::Function(y % 2 == 0));
+            }
+            for &y in [0, h - 3].iter() {
+                self.matrix.set_square(c - 1, y, 3, Module::Function(true));
+                self.matrix.set(c, y + 1, Module::Function(false));
+            }
         }
 
-        Ok(())
-    }
+        self.matrix.set_square(0, 0, 7, Module::Function(true));
+        self.matrix.set_square_outline(1, 1, 5, Module::Function(false));
+        self.matrix.set_rect(7, 0, 7, 6, Module::Function(false));
+        if h > 7 {
+            self.matrix.set_rect(0, 7, 7, 7, Module::Function(false));
 
-    fn add_finders(&mut self) {
-        let size = self.matrix.size;
+            // Corner pattern in the bottom left.
+            self.matrix.set(0, h - 2, Module::Function(true));
+            self.matrix.set(1, h - 2, Module::Function(false));
+            self.matrix.set(1, h - 1, Module::Function(true));
+        }
 
-        self.add_finder(0, 0);
-        self.add_separator(0, 7, 7, 7);
-        self.add_separator(7, 0, 7, 7);
+        // Corner pattern in the top right.
+        self.matrix.set(w - 2, 0, Module::Function(true));
+        self.matrix.set(w - 2, 1, Module::Function(false));
+        self.matrix.set(w - 1, 1, Module::Function(true));
 
-        self.add_finder(size - 7, 0);
-        self.add_separator(size - 8, 7, size - 1, 7);
-        self.add_separator(size - 8, 0, size - 8, 7);
+        self.matrix.set_square(w - 5, h - 5, 5, Module::Function(true));
+        self.matrix.set_square_outline(w - 4, h - 4, 3, Module::Function(false));
 
-        self.add_finder(0, size - 7);
-        self.add_separator(0, size - 8, 7, size - 8);
-        self.add_separator(7, size - 8, 7, size - 1);
+        // Format info.
+        self.matrix.set_rect(8, 1, 10, 5, Module::Reserved);
+        self.matrix.set_rect(11, 1, 11, 3, Module::Reserved);
+        self.matrix.set_rect(w - 8, h - 6, w - 6, h - 2, Module::Reserved);
+        self.matrix.set_rect(w - 5, h - 6, w - 3, h - 6, Module::Reserved);
     }
 
-    // x and y specifies the top left corner
-    fn add_finder(&mut self, x: usize, y: usize) {
-        self.matrix.set_square(x, y, 7, Module::Function(true));
-        self.matrix.set_square_outline(x + 1, y + 1, 5, Module::Function(false));
-    }
+    /// Add data from the added segments.
+    pub fn add_segment_data(&mut self) -> Result<(), Error> {
+        self.ensure_segment_settings()?;
 
-    fn add_separator(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
-        for a in x0..x1 + 1 {
-            for b in y0..y1 + 1 {
-                self.matrix.set(a, b, Module::Function(false));
-            }
-        }
-    }
+        let version = self.version.unwrap();
+        let ecl = self.ecl;
 
-    fn add_alignments(&mut self) {
-        let locations = ALIGNMENT_LOCATIONS[self.version.unwrap().index()];
-        for x in locations.iter() {
-            for y in locations.iter() {
-                self.try_add_alignment(*x, *y);
-            }
-        }
+        let v = data::encode_rmqr_segments(&self.segments, version, ecl);
+        let v = ec::add_rmqr(v, version, ecl);
+        self.add_raw_data(&v);
+
+        Ok(())
     }
 
-    // x and y specifies the center point
-    fn try_add_alignment(&mut self, cx: usize, cy: usize) {
-        let x = cx - 2;
-        let y = cy - 2;
-        if !self.matrix.any_in_square(x, y, 4) {
-            self.matrix.set_square(x, y, 5, Module::Function(true));
-            self.matrix.set_square_outline(x + 1, y + 1, 3, Module::Function(false));
+    /// Add raw data.
+    pub fn add_raw_data(&mut self, v: &BitVec) {
+        let mut vi = 0;
+        for (x, y) in ZigZagIt::rmqr(self.matrix.width, self.matrix.height) {
+            if self.matrix.is_fun(x, y) { continue; }
+            self.matrix.set_data(x, y, v[vi]);
+            vi += 1;
         }
+        assert_eq!(vi, v.len());
     }
 
-    fn add_timing_patterns(&mut self) {
-        let offset = 6;
-        for i in offset..self.matrix.size - offset {
-            let v = i % 2 == 0;
-            self.set_timing(i, offset, v);
-            self.set_timing(offset, i, v);
-        }
+    /// Mask data. rMQR codes always use the same mask, without evaluation.
+    pub fn mask_data(&mut self) {
+        self.matrix = mask::apply_mask(Mask::new(4), &self.matrix);
     }
 
-    fn set_timing(&mut self, x: usize, y: usize, v: bool) {
-        // Timing patterns should always overlap with finders and alignment modules.
-        if self.matrix.is_fun(x, y) {
-            assert_eq!(self.matrix.is_dark(x, y), v, "timing overlap {},{}", x, y);
+    /// Add format info, next to both the finder and the sub-finder.
+    pub fn add_format_info(&mut self) {
+        let (finder, sub_finder) = info::rmqr_format_info(self.version.unwrap(), self.ecl);
+        let (w, h) = (self.matrix.width, self.matrix.height);
+
+        // Bits are placed from the least significant one, in columns of 5.
+        let bit = |bv: &BitVec, i: usize| bv[17 - i];
+        for j in 0..3 {
+            for i in 0..5 {
+                self.matrix.set_fun(8 + j, 1 + i, bit(&finder, j * 5 + i));
+                self.matrix.set_fun(w - 8 + j, h - 6 + i, bit(&sub_finder, j * 5 + i));
+            }
+        }
+        for i in 0..3 {
+            self.matrix.set_fun(11, 1 + i, bit(&finder, 15 + i));
+            self.matrix.set_fun(w - 5 + i, h - 6, bit(&sub_finder, 15 + i));
         }
-
-        self.matrix.set(x, y, Module::Function(v));
     }
 
-    fn add_dark_module(&mut self) {
-        let (x, y) = self.version.unwrap().dark_module_pos();
-        self.matrix.set(x, y, Module::Function(true));
+    /// Convert to debug string.
+    pub fn to_dbg_string(&self) -> String {
+        render::to_dbg_string(&self.matrix)
     }
 
-    fn add_reserved_areas(&mut self) {
-        let size = self.matrix.size;
+    // Ensure we have required settings, otherwise decide from string.
+    fn ensure_settings(&mut self, s: &str) -> Result<(), Error> {
+        if let Some(m) = self.mode {
+            if !m.matches(s) {
+                return Err(Error::UnsupportedMode);
+            }
+        }
 
-        // Around top left finder.
-        // Avoid timing pattern.
-        self.reserve_rect(0, 8, 5, 8);
-        self.reserve_rect(7, 8, 8, 8);
-        self.reserve_rect(8, 0, 8, 5);
-        self.reserve_rect(8, 7, 8, 7);
+        // Byte mode data must be representable in the character set.
+        if let Some(eci) = self.eci {
+            let byte_mode = self.mode.is_none_or(|m| m == Mode::Byte);
+            if byte_mode && eci.encode(s).is_none() {
+                return Err(Error::UnsupportedEci);
+            }
+        }
+        if !RmqrVersion::supports_ecl(self.ecl) {
+            return Err(Error::UnsupportedEcl);
+        }
 
-        // Top right.
-        self.reserve_rect(size - 8, 8, size - 1, 8);
+        let (mode, eci) = (self.mode, self.eci);
+        let segments = |v: RmqrVersion| match mode {
+            // The mode is checked against the string above.
+            Some(mode) => data::segments_with_mode(s, mode, eci).unwrap(),
+            None => data::optimize_rmqr_segments(s, eci, v),
+        };
 
-        // Bottom left.
-        self.reserve_rect(8, size - 7, 8, size - 1);
+        // Try to calculate a minimal version if nothing is provided.
+        if self.version.is_none() {
+            self.version = RmqrVersion::minimal_by(self.ecl, self.height, |v| {
+                data::rmqr_segments_bit_len(&segments(v), v)
+            });
+        }
+        // We can either fail to find a minimum or a faulty version was provided before.
+        let version = self.version.ok_or(Error::MessageTooLong)?;
+        let segments = segments(version);
 
-        //// Larger versions needs two areas for version information.
-        if self.version.unwrap().extra_version_areas() {
-            self.reserve_rect(0, size - 11, 5, size - 9);
-            self.reserve_rect(size - 11, 0, size - 9, 5);
+        // Data outside of ISO-8859-1 is encoded in UTF-8, which needs an ECI.
+        if self.eci.is_none() && segments.iter().any(|x| x.mode == Mode::Eci) {
+            self.eci = Some(Eci::UTF8);
         }
-    }
+        self.segments = segments;
 
-    fn reserve_rect(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
-        assert!(!self.matrix.any_in_rect(x0, y0, x1, y1));
-        self.matrix.set_rect(x0, y0, x1, y1, Module::Reserved);
+        self.ensure_segment_settings()
     }
 
-    fn add_format(&mut self, bv: &BitVec) {
-        assert_eq!(bv.len(), 15);
-        let size = self.matrix.size;
-
-        // Info surrounding the top left finder.
-        let mut iter = bv.iter();
-        for x in 0..8 {
-            // Avoid timing pattern.
-            if x == 6 { continue; }
-            self.matrix.set_fun(x, 8, iter.next().unwrap());
+    // Ensure we have required settings, otherwise decide from the segments.
+    fn ensure_segment_settings(&mut self) -> Result<(), Error> {
+        if !RmqrVersion::supports_ecl(self.ecl) {
+            return Err(Error::UnsupportedEcl);
         }
-        for y in (0..9).rev() {
-            // Avoid timing pattern.
-            if y == 6 { continue; }
-            self.matrix.set_fun(8, y, iter.next().unwrap());
+        if self.segments.iter().any(|x| x.mode == Mode::StructuredAppend) {
+            return Err(Error::UnsupportedMode);
         }
-        assert_eq!(iter.next(), None);
 
-        // Half to the right of the bottom left finder.
-        iter = bv.iter();
-        for y in (size - 7..size).rev() {
-            self.matrix.set_fun(8, y, iter.next().unwrap());
-        }
-        // Rest bottom of the top left finder.
-        for x in (size - 8)..size {
-            self.matrix.set_fun(x, 8, iter.next().unwrap());
+        // Try to calculate a minimal version if nothing is provided.
+        if self.version.is_none() {
+            self.version = RmqrVersion::minimal_for_segments(&self.segments, self.ecl,
+                                                             self.height);
         }
-        assert_eq!(iter.next(), None);
-    }
-
-    fn add_version(&mut self, bv: &BitVec) {
-        assert_eq!(bv.len(), 18);
-        let size = self.matrix.size;
-
-        // Bottom left version block.
-        let mut iter = bv.iter();
-        for x in 0..6 {
-            for y in (size - 11)..(size - 8) {
-                self.matrix.set_fun(x, y, iter.next().unwrap());
-            }
+        // We can either fail to find a minimum or a faulty version was provided before.
+        let version = self.version.ok_or(Error::MessageTooLong)?;
+        let bit_len = data::rmqr_segments_bit_len(&self.segments, version);
+        if bit_len > info::rmqr_total_bits(version, self.ecl) {
+            return Err(Error::MessageTooLong);
         }
-        assert_eq!(iter.next(), None);
 
-        // Top right version block.
-        iter = bv.iter();
-        for y in 0..6 {
-            for x in (size - 11)..(size - 8) {
-                self.matrix.set_fun(x, y, iter.next().unwrap());
-            }
+        // Ensure the matrix is intialized.
+        if self.matrix.modules.is_empty() {
+            self.matrix = Matrix::new_rect(version.width(), version.height());
         }
-        assert_eq!(iter.next(), None);
-    }
 
-    /// Convert to debug string.
-    pub fn to_dbg_string(&self) -> String {
-        render::to_dbg_string(&self.matrix)
+        Ok(())
     }
 }
 
@@ -403,8 +1459,13 @@ impl QrBuilder {
 // Inside the 2 bit flow it alternates between the right and left field.
 // It also avoids the vertical timing pattern column completely,
 // but it does not automatically skip function patterns.
-struct ZigZagIt {
-    size: usize,
+// Micro QR codes have the timing pattern at the edge, so nothing is avoided.
+// rMQR codes have timing patterns on both the left and right edges,
+// so it starts one column in.
+pub(crate) struct ZigZagIt {
+    height: usize,
+    // Is the vertical timing pattern at the edge?
+    micro: bool,
     // Should we move horizontal next step?
     horizontal_next: bool,
     // Are we moving upwards?
@@ -417,9 +1478,10 @@ struct ZigZagIt {
 }
 
 impl ZigZagIt {
-    fn new(size: usize) -> Self {
+    pub(crate) fn new(size: usize) -> Self {
         Self {
-            size: size,
+            height: size,
+            micro: false,
             horizontal_next: true,
             upwards: true,
             x: size - 1,
@@ -428,6 +1490,25 @@ impl ZigZagIt {
         }
     }
 
+    fn micro(size: usize) -> Self {
+        Self {
+            micro: true,
+            ..Self::new(size)
+        }
+    }
+
+    fn rmqr(width: usize, height: usize) -> Self {
+        Self {
+            height,
+            micro: true,
+            horizontal_next: true,
+            upwards: true,
+            x: width - 2,
+            y: height - 1,
+            valid: true,
+        }
+    }
+
     fn advance(&mut self) {
         if self.horizontal_next {
             self.move_horizontally();
@@ -439,14 +1520,14 @@ impl ZigZagIt {
     fn move_horizontally(&mut self) {
         match self.x {
             0 => self.valid = false,
-            6 => self.x -= 2,
+            6 if !self.micro => self.x -= 2,
             _ => self.x -= 1,
         }
         self.horizontal_next = false;
     }
 
     fn move_vertically(&mut self) {
-        if (self.upwards && self.y == 0) || (!self.upwards && self.y == self.size - 1) {
+        if (self.upwards && self.y == 0) || (!self.upwards && self.y == self.height - 1) {
             // When we've reached the edge move in the other direction instead of zagging.
             self.upwards = !self.upwards;
             self.move_horizontally();
@@ -772,6 +1853,9 @@ XX-XXX#XXXX-XXX-XXXXX
         // Regular API.
         assert!(QrBuilder::new().into("HELLO WORLD").is_ok());
 
+        // Japanese text is stored in kanji mode.
+        assert_eq!(QrBuilder::new().into("点茗").unwrap().mode, Mode::Kanji);
+
         // We can encode a zero string although it doesn't make much sense.
         assert!(QrBuilder::new().into("").is_ok());
 
@@ -786,6 +1870,450 @@ XX-XXX#XXXX-XXX-XXXXX
         // Unsupported mode for string content.
         assert_eq!(QrBuilder::new().mode(Mode::Numeric).into("HELLO"),
                    Err(Error::UnsupportedMode));
+
+        // Too long for the supplied version.
+        assert_eq!(QrBuilder::new().version(Version::new(1)).into(&long),
+                   Err(Error::MessageTooLong));
+    }
+
+    #[test]
+    fn verify() {
+        // Every kind of segment survives the round trip.
+        let qr = QrBuilder::new()
+            .verify(true)
+            .fnc1(Fnc1::Second(b'a'))
+            .structured_append(StructuredAppend::new(1, 3, 0x42).unwrap())
+            .push_alphanumeric("ORDER%").unwrap()
+            .push_numeric("1234567").unwrap()
+            .push_kanji("点茗").unwrap()
+            .push_eci(Eci::UTF8)
+            .push_bytes("☃".as_bytes())
+            .build()
+            .unwrap();
+        let decoded = decode::decode(&qr.matrix).unwrap();
+        assert_eq!(decoded.text, Some("ORDER%1234567点茗☃".to_string()));
+        assert_eq!(decoded.structured_append, StructuredAppend::new(1, 3, 0x42).ok());
+        assert_eq!(decoded.fnc1, Some(Fnc1::Second(b'a')));
+        assert_eq!(decoded.eci, Some(Eci::UTF8));
+
+        let s = "SPLIT ".repeat(100);
+        let qrs = QrBuilder::new().verify(true).into_structured_append(&s).unwrap();
+        let text: String = qrs.iter()
+            .map(|qr| decode::decode(&qr.matrix).unwrap().text.unwrap())
+            .collect();
+        assert_eq!(text, s);
+
+        // Step by step, damaged after the last step.
+        let mut builder = QrBuilder::new().version(Version::new(1)).ecl(ECLevel::L);
+        builder.add_all("HELLO WORLD").unwrap();
+        assert_eq!(builder.verify_matrix(), Ok(()));
+        let data: Vec<(usize, usize)> = ZigZagIt::new(21)
+            .filter(|(x, y)| builder.matrix.is_data(*x, *y))
+            .collect();
+        for (x, y) in data.iter().step_by(8).take(10) {
+            builder.matrix.flip(*x, *y);
+        }
+        assert_eq!(builder.verify_matrix(), Err(Error::VerificationFailed));
+
+        // A mismatching setting fails as well.
+        let mut builder = QrBuilder::new().mask(Mask::new(3));
+        builder.add_all("HELLO WORLD").unwrap();
+        builder.mask = Some(Mask::new(4));
+        assert_eq!(builder.verify_matrix(), Err(Error::VerificationFailed));
+
+        // The input is compared, not the segments made from it.
+        let mut builder = QrBuilder::new().verify(true);
+        builder.add_all("café 点 ☃").unwrap();
+        assert!(builder.verify_input(Input::Text("café 点 ☃")).is_ok());
+        assert_eq!(builder.verify_input(Input::Text("cafe 点 ☃")), Err(Error::VerificationFailed));
+        let v = [0xE9, 0x00, 0xFF];
+        let mut builder = QrBuilder::new().verify(true).eci(Eci::UTF8);
+        builder.add_all_bytes(&v).unwrap();
+        assert!(builder.verify_input(Input::Bytes(&v)).is_ok());
+        assert_eq!(builder.verify_input(Input::Bytes(&v[1..])), Err(Error::VerificationFailed));
+        assert!(Qr::new_verified("café 点 ☃").is_ok());
+        let s = format!("☃{}", "é".repeat(61));
+        let builder = QrBuilder::new().verify(true).ecl(ECLevel::L).version(Version::new(2));
+        assert_eq!(builder.into_structured_append(&s).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn segments() {
+        // Mixed data is split into segments, which needs a smaller version
+        // than encoding everything in byte mode.
+        let s = "INVOICE 2019-0001: 123456789012345678901234567890, thanks";
+        let qr = QrBuilder::new().ecl(ECLevel::L).into(s).unwrap();
+        assert_eq!(qr.version, Version::new(3));
+        assert_eq!(qr.mode, Mode::Numeric);
+        let qr = QrBuilder::new().ecl(ECLevel::L).mode(Mode::Byte).into(s).unwrap();
+        assert_eq!(qr.version, Version::new(4));
+    }
+
+    #[test]
+    fn push_segments() {
+        let qr = QrBuilder::new()
+            .push_alphanumeric("ORDER-").unwrap()
+            .push_numeric("12345678901234").unwrap()
+            .push_eci(Eci::UTF8)
+            .push_bytes("☃".as_bytes())
+            .build()
+            .unwrap();
+        assert_eq!(qr.version, Version::new(2));
+        assert_eq!(qr.mode, Mode::Numeric);
+        assert_eq!(qr.eci, Some(Eci::UTF8));
+
+        // Pushed ECIs don't change the character set of strings.
+        let mut builder = QrBuilder::new().push_eci(Eci::ISO_8859_2);
+        builder.add_all("☃").unwrap();
+        assert_eq!(builder.eci, Some(Eci::UTF8));
+        let qr = QrBuilder::new().push_eci(Eci::ISO_8859_2).into("☃").unwrap();
+        assert_eq!(qr.eci, Some(Eci::UTF8));
+
+        // Pushing segments is the same as letting the optimizer do it.
+        let segments = data::optimize_segments("HELLO 123456789", None, Version::new(1));
+        let mut builder = QrBuilder::new().mask(Mask::new(2));
+        for segment in segments.into_iter() {
+            builder = builder.push(segment);
+        }
+        assert_eq!(builder.build(),
+                   QrBuilder::new().mask(Mask::new(2)).into("HELLO 123456789"));
+
+        // Content is validated.
+        assert!(QrBuilder::new().push_numeric("12A").is_err());
+        assert!(QrBuilder::new().push_alphanumeric("hello").is_err());
+        assert!(QrBuilder::new().push_kanji("abc").is_err());
+
+        // Too much data.
+        let long: Vec<u8> = vec![0; 3000];
+        assert_eq!(QrBuilder::new().ecl(ECLevel::L).push_bytes(&long).build(),
+                   Err(Error::MessageTooLong));
+
+        // Nothing to build from.
+        assert_eq!(QrBuilder::new().build(), Err(Error::NoData));
+        assert_eq!(QrBuilder::new().fnc1(Fnc1::First).build(), Err(Error::NoData));
+    }
+
+    #[test]
+    fn into_bytes() {
+        // Not valid UTF-8.
+        let qr = QrBuilder::new().into_bytes(&[0xFF, 0x00, 0x80, 0xC3]).unwrap();
+        assert_eq!(qr.mode, Mode::Byte);
+        assert_eq!(qr.version, Version::new(1));
+        assert_eq!(qr.eci, None);
+
+        let qr = QrBuilder::new().eci(Eci::UTF8).into_bytes("☃".as_bytes()).unwrap();
+        assert_eq!(qr.eci, Some(Eci::UTF8));
+
+        // Max capacity.
+        let mut long: Vec<u8> = vec![0xFF; 17];
+        assert_eq!(QrBuilder::new().ecl(ECLevel::L).into_bytes(&long).unwrap().version,
+                   Version::new(1));
+        long.push(0xFF);
+        assert_eq!(QrBuilder::new().ecl(ECLevel::L).into_bytes(&long).unwrap().version,
+                   Version::new(2));
+        assert_eq!(QrBuilder::new().ecl(ECLevel::L).version(Version::new(1)).into_bytes(&long),
+                   Err(Error::MessageTooLong));
+
+        assert_eq!(QrBuilder::new().mode(Mode::Numeric).into_bytes(&[1, 2, 3]),
+                   Err(Error::UnsupportedMode));
+    }
+
+    #[test]
+    fn eci() {
+        // ISO-8859-1 doesn't need a character set.
+        let qr = QrBuilder::new().into("café").unwrap();
+        assert_eq!(qr.mode, Mode::Byte);
+        assert_eq!(qr.eci, None);
+
+        // But other characters are encoded as UTF-8.
+        let qr = QrBuilder::new().into("☃").unwrap();
+        assert_eq!(qr.eci, Some(Eci::UTF8));
+
+        // The character set can be specified explicitly.
+        let qr = QrBuilder::new().eci(Eci::ISO_8859_2).into("łódź").unwrap();
+        assert_eq!(qr.eci, Some(Eci::ISO_8859_2));
+        assert_eq!(QrBuilder::new().eci(Eci::ISO_8859_2).into("☃"),
+                   Err(Error::UnsupportedEci));
+
+        // The ECI header takes up room which can require a larger version.
+        let s: String = (0..7).map(|_| 'é').collect();
+        assert_eq!(Version::minimal(&s, Mode::Byte, ECLevel::Q), Some(Version::new(1)));
+        let qr = QrBuilder::new().eci(Eci::UTF8).into(&s).unwrap();
+        assert_eq!(qr.version, Version::new(2));
+    }
+
+    #[test]
+    fn micro_fun_patterns() {
+        let mut builder = MicroQrBuilder::new().version(MicroVersion::new(1));
+        builder.add_fun_patterns();
+        let expected = "
+#######.#.#
+#.....#.*??
+#.###.#.*??
+#.###.#.*??
+#.###.#.*??
+#.....#.*??
+#######.*??
+........*??
+#********??
+.??????????
+#??????????
+";
+        assert_eq!(builder.to_dbg_string(), expected);
+    }
+
+    #[test]
+    fn rmqr_fun_patterns() {
+        let mut builder = RmqrBuilder::new().version(RmqrVersion::new(11, 27));
+        builder.add_fun_patterns();
+        let expected = "
+#######.#.#.#.#.#.#.#.#.###
+#.....#.****?????????????.#
+#.###.#.****??????????????#
+#.###.#.****??????????????.
+#.###.#.***???????????????#
+#.....#.***????????******?.
+#######.???????????***#####
+........???????????***#...#
+#??????????????????***#.#.#
+#.?????????????????***#...#
+###.#.#.#.#.#.#.#.#.#.#####
+";
+        assert_eq!(builder.to_dbg_string(), expected);
+
+        // Alignment patterns along the top and bottom edges.
+        let mut builder = RmqrBuilder::new().version(RmqrVersion::new(7, 43));
+        builder.add_fun_patterns();
+        let expected = "
+#######.#.#.#.#.#.#.###.#.#.#.#.#.#.#.#.###
+#.....#.****????????#.#????????????******.#
+#.###.#.****????????###????????????***#####
+#.###.#.****?????????.?????????????***#...#
+#.###.#.***?????????###????????????***#.#.#
+#.....#.***?????????#.#????????????***#...#
+#######.#.#.#.#.#.#.###.#.#.#.#.#.#.#.#####
+";
+        assert_eq!(builder.to_dbg_string(), expected);
+    }
+
+    #[test]
+    fn rmqr() {
+        let qr = Rmqr::with_height("123", 7).unwrap();
+        assert_eq!(qr.version, RmqrVersion::new(7, 43));
+        assert_eq!((qr.width(), qr.height()), (43, 7));
+        assert_eq!((qr.matrix.width, qr.matrix.height), (43, 7));
+        // Only square matrices have a size.
+        assert_eq!(qr.matrix.size(), None);
+        assert!(!qr.matrix.is_square());
+        assert_eq!(Qr::new("1").unwrap().matrix.size(), Some(21));
+        assert_eq!(qr.ecl, ECLevel::M);
+        assert_eq!(qr.mode, Mode::Numeric);
+
+        // The version with the smallest area is used, optionally with a fixed height.
+        assert_eq!(Rmqr::new("123").unwrap().version, RmqrVersion::new(11, 27));
+        assert_eq!(Rmqr::new(&"1".repeat(15)).unwrap().version, RmqrVersion::new(13, 27));
+        assert_eq!(Rmqr::with_height("1", 17).unwrap().version, RmqrVersion::new(17, 43));
+        assert_eq!(Rmqr::with_ecl("HELLO", ECLevel::H).unwrap().version,
+                   RmqrVersion::new(11, 27));
+
+        assert_eq!(Rmqr::with_ecl("1", ECLevel::L), Err(Error::UnsupportedEcl));
+        assert_eq!(Rmqr::with_ecl("1", ECLevel::Q), Err(Error::UnsupportedEcl));
+        assert_eq!(Rmqr::with_version(&"1".repeat(13), RmqrVersion::new(7, 43)),
+                   Err(Error::MessageTooLong));
+        assert_eq!(Rmqr::with_height(&"1".repeat(400), 7), Err(Error::MessageTooLong));
+        assert_eq!(RmqrBuilder::new().mode(Mode::Numeric).into("A"),
+                   Err(Error::UnsupportedMode));
+        assert_eq!(RmqrBuilder::new().eci(Eci::ISO_8859_1).into("☃"),
+                   Err(Error::UnsupportedEci));
+        assert_eq!(Rmqr::new("☃").unwrap().eci, Some(Eci::UTF8));
+        assert_eq!(RmqrBuilder::new().build(), Err(Error::NoData));
+    }
+
+    #[test]
+    fn rmqr_codewords() {
+        let qr = Rmqr::with_version("123", RmqrVersion::new(7, 43)).unwrap();
+        let codewords = read_codewords(&qr.matrix, ZigZagIt::rmqr(43, 7));
+        assert_eq!(codewords.len(), 13);
+        // Mode 001, count 0011, 123 as 0001111011, terminator 000 and padding.
+        assert_eq!(codewords[..6], [0x26, 0x3D, 0x80, 0xEC, 0x11, 0xEC]);
+        assert!(ec::syndromes(&codewords, 7).iter().all(|x| *x == 0));
+
+        // R7x43 is version indicator 0 and M is 0, so only the format masks remain.
+        let format_bit = |x, y| qr.matrix.is_dark(x, y) as u32;
+        let (w, h) = (qr.width(), qr.height());
+        let (mut finder, mut sub_finder) = (0, 0);
+        for j in 0..3 {
+            for i in 0..5 {
+                finder |= format_bit(8 + j, 1 + i) << (j * 5 + i);
+                sub_finder |= format_bit(w - 8 + j, h - 6 + i) << (j * 5 + i);
+            }
+        }
+        for i in 0..3 {
+            finder |= format_bit(11, 1 + i) << (15 + i);
+            sub_finder |= format_bit(w - 5 + i, h - 6) << (15 + i);
+        }
+        assert_eq!((finder, sub_finder), (0x1FAB2, 0x20A7B));
+    }
+
+    #[test]
+    fn micro() {
+        // Example from the QR specification.
+        let qr = MicroQrBuilder::new()
+            .version(MicroVersion::new(2))
+            .into("01234567")
+            .unwrap();
+        assert_eq!(qr.mode, Mode::Numeric);
+        assert_eq!(qr.mask, Mask::new(1));
+        let expected = "
+#######.#.#.#
+#.....#.###.#
+#.###.#..##.#
+#.###.#..####
+#.###.#.###..
+#.....#.#...#
+#######..####
+.........##..
+##.#....#...#
+.##.#.#.#.#.#
+###..#######.
+...#.#....##.
+###.#..##.###
+";
+        assert_eq!(render::to_dbg_string(&qr.matrix).replace('X', "#").replace('-', "."),
+                   expected);
+
+        // The smallest version supporting the data is used.
+        assert_eq!(MicroQr::new("12345").unwrap().version, MicroVersion::new(1));
+        assert_eq!(MicroQr::new("HELLO").unwrap().version, MicroVersion::new(2));
+        assert_eq!(MicroQr::new("hello").unwrap().version, MicroVersion::new(3));
+        assert_eq!(MicroQr::with_ecl("1", ECLevel::Q).unwrap().version, MicroVersion::new(4));
+
+        assert_eq!(MicroQr::with_ecl("1", ECLevel::H), Err(Error::UnsupportedEcl));
+        assert_eq!(MicroQrBuilder::new().ecl(ECLevel::H).push(Segment::new("1", Mode::Numeric).unwrap())
+                   .build(), Err(Error::UnsupportedEcl));
+        assert_eq!(MicroQr::with_version("1", MicroVersion::new(1)).unwrap().ecl, ECLevel::L);
+        assert_eq!(MicroQrBuilder::new().version(MicroVersion::new(2)).ecl(ECLevel::Q).into("1"),
+                   Err(Error::UnsupportedEcl));
+        assert_eq!(MicroQr::with_version("A", MicroVersion::new(1)),
+                   Err(Error::UnsupportedMode));
+        assert_eq!(MicroQr::new("☃"), Err(Error::UnsupportedMode));
+        assert_eq!(MicroQr::new(&"1".repeat(35)).unwrap().version, MicroVersion::new(4));
+        assert_eq!(MicroQr::new(&"1".repeat(36)), Err(Error::MessageTooLong));
+        assert_eq!(MicroQrBuilder::new().build(), Err(Error::NoData));
+    }
+
+    #[test]
+    fn micro_codewords() {
+        // Example from the QR specification, M2-L with mask 1.
+        let qr = MicroQrBuilder::new()
+            .version(MicroVersion::new(2))
+            .into("01234567")
+            .unwrap();
+        assert_eq!(qr.mask, Mask::new(1));
+        let codewords = read_codewords(&qr.matrix, ZigZagIt::micro(13));
+        assert_eq!(codewords.len(), 10);
+        // Mode 0, count 1000, 012 345 67 in 10, 10 and 7 bits, terminator 00000.
+        assert_eq!(codewords[..5], [0x40, 0x18, 0xAC, 0xC3, 0x00]);
+        assert!(ec::syndromes(&codewords, 5).iter().all(|x| *x == 0));
+    }
+
+    #[test]
+    fn structured_append() {
+        // Fits in a single code, no header needed.
+        let qrs = QrBuilder::new().into_structured_append("HELLO WORLD").unwrap();
+        assert_eq!(qrs.len(), 1);
+        assert_eq!(qrs[0].structured_append, None);
+
+        // Version 1-L holds 17 bytes, 15 bytes with the header.
+        let v: Vec<u8> = (0..40).collect();
+        let builder = QrBuilder::new().ecl(ECLevel::L).version(Version::new(1));
+        let qrs = builder.clone().into_structured_append_bytes(&v).unwrap();
+        assert_eq!(qrs.len(), 3);
+        for (i, qr) in qrs.iter().enumerate() {
+            assert_eq!(qr.structured_append, StructuredAppend::new(i, 3, data::parity(&v)).ok());
+        }
+        assert_eq!(builder.clone().into_structured_append_bytes(&[0; 240]).unwrap().len(), 16);
+        assert_eq!(builder.clone().into_structured_append_bytes(&[0; 241]),
+                   Err(Error::MessageTooLong));
+
+        let s: String = (0..40).map(|_| 'a').collect();
+        let qrs = builder.into_structured_append(&s).unwrap();
+        assert_eq!(qrs.len(), 3);
+        assert_eq!(qrs[0].structured_append.unwrap().parity(), data::parity(s.as_bytes()));
+
+        // One character set for the whole message, and the parity over
+        // the data as decoded.
+        let s = format!("☃{}", "é".repeat(61));
+        let builder = QrBuilder::new().ecl(ECLevel::L).version(Version::new(2));
+        let qrs = builder.into_structured_append(&s).unwrap();
+        assert_eq!(qrs.len(), 5);
+        let decoded: Vec<_> = qrs.iter().map(|qr| decode::decode(&qr.matrix).unwrap()).collect();
+        let data: Vec<u8> = decoded.iter().flat_map(|x| x.data.iter().cloned()).collect();
+        assert_eq!(data, s.as_bytes());
+        for (i, x) in decoded.iter().enumerate() {
+            assert_eq!(x.eci, Some(Eci::UTF8));
+            assert_eq!(x.structured_append, StructuredAppend::new(i, 5, data::parity(&data)).ok());
+        }
+        let text: String = decoded.into_iter().map(|x| x.text.unwrap()).collect();
+        assert_eq!(text, s);
+
+        // Kanji mode data counts as Shift JIS.
+        let s = "点".repeat(40);
+        let qrs = QrBuilder::new().ecl(ECLevel::L).version(Version::new(1))
+            .into_structured_append(&s).unwrap();
+        assert!(qrs.len() > 1);
+        let data: Vec<u8> = qrs.iter()
+            .flat_map(|qr| decode::decode(&qr.matrix).unwrap().data)
+            .collect();
+        assert_eq!(qrs[0].structured_append.unwrap().parity(), data::parity(&data));
+        assert!(qrs.iter().all(|qr| qr.eci.is_none()));
+    }
+
+    #[test]
+    fn fnc1() {
+        let mut builder = QrBuilder::new().fnc1(Fnc1::First);
+        builder.add_all("01049123451234591597033130\u{1D}10ABC123").unwrap();
+        assert_eq!(builder.segments[0].mode, Mode::Fnc1First);
+        let qr = builder.into_qr().unwrap();
+        assert_eq!(qr.fnc1, Some(Fnc1::First));
+        assert_eq!(qr.mode, Mode::Numeric);
+
+        // FNC1 comes after ECI.
+        let mut builder = QrBuilder::new().fnc1(Fnc1::with_letter('a')).eci(Eci::UTF8);
+        builder.add_all("☃").unwrap();
+        assert_eq!(builder.segments.iter().map(|x| x.mode).collect::<Vec<_>>(),
+                   vec![Mode::Eci, Mode::Fnc1Second, Mode::Byte]);
+
+        // GS is only valid in alphanumeric segments in FNC1 mode.
+        assert!(QrBuilder::new().fnc1(Fnc1::First).push_alphanumeric("A\u{1D}B").is_ok());
+        assert!(QrBuilder::new().push_alphanumeric("A\u{1D}B").is_err());
+        let qr = QrBuilder::new().fnc1(Fnc1::First).mode(Mode::Alphanumeric)
+            .into("A\u{1D}B%").unwrap();
+        assert_eq!(qr.mode, Mode::Alphanumeric);
+    }
+
+    #[test]
+    fn structured_append_header() {
+        // The header comes first, even when segments are pushed.
+        let mut builder = QrBuilder::new()
+            .structured_append(StructuredAppend::new(1, 2, 0xAB).unwrap())
+            .push_numeric("123").unwrap();
+        builder.add_all_segments().unwrap();
+        assert_eq!(builder.segments.len(), 2);
+        assert_eq!(builder.segments[0].mode, Mode::StructuredAppend);
+        assert_eq!(builder.into_qr().unwrap().mode, Mode::Numeric);
+    }
+
+    // Read the data modules in placement order, unmasked with the
+    // (y / 2 + x / 3) % 2 mask shared by rMQR and Micro QR mask 1.
+    fn read_codewords(matrix: &Matrix, positions: ZigZagIt) -> Vec<u8> {
+        let bits: Vec<bool> = positions
+            .filter(|&(x, y)| matrix.is_data(x, y))
+            .map(|(x, y)| matrix.is_dark(x, y) ^ ((y / 2 + x / 3) % 2 == 0))
+            .collect();
+        bits.chunks_exact(8)
+            .map(|c| c.iter().fold(0, |v, b| (v << 1) | *b as u8))
+            .collect()
     }
 }
 
@@ -830,3 +2358,13 @@ static ALIGNMENT_LOCATIONS: [&[usize]; 40] = [
     &[6, 32, 58, 84, 110, 136, 162],
     &[6, 26, 54, 82, 110, 138, 166],
     &[6, 30, 58, 86, 114, 142, 170]];
+
+// Columns of the alignment patterns for each rMQR width.
+static RMQR_ALIGNMENT_COLUMNS: [(usize, &[usize]); 6] = [
+    (27, &[]),
+    (43, &[21]),
+    (59, &[19, 39]),
+    (77, &[25, 51]),
+    (99, &[23, 49, 75]),
+    (139, &[27, 55, 83, 111]),
+];
@@ -0,0 +1,177 @@
+//! Warns about QR codes which follow the specification but scan poorly.
+//!
+//! Readers, phone cameras in particular, differ a lot in what they handle.
+//! The warnings are advisory, a code with warnings is still valid.
+
+use crate::decode;
+use crate::mode::Mode;
+use crate::qr::Qr;
+use crate::render::{Renderer, SvgRenderer};
+use crate::version::Version;
+
+/// A reader compatibility problem.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Warning {
+    /// Byte mode holds characters outside of ASCII without an ECI.
+    /// Readers guess the character set, often as UTF-8 instead of ISO-8859-1.
+    NonAsciiWithoutEci,
+    /// A URL in byte mode which fits alphanumeric mode in uppercase.
+    /// The scheme and host are case insensitive, the path may not be.
+    LowercaseUrl,
+    /// The version is large, with modules too small for many phone cameras.
+    LargeVersion(Version),
+    /// The contrast ratio between the light and dark color is low.
+    LowContrast(f64),
+    /// The dark modules are lighter than the light modules,
+    /// which many readers don't support.
+    InvertedColors,
+    /// No quiet zone is rendered, the code must not be placed next to other content.
+    NoQuietZone,
+}
+
+impl Warning {
+    /// Human readable description of the warning.
+    pub fn description(&self) -> String {
+        match self {
+            Warning::NonAsciiWithoutEci =>
+                "Non-ASCII data without an ECI, readers may show the wrong characters".to_string(),
+            Warning::LowercaseUrl =>
+                "The URL could use a smaller alphanumeric code if written in uppercase".to_string(),
+            Warning::LargeVersion(v) =>
+                format!("Version {} is above {}, phone cameras may fail to read it",
+                        v.0, MAX_VERSION),
+            Warning::LowContrast(ratio) =>
+                format!("Contrast ratio {:.1} is below {:.1}", ratio, MIN_CONTRAST),
+            Warning::InvertedColors =>
+                "Dark modules are lighter than light modules, many readers fail".to_string(),
+            Warning::NoQuietZone =>
+                "The quiet zone is turned off".to_string(),
+        }
+    }
+}
+
+/// The warnings for a QR code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// All warnings, empty if no problems were found.
+    pub warnings: Vec<Warning>,
+}
+
+impl Report {
+    /// Are there no warnings?
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+/// Check the data and version of a QR code.
+pub fn check(qr: &Qr) -> Report {
+    let mut warnings = Vec::new();
+
+    // A QR code built without verification may not decode, skip the data then.
+    if let Ok(decoded) = decode::decode(&qr.matrix) {
+        let mut eci = false;
+        let mut non_ascii = false;
+        for segment in &decoded.segments {
+            match segment.mode {
+                Mode::Eci => eci = true,
+                // Bytes at or above 0x80 have the top bit set.
+                Mode::Byte if !eci => non_ascii |= segment.data.iter().step_by(8).any(|b| b),
+                _ => {}
+            }
+        }
+        if non_ascii {
+            warnings.push(Warning::NonAsciiWithoutEci);
+        }
+
+        if let Some(text) = decoded.text {
+            let lower = text.to_lowercase();
+            let url = lower.starts_with("http://") || lower.starts_with("https://");
+            if url && text.is_ascii() && !Mode::in_alphanumeric(&text) &&
+                Mode::in_alphanumeric(&text.to_uppercase())
+            {
+                warnings.push(Warning::LowercaseUrl);
+            }
+        }
+    }
+
+    if qr.version.0 > MAX_VERSION {
+        warnings.push(Warning::LargeVersion(qr.version));
+    }
+
+    Report { warnings }
+}
+
+/// Check a QR code and how it's rendered to svg.
+pub fn check_svg(qr: &Qr, renderer: &SvgRenderer) -> Report {
+    let mut report = check(qr);
+    let palette = renderer.palette();
+    let ratio = palette.light.contrast(&palette.dark);
+    if ratio < MIN_CONTRAST {
+        report.warnings.push(Warning::LowContrast(ratio));
+    }
+    if palette.dark.luminance() > palette.light.luminance() {
+        report.warnings.push(Warning::InvertedColors);
+    }
+    if !renderer.has_quiet_zone() {
+        report.warnings.push(Warning::NoQuietZone);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::QrBuilder;
+    use crate::eci::Eci;
+    use crate::render::Color;
+
+    #[test]
+    fn data() {
+        assert!(check(&Qr::new("HELLO WORLD").unwrap()).is_clean());
+        assert_eq!(check(&Qr::new("Grüße").unwrap()).warnings,
+                   vec![Warning::NonAsciiWithoutEci]);
+        let qr = QrBuilder::new().eci(Eci::UTF8).into("Grüße").unwrap();
+        assert!(check(&qr).is_clean());
+
+        assert_eq!(check(&Qr::new("https://example.com/qr").unwrap()).warnings,
+                   vec![Warning::LowercaseUrl]);
+        assert!(check(&Qr::new("HTTPS://EXAMPLE.COM/QR").unwrap()).is_clean());
+        // Query strings don't fit alphanumeric mode.
+        assert!(check(&Qr::new("https://example.com/?q=qr").unwrap()).is_clean());
+    }
+
+    #[test]
+    fn version() {
+        assert!(check(&Qr::with_version("A", Version::new(25)).unwrap()).is_clean());
+        let qr = Qr::with_version("A", Version::new(26)).unwrap();
+        assert_eq!(check(&qr).warnings, vec![Warning::LargeVersion(Version::new(26))]);
+    }
+
+    #[test]
+    fn svg() {
+        let qr = Qr::new("HELLO WORLD").unwrap();
+        assert!(check_svg(&qr, &SvgRenderer::new()).is_clean());
+
+        let renderer = SvgRenderer::new().dark_module(Color::hex(0xaaaaaa));
+        match check_svg(&qr, &renderer).warnings.as_slice() {
+            [Warning::LowContrast(ratio)] => assert!(*ratio > 2.0 && *ratio < 2.5),
+            w => panic!("unexpected warnings {:?}", w),
+        }
+
+        let renderer = SvgRenderer::new()
+            .light_module(Color::hex(0x000000))
+            .dark_module(Color::hex(0xffffff))
+            .quiet_zone(false);
+        assert_eq!(check_svg(&qr, &renderer).warnings,
+                   vec![Warning::InvertedColors, Warning::NoQuietZone]);
+        assert_eq!(Warning::NoQuietZone.description(), "The quiet zone is turned off");
+    }
+}
+
+// Versions above this have modules too small for many phone cameras
+// at a normal print size.
+const MAX_VERSION: usize = 25;
+
+// Minimum contrast ratio between light and dark modules.
+const MIN_CONTRAST: f64 = 4.5;
@@ -0,0 +1,294 @@
+//! Simulates damage to a QR code.
+//!
+//! Damage is applied to a copy of the matrix, which is then rendered to an
+//! image and read back, like a scanner would. The codewords are compared
+//! against the undamaged ones to tell how much of the error correction
+//! each block has used up.
+
+use crate::decode::{self, Decoded};
+use crate::detect::{self, DetectError};
+use crate::ec;
+use crate::info;
+use crate::matrix::{Matrix, Module};
+use crate::qr::Qr;
+
+/// A finder pattern.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Corner {
+    /// The top left finder.
+    TopLeft,
+    /// The top right finder.
+    TopRight,
+    /// The bottom left finder.
+    BottomLeft,
+}
+
+/// Damage to apply to a QR code.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Damage {
+    /// Flip randomly chosen modules, the same seed flips the same modules.
+    Flips { count: usize, seed: u64 },
+    /// Paint a rectangle of modules dark or light.
+    Blot { x: usize, y: usize, width: usize, height: usize, dark: bool },
+    /// Clear a centered square covering a share of the symbol area,
+    /// 0.0 to 1.0, as if a logo was placed on top.
+    Logo(f64),
+    /// Clear a finder pattern and its separator.
+    RemoveFinder(Corner),
+}
+
+/// How much of the error correction a block has used.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BlockBudget {
+    /// Number of damaged codewords.
+    pub errors: usize,
+
+    /// Number of damaged codewords which can be corrected,
+    /// half the error correction codewords.
+    ///
+    /// This is what the decoder in this library corrects. The specification
+    /// reserves some error correction codewords for misdecode protection
+    /// in versions 1-L, 1-M, 1-Q, 1-H, 2-L and 3-L, where readers following
+    /// it correct fewer codewords.
+    pub capacity: usize,
+}
+
+impl BlockBudget {
+    /// Number of codewords which can still be damaged before
+    /// the block can't be corrected.
+    pub fn remaining(&self) -> usize {
+        self.capacity.saturating_sub(self.errors)
+    }
+
+    /// Is the block damaged beyond correction?
+    pub fn exceeded(&self) -> bool {
+        self.errors > self.capacity
+    }
+}
+
+/// The outcome of damaging a QR code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DamageReport {
+    /// The damaged matrix.
+    pub matrix: Matrix,
+
+    /// Number of modules which changed color.
+    pub damaged_modules: usize,
+
+    /// The result of reading the damaged code from an image.
+    pub decoded: Result<Decoded, DetectError>,
+
+    /// Error correction budget of each block.
+    pub blocks: Vec<BlockBudget>,
+}
+
+impl DamageReport {
+    /// Could the damaged code be read?
+    pub fn decodable(&self) -> bool {
+        self.decoded.is_ok()
+    }
+
+    /// The smallest remaining budget of any block.
+    pub fn min_remaining(&self) -> usize {
+        self.blocks.iter().map(|b| b.remaining()).min().unwrap_or(0)
+    }
+}
+
+/// Apply damage to a copy of the QR code matrix.
+/// Modules keep their type, only their color changes.
+pub fn apply(qr: &Qr, damage: &[Damage]) -> Matrix {
+    let mut matrix = qr.matrix.clone();
+    let size = matrix.width;
+    for d in damage {
+        match *d {
+            Damage::Flips { count, seed } => {
+                // Partial Fisher-Yates shuffle, picks distinct modules.
+                let mut indices: Vec<usize> = (0..size * size).collect();
+                let mut rng = XorShift::new(seed);
+                for i in 0..count.min(indices.len()) {
+                    let j = i + (rng.next() % (indices.len() - i) as u64) as usize;
+                    indices.swap(i, j);
+                    let (x, y) = (indices[i] % size, indices[i] / size);
+                    let dark = matrix.is_dark(x, y);
+                    paint(&mut matrix, x, y, !dark);
+                }
+            }
+            Damage::Blot { x, y, width, height, dark } => {
+                for y in y..(y + height).min(size) {
+                    for x in x..(x + width).min(size) {
+                        paint(&mut matrix, x, y, dark);
+                    }
+                }
+            }
+            Damage::Logo(ratio) => {
+                let w = ((ratio.clamp(0.0, 1.0) * (size * size) as f64).sqrt().round() as usize)
+                    .min(size);
+                let from = (size - w) / 2;
+                for y in from..(from + w) {
+                    for x in from..(from + w) {
+                        paint(&mut matrix, x, y, false);
+                    }
+                }
+            }
+            Damage::RemoveFinder(corner) => {
+                let (x0, y0) = match corner {
+                    Corner::TopLeft => (0, 0),
+                    Corner::TopRight => (size - 8, 0),
+                    Corner::BottomLeft => (0, size - 8),
+                };
+                for y in y0..(y0 + 8) {
+                    for x in x0..(x0 + 8) {
+                        paint(&mut matrix, x, y, false);
+                    }
+                }
+            }
+        }
+    }
+    matrix
+}
+
+/// Damage a QR code and report if it can still be read.
+pub fn simulate(qr: &Qr, damage: &[Damage]) -> DamageReport {
+    let matrix = apply(qr, damage);
+    let damaged_modules = matrix.modules.iter().zip(qr.matrix.modules.iter())
+        .filter(|(a, b)| a.is_dark() != b.is_dark())
+        .count();
+
+    // Compare the codewords using the known format, so damaged format info
+    // doesn't hide the state of the data.
+    let original = decode::read_codewords(&qr.matrix, qr.version, qr.ecl, qr.mask);
+    let damaged = decode::read_codewords(&matrix, qr.version, qr.ecl, qr.mask);
+    let layout = info::group_block_count(qr.version, qr.ecl);
+    let ec_count = info::block_ec_count(qr.version, qr.ecl);
+    let mut blocks = vec![BlockBudget { errors: 0, capacity: ec_count / 2 }; layout.len()];
+    let positions = ec::block_positions(&layout, ec_count);
+    for ((a, b), (block, _)) in original.iter().zip(damaged.iter()).zip(positions.iter()) {
+        if a != b {
+            blocks[*block].errors += 1;
+        }
+    }
+
+    let (pixels, width) = to_pixels(&matrix);
+    DamageReport {
+        decoded: detect::read(&pixels, width, width),
+        matrix,
+        damaged_modules,
+        blocks,
+    }
+}
+
+fn paint(matrix: &mut Matrix, x: usize, y: usize, dark: bool) {
+    let m = match *matrix.get(x, y) {
+        Module::Data(_) => Module::Data(dark),
+        _ => Module::Function(dark),
+    };
+    matrix.set(x, y, m);
+}
+
+// Renders the matrix with a quiet zone, 4 pixels per module.
+fn to_pixels(matrix: &Matrix) -> (Vec<u8>, usize) {
+    let size = matrix.width + 2 * QUIET_ZONE;
+    let width = size * MODULE_PIXELS;
+    let mut pixels = vec![255u8; width * width];
+    for y in 0..matrix.width {
+        for x in 0..matrix.width {
+            if !matrix.is_dark(x, y) {
+                continue;
+            }
+            for py in 0..MODULE_PIXELS {
+                let row = ((y + QUIET_ZONE) * MODULE_PIXELS + py) * width;
+                let col = (x + QUIET_ZONE) * MODULE_PIXELS;
+                for p in &mut pixels[(row + col)..(row + col + MODULE_PIXELS)] {
+                    *p = 0;
+                }
+            }
+        }
+    }
+    (pixels, width)
+}
+
+// Small pseudo random generator, reproducible without extra dependencies.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> XorShift {
+        // Zero is a fixed point.
+        XorShift(if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed })
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ec::ECLevel;
+
+    #[test]
+    fn undamaged() {
+        let qr = Qr::new("HELLO WORLD").unwrap();
+        let report = simulate(&qr, &[]);
+        assert_eq!(report.damaged_modules, 0);
+        assert_eq!(report.decoded.as_ref().unwrap().text.as_deref(), Some("HELLO WORLD"));
+        assert_eq!(report.blocks, vec![BlockBudget { errors: 0, capacity: 6 }]);
+        assert_eq!(report.min_remaining(), 6);
+    }
+
+    #[test]
+    fn flips() {
+        let qr = Qr::with_ecl("https://github.com/treeman/rqr", ECLevel::H).unwrap();
+        let damage = [Damage::Flips { count: 12, seed: 7 }];
+        let report = simulate(&qr, &damage);
+        assert_eq!(report.damaged_modules, 12);
+        assert_eq!(apply(&qr, &damage), report.matrix);
+        assert_ne!(apply(&qr, &[Damage::Flips { count: 12, seed: 8 }]), report.matrix);
+        assert!(report.decodable());
+        let errors: usize = report.blocks.iter().map(|b| b.errors).sum();
+        assert!(errors <= 12);
+    }
+
+    #[test]
+    fn logo() {
+        let s = "A logo in the middle";
+        let report = simulate(&Qr::with_ecl(s, ECLevel::H).unwrap(), &[Damage::Logo(0.08)]);
+        assert!(report.decodable());
+        assert!(report.blocks.iter().all(|b| !b.exceeded()));
+
+        let report = simulate(&Qr::with_ecl(s, ECLevel::L).unwrap(), &[Damage::Logo(0.08)]);
+        assert!(!report.decodable());
+        assert!(report.blocks.iter().any(|b| b.exceeded()));
+        assert_eq!(report.min_remaining(), 0);
+    }
+
+    #[test]
+    fn blot() {
+        let qr = Qr::with_ecl("HELLO WORLD", ECLevel::M).unwrap();
+        // The bottom right corner holds the first codewords.
+        let report = simulate(&qr, &[Damage::Blot { x: 17, y: 17, width: 4, height: 4,
+                                                    dark: true }]);
+        assert!(report.damaged_modules > 0);
+        assert_eq!(report.blocks[0].errors, 2);
+        assert_eq!(report.blocks[0].remaining(), 3);
+        assert!(report.decodable());
+    }
+
+    #[test]
+    fn remove_finder() {
+        let qr = Qr::with_ecl("HELLO WORLD", ECLevel::H).unwrap();
+        let report = simulate(&qr, &[Damage::RemoveFinder(Corner::TopRight)]);
+        // The data is intact but the code can't be located.
+        assert_eq!(report.blocks[0].errors, 0);
+        assert_eq!(report.decoded, Err(DetectError::NotFound));
+    }
+}
+
+// Quiet zone around the rendered image, in modules.
+const QUIET_ZONE: usize = 4;
+
+// Size of a module in the rendered image.
+const MODULE_PIXELS: usize = 4;
@@ -1,8 +1,10 @@
 //! Data encoding.
 
-use crate::mode::Mode;
+use crate::builder::Error;
+use crate::mode::{self, Mode};
+use crate::eci::Eci;
 use crate::ec::ECLevel;
-use crate::version::Version;
+use crate::version::{Version, MicroVersion, RmqrVersion};
 use crate::info;
 
 use bitvec::*;
@@ -12,20 +14,56 @@ use std::cmp;
 /// Does not include error correction codes, it only encodes the data portion.
 pub fn encode(s: &str, version: Version, ecl: ECLevel) -> (Mode, BitVec) {
     let mode = Mode::from_str(s);
-    let encoded = encode_with_mode(s, mode, version, ecl);
+    // The mode is decided from the string, so it can always encode it.
+    let encoded = encode_with_mode(s, mode, version, ecl).unwrap();
     (mode, encoded)
 }
 
+/// Encode binary data to BitVec in byte mode.
+/// Does not include error correction codes, it only encodes the data portion.
+pub fn encode_bytes(v: &[u8], version: Version, ecl: ECLevel) -> BitVec {
+    encode_segments(&[Segment::bytes(v)], version, ecl)
+}
+
 /// Encode string data to BitVec in a specific mode.
+/// Fails if the mode can't encode the string.
+/// Does not include error correction codes, it only encodes the data portion.
+pub fn encode_with_mode(s: &str, mode: Mode, version: Version, ecl: ECLevel)
+    -> Result<BitVec, Error>
+{
+    encode_with_eci(s, mode, None, version, ecl)
+}
+
+/// Encode string data to BitVec in a specific mode, preceded by an ECI header
+/// if specified. Byte mode data is converted to the ECI character set.
+/// Does not include error correction codes, it only encodes the data portion.
+pub fn encode_with_eci(s: &str, mode: Mode, eci: Option<Eci>,
+                       version: Version, ecl: ECLevel) -> Result<BitVec, Error> {
+    Ok(encode_segments(&segments_with_mode(s, mode, eci)?, version, ecl))
+}
+
+/// Encode string data to BitVec in a specific mode, in FNC1 mode.
+/// GS separators are encoded as % in alphanumeric mode.
 /// Does not include error correction codes, it only encodes the data portion.
-pub fn encode_with_mode(s: &str, mode: Mode, version: Version, ecl: ECLevel) -> BitVec {
+pub fn encode_with_fnc1(s: &str, mode: Mode, fnc1: Fnc1,
+                        version: Version, ecl: ECLevel) -> Result<BitVec, Error> {
+    let mut segments = match mode {
+        Mode::Alphanumeric => segments_with_mode(&fnc1_escape(s), mode, None)?,
+        _ => segments_with_mode(s, mode, None)?,
+    };
+    add_fnc1(&mut segments, fnc1);
+    Ok(encode_segments(&segments, version, ecl))
+}
+
+/// Encode segments to BitVec, followed by terminator and padding.
+/// Does not include error correction codes, it only encodes the data portion.
+pub fn encode_segments(segments: &[Segment], version: Version, ecl: ECLevel) -> BitVec {
     let total_capacity = info::total_bits(version, ecl);
 
-    // Encoding is mode, char count, data.
-    let mut bv = mode.to_bitvec();
-    bv.reserve(total_capacity);
-    bv.append(&mut bitvec_char_count(s.len(), mode, version));
-    bv.append(&mut bitvec_data(s, mode));
+    let mut bv = BitVec::with_capacity(total_capacity);
+    for segment in segments.iter() {
+        bv.append(&mut segment.to_bitvec(version));
+    }
     assert!(bv.len() <= total_capacity);
 
     // Add up to 4 zero bits if we're below capacity.
@@ -36,7 +74,7 @@ pub fn encode_with_mode(s: &str, mode: Mode, version: Version, ecl: ECLevel) ->
     // If we're still below capacity add zero bits until we have full bytes.
     let zero_bits = (total_capacity - bv.len()) % 8;
     append(&mut bv, 0, zero_bits);
-    assert!(bv.len() % 8 == 0);
+    assert!(bv.len().is_multiple_of(8));
 
     // Until we reach our capacity add pad bytes.
     for pad in [0xEC, 0x11].iter().cycle() {
@@ -50,42 +88,569 @@ pub fn encode_with_mode(s: &str, mode: Mode, version: Version, ecl: ECLevel) ->
     bv
 }
 
+/// Encode segments to BitVec for a Micro QR version, followed by
+/// terminator and padding.
+/// Does not include error correction codes, it only encodes the data portion.
+pub fn encode_micro_segments(segments: &[Segment], version: MicroVersion,
+                             ecl: ECLevel) -> BitVec {
+    let total_capacity = info::micro_data_bits(version, ecl);
+
+    let mut bv = BitVec::with_capacity(total_capacity);
+    for segment in segments.iter() {
+        bv.append(&mut segment.to_micro_bitvec(version));
+    }
+    assert!(bv.len() <= total_capacity);
+
+    // The terminator length depends on the version.
+    let zero_bits = cmp::min(total_capacity - bv.len(), version.terminator_len());
+    append(&mut bv, 0, zero_bits);
+
+    // Zero bits until we have full bytes, or until we reach the capacity
+    // as M1 and M3 ends with a 4 bit codeword.
+    let zero_bits = cmp::min((8 - bv.len() % 8) % 8, total_capacity - bv.len());
+    append(&mut bv, 0, zero_bits);
+
+    // Add pad bytes while they fit, a trailing 4 bit codeword is left as zeros.
+    for pad in [0xEC, 0x11].iter().cycle() {
+        if bv.len() + 8 > total_capacity {
+            break;
+        }
+        append(&mut bv, *pad, 8);
+    }
+    let zero_bits = total_capacity - bv.len();
+    append(&mut bv, 0, zero_bits);
+    assert_eq!(bv.len(), total_capacity);
+
+    bv
+}
+
+/// Encode segments to BitVec for a rMQR version, followed by
+/// terminator and padding.
+/// Does not include error correction codes, it only encodes the data portion.
+pub fn encode_rmqr_segments(segments: &[Segment], version: RmqrVersion,
+                            ecl: ECLevel) -> BitVec {
+    let total_capacity = info::rmqr_total_bits(version, ecl);
+
+    let mut bv = BitVec::with_capacity(total_capacity);
+    for segment in segments.iter() {
+        bv.append(&mut segment.to_rmqr_bitvec(version));
+    }
+    assert!(bv.len() <= total_capacity);
+
+    // The terminator is 3 zero bits, the rest is padded like a QR code.
+    let zero_bits = cmp::min(total_capacity - bv.len(), 3);
+    append(&mut bv, 0, zero_bits);
+
+    let zero_bits = (total_capacity - bv.len()) % 8;
+    append(&mut bv, 0, zero_bits);
+    assert!(bv.len().is_multiple_of(8));
+
+    for pad in [0xEC, 0x11].iter().cycle() {
+        if bv.len() >= total_capacity {
+            break;
+        }
+        append(&mut bv, *pad, 8);
+    }
+    assert_eq!(bv.len(), total_capacity);
+
+    bv
+}
+
+/// Returns the number of bits needed to encode the string,
+/// excluding terminator and padding. Fails if the mode can't encode the string.
+pub fn bit_len(s: &str, mode: Mode, eci: Option<Eci>, version: Version)
+    -> Result<usize, Error>
+{
+    Ok(segments_bit_len(&segments_with_mode(s, mode, eci)?, version))
+}
+
+/// Returns the number of bits needed to encode the segments,
+/// excluding terminator and padding.
+pub fn segments_bit_len(segments: &[Segment], version: Version) -> usize {
+    segments.iter().map(|x| x.bit_len(version)).sum()
+}
+
+/// Returns the number of bits needed to encode the segments in a Micro QR
+/// version, excluding terminator and padding.
+pub fn micro_segments_bit_len(segments: &[Segment], version: MicroVersion) -> usize {
+    segments.iter().map(|x| x.micro_bit_len(version)).sum()
+}
+
+/// Returns the number of bits needed to encode the segments in a rMQR
+/// version, excluding terminator and padding.
+pub fn rmqr_segments_bit_len(segments: &[Segment], version: RmqrVersion) -> usize {
+    segments.iter().map(|x| x.rmqr_bit_len(version)).sum()
+}
+
 /// Append data to bitvec of a certain len.
 pub fn append(bv: &mut BitVec, v: u32, len: usize) {
     bv.extend((0..len).rev().map(|i| (v >> i) & 1 != 0));
 }
 
+/// A segment of data encoded in a single mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// Encoding mode.
+    pub mode: Mode,
+    /// Number of characters, stored in the char count indicator.
+    pub char_count: usize,
+    /// Encoded data, excluding mode indicator and char count.
+    pub data: BitVec,
+}
+
+impl Segment {
+    /// Create a segment from a string in a specific mode.
+    /// Fails if the mode can't encode the string, which modes without data
+    /// like ECI never can. They have their own constructors.
+    pub fn new(s: &str, mode: Mode) -> Result<Segment, Error> {
+        if !mode.matches(s) {
+            return Err(Error::UnsupportedMode);
+        }
+        let bytes = string_to_bytes(s, mode);
+        // Kanji characters are two bytes, others are one byte each.
+        let char_count = if mode == Mode::Kanji { bytes.len() / 2 } else { bytes.len() };
+        Ok(Segment {
+            mode,
+            char_count,
+            data: bitvec_data(&bytes, mode),
+        })
+    }
+
+    /// Create a byte mode segment from raw bytes.
+    pub fn bytes(v: &[u8]) -> Segment {
+        Segment {
+            mode: Mode::Byte,
+            char_count: v.len(),
+            data: encode_byte_data(v),
+        }
+    }
+
+    /// Create an ECI segment, specifying the character set of the byte
+    /// mode segments that follows.
+    pub fn eci(eci: Eci) -> Segment {
+        Segment {
+            mode: Mode::Eci,
+            char_count: 0,
+            data: eci.to_bitvec(),
+        }
+    }
+
+    /// Create a Structured Append segment, it must be placed first
+    /// in the symbol.
+    pub fn structured_append(header: StructuredAppend) -> Segment {
+        let mut data = BitVec::with_capacity(16);
+        append(&mut data, header.index as u32, 4);
+        // Headers are checked on creation, so there is at least one symbol.
+        append(&mut data, (header.total - 1) as u32, 4);
+        append(&mut data, header.parity as u32, 8);
+        Segment {
+            mode: Mode::StructuredAppend,
+            char_count: 0,
+            data,
+        }
+    }
+
+    /// Create an FNC1 segment, it must be placed before the data segments.
+    pub fn fnc1(fnc1: Fnc1) -> Segment {
+        match fnc1 {
+            Fnc1::First => Segment {
+                mode: Mode::Fnc1First,
+                char_count: 0,
+                data: BitVec::new(),
+            },
+            Fnc1::Second(indicator) => {
+                let mut data = BitVec::with_capacity(8);
+                append(&mut data, indicator as u32, 8);
+                Segment {
+                    mode: Mode::Fnc1Second,
+                    char_count: 0,
+                    data,
+                }
+            }
+        }
+    }
+
+    /// Returns the number of bits needed to encode the segment in a version.
+    pub fn bit_len(&self, version: Version) -> usize {
+        4 + version.char_count_len(self.mode) + self.data.len()
+    }
+
+    /// Encoding is mode, char count and data.
+    pub fn to_bitvec(&self, version: Version) -> BitVec {
+        let mut bv = self.mode.to_bitvec();
+        bv.append(&mut bitvec_char_count(self.char_count, self.mode, version));
+        bv.extend(self.data.iter());
+        bv
+    }
+
+    /// Returns the number of bits needed to encode the segment in a Micro QR version.
+    pub fn micro_bit_len(&self, version: MicroVersion) -> usize {
+        version.mode_indicator_len() + version.char_count_len(self.mode) + self.data.len()
+    }
+
+    /// Encoding in a Micro QR version, where the mode indicator and char count
+    /// are shorter. Fails if the version doesn't support the mode.
+    pub fn to_micro_bitvec(&self, version: MicroVersion) -> BitVec {
+        assert!(version.supports_mode(self.mode));
+        let mut bv = BitVec::new();
+        // Micro QR mode indicators are 0 for numeric, 1 for alphanumeric and so on.
+        append(&mut bv, self.mode as u32, version.mode_indicator_len());
+        append(&mut bv, self.char_count as u32, version.char_count_len(self.mode));
+        bv.extend(self.data.iter());
+        bv
+    }
+
+    /// Returns the number of bits needed to encode the segment in a rMQR version.
+    pub fn rmqr_bit_len(&self, version: RmqrVersion) -> usize {
+        3 + version.char_count_len(self.mode) + self.data.len()
+    }
+
+    /// Encoding in a rMQR version, with a 3 bit mode indicator.
+    pub fn to_rmqr_bitvec(&self, version: RmqrVersion) -> BitVec {
+        let mut bv = self.mode.to_rmqr_bitvec();
+        append(&mut bv, self.char_count as u32, version.char_count_len(self.mode));
+        bv.extend(self.data.iter());
+        bv
+    }
+}
+
+/// Structured Append header, used when a message is split over several symbols.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StructuredAppend {
+    index: usize,
+    total: usize,
+    parity: u8,
+}
+
+impl StructuredAppend {
+    /// Create a new header.
+    /// Fails if there aren't 1 to 16 symbols or if the index is out of range.
+    pub fn new(index: usize, total: usize, parity: u8) -> Result<StructuredAppend, Error> {
+        if !(1..=16).contains(&total) || index >= total {
+            return Err(Error::InvalidStructuredAppend);
+        }
+        Ok(StructuredAppend { index, total, parity })
+    }
+
+    /// Position of the symbol in the sequence, starting from 0.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Total number of symbols, at most 16.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Parity of the whole message, the same in all symbols.
+    pub fn parity(&self) -> u8 {
+        self.parity
+    }
+}
+
+/// Returns the Structured Append parity of a message,
+/// all the message bytes XOR:ed together.
+pub fn parity(v: &[u8]) -> u8 {
+    v.iter().fold(0, |acc, x| acc ^ x)
+}
+
+/// The GS character, separates variable length fields in FNC1 mode.
+pub const GS: char = '\u{1D}';
+
+/// FNC1 mode, marks the data as formatted per an industry standard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fnc1 {
+    /// FNC1 in first position, the data follows the GS1 General Specifications.
+    First,
+    /// FNC1 in second position, the data follows an industry application
+    /// identified by the application indicator.
+    Second(u8),
+}
+
+impl Fnc1 {
+    /// FNC1 in second position with a letter, a-z or A-Z, as application indicator.
+    pub fn with_letter(c: char) -> Fnc1 {
+        assert!(c.is_ascii_alphabetic());
+        Fnc1::Second(c as u8 + 100)
+    }
+
+    /// FNC1 in second position with two digits, 00-99, as application indicator.
+    pub fn with_digits(n: u8) -> Fnc1 {
+        assert!(n < 100);
+        Fnc1::Second(n)
+    }
+}
+
+/// Escape a string for alphanumeric mode in FNC1 mode.
+/// There % represents the GS separator, so GS becomes % and % becomes %%.
+pub fn fnc1_escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            GS => res.push('%'),
+            '%' => res.push_str("%%"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+/// Insert an FNC1 segment before the data segments,
+/// after any Structured Append and ECI segments.
+pub fn add_fnc1(segments: &mut Vec<Segment>, fnc1: Fnc1) {
+    let pos = segments.iter()
+        .position(|x| x.mode != Mode::StructuredAppend && x.mode != Mode::Eci)
+        .unwrap_or(segments.len());
+    segments.insert(pos, Segment::fnc1(fnc1));
+}
+
+/// Create segments for a string in a specific mode.
+///
+/// Byte mode data is converted to the ECI character set if specified,
+/// otherwise it's encoded as ISO-8859-1 with a fallback to UTF-8.
+/// An ECI segment is added first if specified or if UTF-8 is used.
+pub fn segments_with_mode(s: &str, mode: Mode, eci: Option<Eci>)
+    -> Result<Vec<Segment>, Error>
+{
+    let eci = match eci {
+        None if mode == Mode::Byte => Eci::for_str(s),
+        x => x,
+    };
+
+    let mut res = Vec::with_capacity(2);
+    if let Some(eci) = eci {
+        res.push(Segment::eci(eci));
+    }
+    match eci {
+        Some(eci) if mode == Mode::Byte => res.push(Segment::bytes(&eci.encode(s).unwrap())),
+        _ => res.push(Segment::new(s, mode)?),
+    }
+    Ok(res)
+}
+
+/// Split a string into segments of different modes, optimized to use as few
+/// bits as possible for a version. Only the version's char count lengths
+/// matters, so the result is the same for versions 1-9, 10-26 and 27-40.
+///
+/// Byte mode data is converted to the ECI character set if specified,
+/// otherwise it's encoded as ISO-8859-1 with a fallback to UTF-8.
+/// An ECI segment is added first if specified or if UTF-8 is used.
+pub fn optimize_segments(s: &str, eci: Option<Eci>, version: Version) -> Vec<Segment> {
+    let head_len = |mode: Mode| Some(4 + version.char_count_len(mode));
+    optimize(s, eci, false, &head_len).unwrap()
+}
+
+/// Split a string into optimized segments like `optimize_segments`, in FNC1 mode.
+/// GS separators may then be encoded as % in alphanumeric mode.
+pub fn optimize_fnc1_segments(s: &str, eci: Option<Eci>, version: Version) -> Vec<Segment> {
+    let head_len = |mode: Mode| Some(4 + version.char_count_len(mode));
+    optimize(s, eci, true, &head_len).unwrap()
+}
+
+/// Split a string into optimized segments for a Micro QR version.
+/// Returns None if the version doesn't support the modes needed,
+/// byte mode data must be in ISO-8859-1 as there is no ECI.
+pub fn optimize_micro_segments(s: &str, version: MicroVersion) -> Option<Vec<Segment>> {
+    let head_len = |mode: Mode| {
+        if version.supports_mode(mode) {
+            Some(version.mode_indicator_len() + version.char_count_len(mode))
+        } else {
+            None
+        }
+    };
+    // Restricting byte mode to ISO-8859-1 adds an ECI segment we don't want.
+    let mut res = optimize(s, Some(Eci::ISO_8859_1), false, &head_len)?;
+    res.retain(|x| x.mode != Mode::Eci);
+    if res.iter().all(|x| version.supports_mode(x.mode)) {
+        Some(res)
+    } else {
+        None
+    }
+}
+
+/// Split a string into optimized segments for a rMQR version.
+/// Byte mode data and the ECI are handled like `optimize_segments`.
+pub fn optimize_rmqr_segments(s: &str, eci: Option<Eci>, version: RmqrVersion) -> Vec<Segment> {
+    let head_len = |mode: Mode| Some(3 + version.char_count_len(mode));
+    optimize(s, eci, false, &head_len).unwrap()
+}
+
+// Returns None if some character can't be encoded in the available modes.
+// head_len is the mode indicator and char count len of a mode,
+// or None if the mode isn't available.
+fn optimize(s: &str, eci: Option<Eci>, fnc1: bool,
+            head_len: &dyn Fn(Mode) -> Option<usize>) -> Option<Vec<Segment>> {
+    let chars: Vec<char> = s.chars().collect();
+    if chars.is_empty() {
+        return segments_with_mode(s, Mode::from_str(s), eci).ok();
+    }
+
+    // Byte mode is costed in the character set each byte segment ends up in.
+    // Without an ECI characters outside of ISO-8859-1 need UTF-8, which then
+    // applies to every byte segment and needs an ECI segment of its own.
+    // So try keeping them out of byte mode as well, they may fit kanji mode.
+    let byte_len = |eci: Eci| {
+        move |c: char| eci.encode(c.encode_utf8(&mut [0; 4])).map(|x| x.len())
+    };
+    let (eci, modes) = match eci {
+        Some(eci) => (Some(eci), optimize_modes(&chars, fnc1, head_len, &byte_len(eci))?.1),
+        None => {
+            let latin1 = optimize_modes(&chars, fnc1, head_len, &byte_len(Eci::ISO_8859_1));
+            let utf8 = if mode::to_latin1(s).is_none() {
+                head_len(Mode::Eci).and_then(|x| {
+                    let eci_cost = 6 * (x + Segment::eci(Eci::UTF8).data.len());
+                    optimize_modes(&chars, fnc1, head_len, &byte_len(Eci::UTF8))
+                        .map(|(cost, modes)| (cost + eci_cost, modes))
+                })
+            } else {
+                None
+            };
+            match (latin1, utf8) {
+                (Some(latin1), Some(utf8)) if utf8.0 < latin1.0 => (Some(Eci::UTF8), utf8.1),
+                (Some(latin1), _) => (None, latin1.1),
+                (None, Some(utf8)) => (Some(Eci::UTF8), utf8.1),
+                (None, None) => return None,
+            }
+        }
+    };
+
+    // Group characters in the same mode into segments.
+    let mut groups: Vec<(Mode, String)> = Vec::new();
+    for (c, mode) in chars.iter().zip(modes.iter()) {
+        match groups.last_mut() {
+            Some((m, s)) if m == mode => s.push(*c),
+            _ => groups.push((*mode, c.to_string())),
+        }
+    }
+
+    let mut res = Vec::with_capacity(groups.len() + 1);
+    if let Some(eci) = eci {
+        res.push(Segment::eci(eci));
+    }
+    for (m, s) in groups.iter() {
+        match (m, eci) {
+            (Mode::Byte, Some(eci)) => res.push(Segment::bytes(&eci.encode(s).unwrap())),
+            (Mode::Alphanumeric, _) if fnc1 => {
+                res.push(Segment::new(&fnc1_escape(s), Mode::Alphanumeric).ok()?)
+            }
+            _ => res.push(Segment::new(s, *m).ok()?),
+        }
+    }
+    Some(res)
+}
+
+// Returns the mode of each character and the total cost in 1/6 bits,
+// or None if some character can't be encoded in the available modes.
+// byte_len is the number of bytes of a character in byte mode,
+// or None if it can't be encoded.
+fn optimize_modes(chars: &[char], fnc1: bool, head_len: &dyn Fn(Mode) -> Option<usize>,
+                  byte_len: &dyn Fn(char) -> Option<usize>) -> Option<(usize, Vec<Mode>)> {
+    // Costs are in 1/6 bits, so numeric and alphanumeric characters
+    // can be represented with whole numbers.
+    let char_cost = |mode: Mode, c: char| -> Option<usize> {
+        let mut buf = [0; 4];
+        let s = c.encode_utf8(&mut buf);
+        match mode {
+            Mode::Numeric if Mode::in_numeric(s) => Some(20),
+            // In FNC1 mode GS is written as % and % as %%.
+            Mode::Alphanumeric if fnc1 && c == GS => Some(33),
+            Mode::Alphanumeric if fnc1 && c == '%' => Some(66),
+            Mode::Alphanumeric if Mode::in_alphanumeric(s) => Some(33),
+            Mode::Byte => byte_len(c).map(|x| 48 * x),
+            Mode::Kanji if Mode::in_kanji(s) => Some(78),
+            _ => None,
+        }
+    };
+    // Segments are stored in whole bits.
+    let round_up = |x: usize| x.div_ceil(6) * 6;
+
+    // costs[m] is the minimum cost to encode the characters so far
+    // where the last character is encoded in mode m.
+    // prev[i][m] is the mode of character i - 1 in that case.
+    let mut costs = [None; 4];
+    let mut prev = vec![[0; 4]; chars.len()];
+    for (i, c) in chars.iter().enumerate() {
+        let mut next = [None; 4];
+        for (m, mode) in SEGMENT_MODES.iter().enumerate() {
+            // Starting a new segment costs the mode and char count bits.
+            let head_cost = match head_len(*mode) {
+                Some(x) => 6 * x,
+                None => continue,
+            };
+            let char_cost = match char_cost(*mode, *c) {
+                Some(x) => x,
+                None => continue,
+            };
+            // Continue the current segment, start a new one or switch
+            // from another segment, whichever is cheapest.
+            let mut best = costs[m].map(|x| (x, m));
+            for (pm, cost) in costs.iter().enumerate() {
+                if let Some(cost) = cost {
+                    let cost = round_up(*cost) + head_cost;
+                    if best.is_none_or(|(x, _)| cost < x) {
+                        best = Some((cost, pm));
+                    }
+                }
+            }
+            if i == 0 {
+                best = Some((head_cost, m));
+            }
+            if let Some((cost, pm)) = best {
+                next[m] = Some(cost + char_cost);
+                prev[i][m] = pm;
+            }
+        }
+        costs = next;
+    }
+
+    // Find the best ending mode and backtrack to find the mode of each character.
+    let mut m = (0..4)
+        .filter(|m| costs[*m].is_some())
+        .min_by_key(|m| round_up(costs[*m].unwrap()))?;
+    let cost = round_up(costs[m].unwrap());
+    let mut modes = vec![Mode::Byte; chars.len()];
+    for i in (0..chars.len()).rev() {
+        modes[i] = SEGMENT_MODES[m];
+        m = prev[i][m];
+    }
+    Some((cost, modes))
+}
+
+// Modes the optimizer can choose from.
+static SEGMENT_MODES: [Mode; 4] = [Mode::Numeric, Mode::Alphanumeric, Mode::Byte, Mode::Kanji];
+
 fn bitvec_char_count(len: usize, mode: Mode, v: Version) -> BitVec {
     let mut bv = BitVec::new();
     append(&mut bv, len as u32, v.char_count_len(mode));
     bv
 }
 
-fn bitvec_data(s: &str, mode: Mode) -> BitVec {
-    let bytes = string_to_bytes(s, mode);
-
+fn bitvec_data(bytes: &[u8], mode: Mode) -> BitVec {
     match mode {
-        Mode::Numeric => encode_numeric_data(&bytes),
-        Mode::Alphanumeric => encode_alphanumeric_data(&bytes),
-        Mode::Byte => encode_byte_data(&bytes),
+        Mode::Numeric => encode_numeric_data(bytes),
+        Mode::Alphanumeric => encode_alphanumeric_data(bytes),
+        Mode::Byte => encode_byte_data(bytes),
+        Mode::Kanji => encode_kanji_data(bytes),
+        Mode::Eci => panic!("ECI doesn't hold any data"),
+        Mode::StructuredAppend => panic!("Structured Append doesn't hold any data"),
+        Mode::Fnc1First | Mode::Fnc1Second => panic!("FNC1 doesn't hold any data"),
     }
 }
 
-fn encode_numeric_data(v: &Vec<u8>) -> BitVec {
+fn encode_numeric_data(v: &[u8]) -> BitVec {
     // Encoding is done by grouping into groups of three
     // and converting that to binary.
 
-    // If both first numbers are zero, convert it uses 4 bits.
-    // If the first number in the group is zero it should use 7 bits.
-    // Otherwise it should use 10 bits.
+    // A group of three digits uses 10 bits, a trailing group of two
+    // digits uses 7 bits and a single digit uses 4 bits.
     // It's the minimal amount of bits that can all numbers of that length.
-    let bit_len = |num: u32| {
-        if num > 99 {
-            10
-        } else if num > 9 {
-            7
-        } else {
-            4
+    // Leading zeros doesn't matter, the reader decides the length from
+    // the char count.
+    let bit_len = |digits: usize| {
+        match digits {
+            3 => 10,
+            2 => 7,
+            _ => 4,
         }
     };
 
@@ -94,7 +659,7 @@ fn encode_numeric_data(v: &Vec<u8>) -> BitVec {
 
     let mut add = |s: &str| {
         let num: u32 = s.parse().unwrap();
-        let len = bit_len(num);
+        let len = bit_len(s.len());
         append(&mut bv, num, len);
     };
 
@@ -113,7 +678,7 @@ fn encode_numeric_data(v: &Vec<u8>) -> BitVec {
     bv
 }
 
-fn encode_alphanumeric_data(v: &Vec<u8>) -> BitVec {
+fn encode_alphanumeric_data(v: &[u8]) -> BitVec {
     let mut bv = BitVec::new();
     bv.reserve(v.len() * 8);
 
@@ -133,13 +698,37 @@ fn encode_alphanumeric_data(v: &Vec<u8>) -> BitVec {
 
     bv
 }
-fn encode_byte_data(v: &Vec<u8>) -> BitVec {
+
+fn encode_byte_data(v: &[u8]) -> BitVec {
     // It's already in ISO 8859-1, or UTF-8
-    v[..].into()
+    v.into()
+}
+
+fn encode_kanji_data(v: &[u8]) -> BitVec {
+    let mut bv = BitVec::new();
+    bv.reserve(v.len() / 2 * 13);
+
+    // Each character is a double-byte Shift JIS value.
+    for c in v.chunks(2) {
+        let sjis = ((c[0] as u32) << 8) | (c[1] as u32);
+        // Subtract the start of the range the character is in.
+        let x = if sjis <= 0x9FFC {
+            sjis - 0x8140
+        } else {
+            sjis - 0xC140
+        };
+        // Multiply the most significant byte with 0xC0 and add the least
+        // significant byte, the result fits into 13 bits.
+        let num = (x >> 8) * 0xC0 + (x & 0xFF);
+        append(&mut bv, num, 13);
+    }
+
+    bv
 }
 
 // Converts string to byte representation.
 // Numeric and alphanumeric are compacted more.
+// Byte mode uses ISO-8859-1 if possible and falls back to UTF-8.
 fn string_to_bytes(s: &str, mode: Mode) -> Vec<u8> {
     match mode {
         Mode::Numeric =>
@@ -147,7 +736,12 @@ fn string_to_bytes(s: &str, mode: Mode) -> Vec<u8> {
         Mode::Alphanumeric =>
             s.chars().map(convert_alphanumeric).collect(),
         Mode::Byte =>
-            s.bytes().collect(),
+            mode::to_latin1(s).unwrap_or_else(|| s.bytes().collect()),
+        Mode::Kanji =>
+            mode::to_shift_jis(s).unwrap(),
+        // Rejected by Segment::new.
+        Mode::Eci | Mode::StructuredAppend | Mode::Fnc1First | Mode::Fnc1Second =>
+            unreachable!("{:?} doesn't hold any data", mode),
     }
 }
 
@@ -228,13 +822,17 @@ mod tests {
         assert_eq!(bitvec_char_count("HELLO WORLD".len(), Mode::Alphanumeric, Version::new(1)),
                    bitvec![0, 0, 0, 0, 0, 1, 0, 1, 1]);
 
-        assert_eq!(encode_numeric_data(&vec![8, 6, 7, 5, 3, 0, 9]),
+        assert_eq!(encode_numeric_data(&[8, 6, 7, 5, 3, 0, 9]),
                    bitvec![1, 1, 0, 1, 1, 0, 0, 0, 1, 1, // 867
                            1, 0, 0, 0, 0, 1, 0, 0, 1, 0, // 530
                            1, 0, 0, 1]); // 9
-        assert_eq!(encode_alphanumeric_data(&vec![17, 14]),
+        // Leading zeros still uses the full group length.
+        assert_eq!(encode_numeric_data(&[0, 1, 2, 0, 5]),
+                   bitvec![0, 0, 0, 0, 0, 0, 1, 1, 0, 0, // 012
+                           0, 0, 0, 0, 1, 0, 1]); // 05
+        assert_eq!(encode_alphanumeric_data(&[17, 14]),
                    bitvec![0, 1, 1, 0, 0, 0, 0, 1, 0, 1, 1]);
-        assert_eq!(encode_alphanumeric_data(&vec![45]),
+        assert_eq!(encode_alphanumeric_data(&[45]),
                    bitvec![1, 0, 1, 1, 0, 1]);
 
         assert_eq!(string_to_bytes("0123456789", Mode::Numeric),
@@ -245,6 +843,188 @@ mod tests {
                         37, 38, 39, 40, 41, 42, 43, 44]);
         assert_eq!(string_to_bytes("☃", Mode::Byte),
                    vec![0b11100010, 0b10011000, 0b10000011]);
+        assert_eq!(string_to_bytes("é", Mode::Byte),
+                   vec![0xE9]);
+        assert_eq!(string_to_bytes("点茗", Mode::Kanji),
+                   vec![0x93, 0x5F, 0xE4, 0xAA]);
+
+        assert_eq!(encode_kanji_data(&[0x93, 0x5F, 0xE4, 0xAA]),
+                   bitvec![0, 1, 1, 0, 1, 1, 0, 0, 1, 1, 1, 1, 1, // 0x935F
+                           1, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0]); // 0xE4AA
+    }
+
+    #[test]
+    fn segment_modes() {
+        let segment = Segment::new("点茗", Mode::Kanji).unwrap();
+        assert_eq!((segment.mode, segment.char_count), (Mode::Kanji, 2));
+        assert_eq!(Segment::new("12A", Mode::Numeric), Err(Error::UnsupportedMode));
+        assert_eq!(Segment::new("abc", Mode::Kanji), Err(Error::UnsupportedMode));
+
+        // Modes without data have their own constructors.
+        for mode in [Mode::Eci, Mode::StructuredAppend, Mode::Fnc1First, Mode::Fnc1Second].iter() {
+            assert_eq!(Segment::new("x", *mode), Err(Error::UnsupportedMode));
+            assert_eq!(segments_with_mode("x", *mode, None), Err(Error::UnsupportedMode));
+        }
+        assert_eq!(encode_with_mode("x", Mode::Eci, Version::new(1), ECLevel::L),
+                   Err(Error::UnsupportedMode));
+    }
+
+    #[test]
+    fn encode_binary() {
+        let encoded = encode_bytes(&[0xFF, 0x00], Version::new(1), ECLevel::Q);
+        let expected = bitvec![0, 1, 0, 0,
+                               0, 0, 0, 0, 0, 0, 1, 0,
+                               1, 1, 1, 1, 1, 1, 1, 1,
+                               0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(encoded[..expected.len()], expected[..]);
+        assert_eq!(encoded.len(), info::total_bits(Version::new(1), ECLevel::Q));
+    }
+
+    #[test]
+    fn encode_eci() {
+        let encoded = encode_with_eci("é", Mode::Byte, Some(Eci::UTF8),
+                                      Version::new(1), ECLevel::Q).unwrap();
+        // ECI mode and designator, then byte mode with two bytes.
+        let expected = bitvec![0, 1, 1, 1,
+                               0, 0, 0, 1, 1, 0, 1, 0,
+                               0, 1, 0, 0,
+                               0, 0, 0, 0, 0, 0, 1, 0,
+                               1, 1, 0, 0, 0, 0, 1, 1,
+                               1, 0, 1, 0, 1, 0, 0, 1];
+        assert_eq!(encoded[..expected.len()], expected[..]);
+        assert_eq!(bit_len("é", Mode::Byte, Some(Eci::UTF8), Version::new(1)),
+                   Ok(expected.len()));
+    }
+
+    #[test]
+    fn structured_append() {
+        let segment = Segment::structured_append(StructuredAppend::new(2, 4, 0x5A).unwrap());
+        let bv = segment.to_bitvec(Version::new(1));
+        // Mode, index, total - 1 and parity.
+        assert_eq!(bv, bitvec![0, 0, 1, 1,
+                               0, 0, 1, 0,
+                               0, 0, 1, 1,
+                               0, 1, 0, 1, 1, 0, 1, 0]);
+        assert_eq!(segment.bit_len(Version::new(40)), 20);
+
+        assert_eq!(StructuredAppend::new(15, 16, 0).map(|x| x.total()), Ok(16));
+        assert_eq!(StructuredAppend::new(0, 0, 0), Err(Error::InvalidStructuredAppend));
+        assert_eq!(StructuredAppend::new(0, 17, 0), Err(Error::InvalidStructuredAppend));
+        assert_eq!(StructuredAppend::new(3, 3, 0), Err(Error::InvalidStructuredAppend));
+
+        assert_eq!(parity(&[]), 0);
+        assert_eq!(parity(&[0x0F, 0xF0, 0x01]), 0xFE);
+    }
+
+    #[test]
+    fn fnc1() {
+        assert_eq!(Segment::fnc1(Fnc1::First).to_bitvec(Version::new(1)),
+                   bitvec![0, 1, 0, 1]);
+        // Application indicator is the letter + 100, 'a' is 197.
+        assert_eq!(Segment::fnc1(Fnc1::with_letter('a')).to_bitvec(Version::new(1)),
+                   bitvec![1, 0, 0, 1,
+                           1, 1, 0, 0, 0, 1, 0, 1]);
+        assert_eq!(Fnc1::with_digits(37), Fnc1::Second(37));
+
+        assert_eq!(fnc1_escape("A%B\u{1D}C"), "A%%B%C");
+
+        // FNC1 mode indicator, then alphanumeric mode with "A%".
+        let encoded = encode_with_fnc1("A\u{1D}", Mode::Alphanumeric, Fnc1::First,
+                                       Version::new(1), ECLevel::Q).unwrap();
+        let expected = bitvec![0, 1, 0, 1,
+                               0, 0, 1, 0,
+                               0, 0, 0, 0, 0, 0, 0, 1, 0,
+                               0, 0, 1, 1, 1, 1, 0, 1, 0, 0, 0];
+        assert_eq!(encoded[..expected.len()], expected[..]);
+
+        // GS can be encoded in alphanumeric mode, avoiding byte mode.
+        let s = "0104912345123459\u{1D}10ABC";
+        let segments = optimize_fnc1_segments(s, None, Version::new(1));
+        assert_eq!(segments.iter().map(|x| (x.mode, x.char_count)).collect::<Vec<_>>(),
+                   vec![(Mode::Numeric, 16), (Mode::Alphanumeric, 6)]);
+        let segments = optimize_segments(s, None, Version::new(1));
+        assert!(segments.iter().any(|x| x.mode == Mode::Byte));
+    }
+
+    #[test]
+    fn encode_micro() {
+        // Example from the QR specification.
+        let v = MicroVersion::new(2);
+        let segments = optimize_micro_segments("01234567", v).unwrap();
+        let encoded = encode_micro_segments(&segments, v, ECLevel::L);
+        assert_eq!(encoded.as_slice(), &[0b01000000, 0b00011000, 0b10101100,
+                                         0b11000011, 0b00000000]);
+
+        // M1 ends with a 4 bit codeword.
+        let v = MicroVersion::new(1);
+        let encoded = encode_micro_segments(&optimize_micro_segments("1", v).unwrap(),
+                                            v, ECLevel::L);
+        // Char count, data, terminator, zero bits and the 4 bit codeword.
+        assert_eq!(encoded, bitvec![0, 0, 1,
+                                    0, 0, 0, 1,
+                                    0, 0, 0, 0, 0, 0, 0, 0, 0,
+                                    0, 0, 0, 0]);
+
+        assert_eq!(optimize_micro_segments("a", MicroVersion::new(2)), None);
+        assert_eq!(optimize_micro_segments("☃", MicroVersion::new(4)), None);
+        let segments = optimize_micro_segments("AB12345678", MicroVersion::new(3)).unwrap();
+        assert_eq!(segments.iter().map(|x| x.mode).collect::<Vec<_>>(),
+                   vec![Mode::Alphanumeric, Mode::Numeric]);
+    }
+
+    #[test]
+    fn encode_rmqr() {
+        let v = RmqrVersion::new(7, 43);
+        let segments = optimize_rmqr_segments("123", None, v);
+        assert_eq!(rmqr_segments_bit_len(&segments, v), 17);
+        let encoded = encode_rmqr_segments(&segments, v, ECLevel::M);
+        // Mode, char count, data, terminator and padding.
+        assert_eq!(encoded.as_slice(), &[0b00100110, 0b00111101, 0b10000000,
+                                         0xEC, 0x11, 0xEC]);
+    }
+
+    #[test]
+    fn optimize() {
+        let modes = |s: &str| -> Vec<(Mode, usize)> {
+            optimize_segments(s, None, Version::new(1)).iter()
+                .map(|x| (x.mode, x.char_count))
+                .collect()
+        };
+        assert_eq!(modes("HELLO WORLD"),
+                   vec![(Mode::Alphanumeric, 11)]);
+        assert_eq!(modes("ORDER-12345678901234 for café"),
+                   vec![(Mode::Alphanumeric, 6), (Mode::Numeric, 14), (Mode::Byte, 9)]);
+        assert_eq!(modes("日本語123456789ABC"),
+                   vec![(Mode::Kanji, 3), (Mode::Numeric, 9), (Mode::Alphanumeric, 3)]);
+        // Short runs aren't worth switching modes for.
+        assert_eq!(modes("a1b"),
+                   vec![(Mode::Byte, 3)]);
+        // UTF-8 needs an ECI.
+        assert_eq!(modes("☃ 1234567890123"),
+                   vec![(Mode::Eci, 0), (Mode::Byte, 4), (Mode::Numeric, 13)]);
+        // Unless the characters outside of ISO-8859-1 fit kanji mode,
+        // then byte mode stays in ISO-8859-1 without an ECI.
+        assert_eq!(modes("点 café"),
+                   vec![(Mode::Kanji, 1), (Mode::Byte, 5)]);
+        assert_eq!(segments_bit_len(&optimize_segments("点 café", None, Version::new(1)),
+                                    Version::new(1)), 77);
+
+        let segments = optimize_segments("ORDER-12345678901234 for café", None, Version::new(1));
+        assert_eq!(segments_bit_len(&segments, Version::new(1)), 191);
+        assert!(segments_bit_len(&segments, Version::new(1)) <
+                bit_len("ORDER-12345678901234 for café", Mode::Byte, None, Version::new(1)).unwrap());
+    }
+
+    #[test]
+    fn encode_kanji() {
+        let (mode, encoded) = encode("点茗", Version::new(1), ECLevel::H);
+        assert_eq!(mode, Mode::Kanji);
+        // Mode, char count and the two characters.
+        let expected = bitvec![1, 0, 0, 0,
+                               0, 0, 0, 0, 0, 0, 1, 0,
+                               0, 1, 1, 0, 1, 1, 0, 0, 1, 1, 1, 1, 1,
+                               1, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0];
+        assert_eq!(encoded[..expected.len()], expected[..]);
     }
 }
 
@@ -0,0 +1,626 @@
+//! Decodes a QR code matrix back to its data.
+//!
+//! The steps are the reverse of the builder: read the format and version
+//! info, unmask, read the codewords in the zig-zag order, de-interleave
+//! the blocks, correct errors and parse the segments.
+
+use crate::builder::{QrBuilder, ZigZagIt};
+use crate::data::{Fnc1, Segment, StructuredAppend, GS};
+use crate::ec::{self, ECLevel};
+use crate::eci::Eci;
+use crate::info;
+use crate::mask::{self, Mask};
+use crate::matrix::Matrix;
+use crate::mode::Mode;
+use crate::version::Version;
+
+use bitvec::*;
+use encoding_rs::SHIFT_JIS;
+
+/// A decoded QR code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    /// Version of the QR code.
+    pub version: Version,
+
+    /// Error correction level.
+    pub ecl: ECLevel,
+
+    /// The applied mask, 0 to 7.
+    pub mask: Mask,
+
+    /// The data segments, including ECI, Structured Append and FNC1 headers.
+    pub segments: Vec<Segment>,
+
+    /// Character set of the byte mode data, the last one if there are several.
+    pub eci: Option<Eci>,
+
+    /// Position in a sequence of QR codes holding a message together, if any.
+    pub structured_append: Option<StructuredAppend>,
+
+    /// FNC1 mode, if the data is formatted per GS1 or another industry standard.
+    pub fnc1: Option<Fnc1>,
+
+    /// The data, numeric and alphanumeric mode as ASCII, byte mode as is
+    /// and kanji mode as Shift JIS.
+    pub data: Vec<u8>,
+
+    /// The data as text, or None if the data can't be converted
+    /// from its character set.
+    pub text: Option<String>,
+
+    /// Number of corrected codewords in each block.
+    pub corrected: Vec<usize>,
+}
+
+/// Errors when decoding.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The matrix doesn't have the size of a QR code.
+    InvalidSize,
+    /// Neither copy of the format info could be read.
+    InvalidFormat,
+    /// Neither copy of the version info could be read, or it doesn't match the size.
+    InvalidVersion,
+    /// There are more errors than the error correction can handle.
+    TooManyErrors,
+    /// The data segments are malformed.
+    InvalidData,
+}
+
+/// Decode a QR code matrix.
+///
+/// Only the darkness of the modules is used, so the matrix doesn't need
+/// to come from a builder.
+pub fn decode(matrix: &Matrix) -> Result<Decoded, DecodeError> {
+    let version = read_version(matrix)?;
+    let FormatInfo { ecl, mask, .. } = read_format_info(matrix)?;
+
+    let bytes = read_codewords(matrix, version, ecl, mask);
+    let (data, corrected) = ec::correct(&bytes, version, ecl, &[])
+        .ok_or(DecodeError::TooManyErrors)?;
+
+    let mut res = parse(&data.into(), version)?;
+    res.ecl = ecl;
+    res.mask = mask;
+    res.corrected = corrected;
+    Ok(res)
+}
+
+/// Format info read from both copies in a matrix.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FormatInfo {
+    /// Error correction level.
+    pub ecl: ECLevel,
+
+    /// The applied mask.
+    pub mask: Mask,
+
+    /// Each copy decoded on its own with the number of bit errors,
+    /// None if it's beyond correction. The copy around the top left finder
+    /// comes first, then the one split between the other finders.
+    pub copies: [Option<(ECLevel, Mask, usize)>; 2],
+}
+
+impl FormatInfo {
+    /// Do both copies hold the same format info?
+    pub fn consistent(&self) -> bool {
+        match self.copies {
+            [Some((ecl0, mask0, _)), Some((ecl1, mask1, _))] => ecl0 == ecl1 && mask0 == mask1,
+            _ => false,
+        }
+    }
+}
+
+/// Version info read from both copies in a matrix.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VersionInfo {
+    /// The version.
+    pub version: Version,
+
+    /// Each copy decoded on its own with the number of bit errors,
+    /// None if it's beyond correction. The bottom left copy comes first,
+    /// then the top right.
+    pub copies: [Option<(Version, usize)>; 2],
+}
+
+impl VersionInfo {
+    /// Do both copies hold the same version?
+    pub fn consistent(&self) -> bool {
+        match self.copies {
+            [Some((v0, _)), Some((v1, _))] => v0 == v1,
+            _ => false,
+        }
+    }
+}
+
+/// Read the format info of a QR code matrix.
+/// If the copies disagree the one with the fewest bit errors is used.
+pub fn read_format_info(matrix: &Matrix) -> Result<FormatInfo, DecodeError> {
+    if matrix.width != matrix.height || matrix.width < 21 {
+        return Err(DecodeError::InvalidSize);
+    }
+    let size = matrix.width;
+
+    // Surrounding the top left finder, avoiding the timing patterns.
+    let mut top_left = BitVec::with_capacity(15);
+    for x in (0..8).filter(|x| *x != 6) {
+        top_left.push(matrix.is_dark(x, 8));
+    }
+    for y in (0..9).rev().filter(|y| *y != 6) {
+        top_left.push(matrix.is_dark(8, y));
+    }
+
+    // Split between the bottom left and the top right finders.
+    let mut split = BitVec::with_capacity(15);
+    for y in (size - 7..size).rev() {
+        split.push(matrix.is_dark(8, y));
+    }
+    for x in (size - 8)..size {
+        split.push(matrix.is_dark(x, 8));
+    }
+
+    let copies = [info::decode_format_info(&top_left), info::decode_format_info(&split)];
+    let (ecl, mask, _) = copies.iter().flatten()
+        .min_by_key(|(_, _, dist)| *dist)
+        .ok_or(DecodeError::InvalidFormat)?;
+    Ok(FormatInfo { ecl: *ecl, mask: *mask, copies })
+}
+
+/// Read the version info of a QR code matrix, only present in version 7 and up.
+/// Like the specification the top right copy is used, the bottom left copy
+/// only if the top right one is beyond correction.
+pub fn read_version_info(matrix: &Matrix) -> Result<VersionInfo, DecodeError> {
+    if matrix.width != matrix.height || matrix.width < 45 {
+        return Err(DecodeError::InvalidSize);
+    }
+    let size = matrix.width;
+
+    // Bottom left and top right version blocks.
+    let mut bottom_left = BitVec::with_capacity(18);
+    for x in 0..6 {
+        for y in (size - 11)..(size - 8) {
+            bottom_left.push(matrix.is_dark(x, y));
+        }
+    }
+    let mut top_right = BitVec::with_capacity(18);
+    for y in 0..6 {
+        for x in (size - 11)..(size - 8) {
+            top_right.push(matrix.is_dark(x, y));
+        }
+    }
+
+    let copies = [info::decode_version_info(&bottom_left), info::decode_version_info(&top_right)];
+    let (version, _) = copies[1].or(copies[0]).ok_or(DecodeError::InvalidVersion)?;
+    Ok(VersionInfo { version, copies })
+}
+
+// Reads the data and ec codewords in the interleaved order they're placed
+// in the matrix, excluding remainder bits.
+pub(crate) fn read_codewords(matrix: &Matrix, version: Version, ecl: ECLevel,
+                             mask: Mask) -> Vec<u8> {
+    // The function patterns tells us which modules hold data.
+    let mut builder = QrBuilder::new().version(version);
+    builder.add_fun_patterns();
+    let mut unmasked = builder.matrix;
+    let size = matrix.width;
+    for y in 0..size {
+        for x in 0..size {
+            if !unmasked.is_fun(x, y) {
+                unmasked.set_data(x, y, matrix.is_dark(x, y));
+            }
+        }
+    }
+    // Masking is its own inverse.
+    let unmasked = mask::apply_mask(mask, &unmasked);
+
+    let mut bytes = Vec::new();
+    let mut byte = 0u8;
+    let mut bit_count = 0;
+    for (x, y) in ZigZagIt::new(size) {
+        if unmasked.is_fun(x, y) { continue; }
+        byte = (byte << 1) | unmasked.is_dark(x, y) as u8;
+        bit_count += 1;
+        if bit_count == 8 {
+            bytes.push(byte);
+            bit_count = 0;
+        }
+    }
+    // Trailing remainder bits are ignored.
+
+    bytes.truncate(info::total_codewords(version, ecl) +
+                   info::block_ec_count(version, ecl) *
+                   info::group_block_count(version, ecl).len());
+    bytes
+}
+
+// The version is given by the size, larger versions also holds version info.
+fn read_version(matrix: &Matrix) -> Result<Version, DecodeError> {
+    let size = matrix.width;
+    if matrix.height != size || !(21..=177).contains(&size) || !(size - 17).is_multiple_of(4) {
+        return Err(DecodeError::InvalidSize);
+    }
+    let version = Version::new((size - 17) / 4);
+    if !version.extra_version_areas() {
+        return Ok(version);
+    }
+    if read_version_info(matrix)?.version == version {
+        Ok(version)
+    } else {
+        Err(DecodeError::InvalidVersion)
+    }
+}
+
+// Reads bits from the start of a BitVec.
+struct BitReader<'a> {
+    bv: &'a BitVec,
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn remaining(&self) -> usize {
+        self.bv.len() - self.pos
+    }
+
+    fn read(&mut self, len: usize) -> Result<u32, DecodeError> {
+        if len > self.remaining() {
+            return Err(DecodeError::InvalidData);
+        }
+        let mut v = 0;
+        for i in self.pos..self.pos + len {
+            v = (v << 1) | self.bv[i] as u32;
+        }
+        self.pos += len;
+        Ok(v)
+    }
+
+    // Returns the bits read since a position.
+    fn since(&self, start: usize) -> BitVec {
+        (start..self.pos).map(|i| self.bv[i]).collect()
+    }
+}
+
+// Parse the data segments, until the terminator or the end of data.
+pub(crate) fn parse(bv: &BitVec, version: Version) -> Result<Decoded, DecodeError> {
+    let mut res = Decoded {
+        version,
+        ecl: ECLevel::L,
+        mask: Mask::new(0),
+        segments: Vec::new(),
+        eci: None,
+        structured_append: None,
+        fnc1: None,
+        data: Vec::new(),
+        text: Some(String::new()),
+        corrected: Vec::new(),
+    };
+
+    let mut reader = BitReader { bv, pos: 0 };
+    while reader.remaining() >= 4 {
+        let mode = match reader.read(4)? {
+            0b0000 => break,
+            0b0001 => Mode::Numeric,
+            0b0010 => Mode::Alphanumeric,
+            0b0100 => Mode::Byte,
+            0b1000 => Mode::Kanji,
+            0b0111 => Mode::Eci,
+            0b0011 => Mode::StructuredAppend,
+            0b0101 => Mode::Fnc1First,
+            0b1001 => Mode::Fnc1Second,
+            _ => return Err(DecodeError::InvalidData),
+        };
+        let char_count = reader.read(version.char_count_len(mode))? as usize;
+        let start = reader.pos;
+
+        match mode {
+            Mode::Eci => res.eci = Some(read_eci(&mut reader)?),
+            Mode::StructuredAppend => {
+                let index = reader.read(4)? as usize;
+                let total = reader.read(4)? as usize + 1;
+                let parity = reader.read(8)? as u8;
+                let header = StructuredAppend::new(index, total, parity)
+                    .map_err(|_| DecodeError::InvalidData)?;
+                res.structured_append = Some(header);
+            }
+            Mode::Fnc1First => res.fnc1 = Some(Fnc1::First),
+            Mode::Fnc1Second => res.fnc1 = Some(Fnc1::Second(reader.read(8)? as u8)),
+            _ => {
+                let (bytes, text) = read_data(&mut reader, mode, char_count, res.eci)?;
+                // In FNC1 mode % is the GS separator and %% is %.
+                let (bytes, text) = if res.fnc1.is_some() && mode == Mode::Alphanumeric {
+                    let s = text.unwrap().replace("%%", "\0").replace('%', &GS.to_string())
+                        .replace('\0', "%");
+                    (s.bytes().collect(), Some(s))
+                } else {
+                    (bytes, text)
+                };
+                res.data.extend(bytes);
+                res.text = match (res.text, text) {
+                    (Some(mut a), Some(b)) => {
+                        a.push_str(&b);
+                        Some(a)
+                    }
+                    _ => None,
+                };
+            }
+        }
+
+        res.segments.push(Segment {
+            mode,
+            char_count,
+            data: reader.since(start),
+        });
+    }
+
+    Ok(res)
+}
+
+// The designator is 1, 2 or 3 bytes long and the leading bits
+// tells us how many bytes there are.
+fn read_eci(reader: &mut BitReader) -> Result<Eci, DecodeError> {
+    let v = if reader.read(1)? == 0 {
+        reader.read(7)?
+    } else if reader.read(1)? == 0 {
+        reader.read(14)?
+    } else if reader.read(1)? == 0 {
+        reader.read(21)?
+    } else {
+        return Err(DecodeError::InvalidData);
+    };
+    if v > 999999 {
+        return Err(DecodeError::InvalidData);
+    }
+    Ok(Eci::new(v))
+}
+
+// Returns the bytes and text of a data segment.
+fn read_data(reader: &mut BitReader, mode: Mode, char_count: usize, eci: Option<Eci>)
+    -> Result<(Vec<u8>, Option<String>), DecodeError>
+{
+    let mut bytes = Vec::with_capacity(char_count);
+    match mode {
+        Mode::Numeric => {
+            // Groups of three digits, the last group may have one or two digits.
+            let mut left = char_count;
+            while left > 0 {
+                let digits = left.min(3);
+                let v = reader.read([0, 4, 7, 10][digits])?;
+                if v >= 10u32.pow(digits as u32) {
+                    return Err(DecodeError::InvalidData);
+                }
+                bytes.extend(format!("{:0width$}", v, width = digits).bytes());
+                left -= digits;
+            }
+        }
+        Mode::Alphanumeric => {
+            // Pairs of characters, the last may be a single character.
+            let mut left = char_count;
+            while left > 0 {
+                if left >= 2 {
+                    let v = reader.read(11)? as usize;
+                    if v >= 45 * 45 {
+                        return Err(DecodeError::InvalidData);
+                    }
+                    bytes.push(ALPHANUMERIC_CHARS[v / 45]);
+                    bytes.push(ALPHANUMERIC_CHARS[v % 45]);
+                    left -= 2;
+                } else {
+                    let v = reader.read(6)? as usize;
+                    if v >= 45 {
+                        return Err(DecodeError::InvalidData);
+                    }
+                    bytes.push(ALPHANUMERIC_CHARS[v]);
+                    left -= 1;
+                }
+            }
+        }
+        Mode::Byte => {
+            for _ in 0..char_count {
+                bytes.push(reader.read(8)? as u8);
+            }
+        }
+        Mode::Kanji => {
+            for _ in 0..char_count {
+                let v = reader.read(13)?;
+                // Reverse the compaction, then add the start of the range.
+                let x = ((v / 0xC0) << 8) | (v % 0xC0);
+                let sjis = if x + 0x8140 <= 0x9FFC { x + 0x8140 } else { x + 0xC140 };
+                bytes.push((sjis >> 8) as u8);
+                bytes.push(sjis as u8);
+            }
+        }
+        _ => unreachable!(),
+    }
+
+    let text = match mode {
+        Mode::Byte => eci.unwrap_or(Eci::ISO_8859_1).decode(&bytes),
+        Mode::Kanji => {
+            SHIFT_JIS.decode_without_bom_handling_and_without_replacement(&bytes)
+                .map(|x| x.into_owned())
+        }
+        _ => Some(bytes.iter().map(|x| *x as char).collect()),
+    };
+    Ok((bytes, text))
+}
+
+static ALPHANUMERIC_CHARS: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::QrBuilder;
+    use crate::matrix::Module;
+    use crate::qr::Qr;
+
+    #[test]
+    fn hello_world() {
+        let qr = QrBuilder::new()
+            .version(Version::new(1))
+            .ecl(ECLevel::Q)
+            .into("HELLO WORLD")
+            .unwrap();
+        let decoded = decode(&qr.matrix).unwrap();
+        assert_eq!(decoded.version, Version::new(1));
+        assert_eq!(decoded.ecl, ECLevel::Q);
+        assert_eq!(decoded.mask, qr.mask);
+        assert_eq!(decoded.text, Some("HELLO WORLD".to_string()));
+        assert_eq!(decoded.data, b"HELLO WORLD".to_vec());
+        assert_eq!(decoded.segments.len(), 1);
+        assert_eq!(decoded.segments[0], Segment::new("HELLO WORLD", Mode::Alphanumeric).unwrap());
+        assert_eq!(decoded.corrected, vec![0]);
+    }
+
+    #[test]
+    fn segments() {
+        let s = "ORDER-12345678901234 for café 日本語";
+        let qr = Qr::new(s).unwrap();
+        let decoded = decode(&qr.matrix).unwrap();
+        assert_eq!(decoded.text.as_deref(), Some(s));
+        assert_eq!(decoded.eci, None);
+
+        let qr = Qr::new("☃ snowman").unwrap();
+        let decoded = decode(&qr.matrix).unwrap();
+        assert_eq!(decoded.eci, Some(Eci::UTF8));
+        assert_eq!(decoded.text.as_deref(), Some("☃ snowman"));
+
+        let qr = Qr::from_bytes(&[0xFF, 0x00, 0x80]).unwrap();
+        assert_eq!(decode(&qr.matrix).unwrap().data, vec![0xFF, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn headers() {
+        let qr = QrBuilder::new()
+            .fnc1(Fnc1::First)
+            .structured_append(StructuredAppend::new(1, 3, 0x42).unwrap())
+            .mode(Mode::Alphanumeric)
+            .into("01A\u{1D}10%")
+            .unwrap();
+        let decoded = decode(&qr.matrix).unwrap();
+        assert_eq!(decoded.fnc1, Some(Fnc1::First));
+        assert_eq!(decoded.structured_append, StructuredAppend::new(1, 3, 0x42).ok());
+        assert_eq!(decoded.text.as_deref(), Some("01A\u{1D}10%"));
+    }
+
+    #[test]
+    fn version_info() {
+        let qr = Qr::with_version("VERSION 7", Version::new(7)).unwrap();
+        let decoded = decode(&qr.matrix).unwrap();
+        assert_eq!(decoded.version, Version::new(7));
+        assert_eq!(decoded.text.as_deref(), Some("VERSION 7"));
+
+        let s = "LARGE".repeat(200);
+        let qr = QrBuilder::new().version(Version::new(40)).ecl(ECLevel::H).into(&s).unwrap();
+        let decoded = decode(&qr.matrix).unwrap();
+        assert_eq!(decoded.version, Version::new(40));
+        assert_eq!(decoded.text, Some(s));
+        assert_eq!(decoded.corrected, vec![0; 81]);
+    }
+
+    #[test]
+    fn info_copies() {
+        let qr = Qr::with_version("COPIES", Version::new(7)).unwrap();
+        let format = read_format_info(&qr.matrix).unwrap();
+        assert!(format.consistent());
+        assert_eq!(format.copies[0], Some((qr.ecl, qr.mask, 0)));
+        let version = read_version_info(&qr.matrix).unwrap();
+        assert!(version.consistent());
+        assert_eq!(version.copies, [Some((Version::new(7), 0)); 2]);
+
+        // Damage the format info around the top left finder beyond repair
+        // and two bits of the bottom left version info.
+        let mut matrix = qr.matrix.clone();
+        let mut flip = |x, y| {
+            let m = *matrix.get(x, y);
+            matrix.set(x, y, !m);
+        };
+        for x in 0..6 {
+            flip(x, 8);
+        }
+        flip(0, 34);
+        flip(5, 36);
+        let format = read_format_info(&matrix).unwrap();
+        assert!(!format.consistent());
+        assert_eq!((format.ecl, format.mask), (qr.ecl, qr.mask));
+        assert_eq!(format.copies[1], Some((qr.ecl, qr.mask, 0)));
+        let version = read_version_info(&matrix).unwrap();
+        assert!(version.consistent());
+        assert_eq!(version.copies[0], Some((Version::new(7), 2)));
+        assert_eq!(decode(&matrix).unwrap().text.as_deref(), Some("COPIES"));
+
+        assert_eq!(read_version_info(&Qr::new("V1").unwrap().matrix),
+                   Err(DecodeError::InvalidSize));
+    }
+
+    #[test]
+    fn mismatched_version_info() {
+        let qr = Qr::with_version("MISMATCH", Version::new(7)).unwrap();
+        let size = qr.matrix.width;
+        let other = info::version_info(Version::new(8)).unwrap();
+
+        // Another version in the bottom left copy, the top right copy is used.
+        let mut matrix = qr.matrix.clone();
+        let mut iter = other.iter();
+        for x in 0..6 {
+            for y in (size - 11)..(size - 8) {
+                matrix.set(x, y, Module::Function(iter.next().unwrap()));
+            }
+        }
+        let version = read_version_info(&matrix).unwrap();
+        assert!(!version.consistent());
+        assert_eq!(version.version, Version::new(7));
+        assert_eq!(decode(&matrix).unwrap().text.as_deref(), Some("MISMATCH"));
+
+        // Another version in the top right copy doesn't match the size.
+        let mut matrix = qr.matrix.clone();
+        let mut iter = other.iter();
+        for y in 0..6 {
+            for x in (size - 11)..(size - 8) {
+                matrix.set(x, y, Module::Function(iter.next().unwrap()));
+            }
+        }
+        assert_eq!(read_version_info(&matrix).unwrap().version, Version::new(8));
+        assert_eq!(decode(&matrix), Err(DecodeError::InvalidVersion));
+    }
+
+    #[test]
+    fn errors() {
+        let qr = QrBuilder::new()
+            .version(Version::new(1))
+            .ecl(ECLevel::H)
+            .into("HELLO")
+            .unwrap();
+
+        // Flip a module in four different codewords.
+        let mut matrix = qr.matrix.clone();
+        let data_modules: Vec<(usize, usize)> = ZigZagIt::new(21)
+            .filter(|(x, y)| matrix.is_data(*x, *y))
+            .collect();
+        for i in [0, 20, 45, 130].iter() {
+            let (x, y) = data_modules[*i];
+            matrix.set(x, y, Module::Data(!matrix.is_dark(x, y)));
+        }
+        let decoded = decode(&matrix).unwrap();
+        assert_eq!(decoded.text.as_deref(), Some("HELLO"));
+        assert_eq!(decoded.corrected, vec![4]);
+
+        // Damaging one copy of the format info is fine.
+        let mut matrix = qr.matrix.clone();
+        for x in 0..6 {
+            matrix.set(x, 8, Module::Function(!matrix.is_dark(x, 8)));
+        }
+        assert!(decode(&matrix).is_ok());
+
+        // Wiping out the data is not.
+        let mut matrix = qr.matrix.clone();
+        for y in 9..21 {
+            for x in 9..21 {
+                if matrix.is_data(x, y) {
+                    matrix.set(x, y, Module::Data(false));
+                }
+            }
+        }
+        assert_eq!(decode(&matrix), Err(DecodeError::TooManyErrors));
+
+        assert_eq!(decode(&Matrix::new(22)), Err(DecodeError::InvalidSize));
+    }
+}
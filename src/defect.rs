@@ -0,0 +1,283 @@
+//! Builds QR codes with specific defects, for testing how readers handle them.
+//!
+//! The codes are built step by step like the builder does, with the defect
+//! introduced at the step where it belongs. Each code comes with a description
+//! of the defect and the outcome a reader following the specification should have.
+
+use crate::builder::{self, Error, QrBuilder};
+use crate::data;
+use crate::decode::DecodeError;
+use crate::ec::{self, ECLevel};
+use crate::info;
+use crate::mask::Mask;
+use crate::matrix::{Matrix, Module};
+use crate::version::Version;
+
+/// A defect to build into a QR code.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Defect {
+    /// Flip the first bits of both format info copies.
+    FormatErrors(usize),
+    /// The bottom left version info copy holds another version,
+    /// the top right copy is correct. Only in version 7 and up.
+    MismatchedVersionInfo,
+    /// Damage exactly `count` codewords in a block, the blocks are counted from 0.
+    CodewordErrors { block: usize, count: usize },
+    /// The alignment patterns are cleared. Only in version 2 and up.
+    MissingAlignment,
+    /// The data is masked with another mask than the format info says.
+    WrongMask,
+}
+
+/// An error from generating a defective code.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DefectError {
+    /// Building the code failed.
+    Build(Error),
+    /// The defect can't be made in the version, like version info below version 7.
+    Unsupported,
+}
+
+impl From<Error> for DefectError {
+    fn from(e: Error) -> Self {
+        DefectError::Build(e)
+    }
+}
+
+/// The expected outcome of reading a defective code.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The original data is read.
+    Decodes,
+    /// Reading fails.
+    Fails(DecodeError),
+}
+
+/// A QR code with a defect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefectiveQr {
+    /// Version of the QR code.
+    pub version: Version,
+
+    /// Error correction level.
+    pub ecl: ECLevel,
+
+    /// The mask given by the format info.
+    pub mask: Mask,
+
+    /// The defect.
+    pub defect: Defect,
+
+    /// Human readable description of the defect.
+    pub description: String,
+
+    /// What a reader should do with the code.
+    pub expected: Outcome,
+
+    /// The modules.
+    pub matrix: Matrix,
+}
+
+/// Build a QR code holding a string with a defect.
+/// The builder settings, like version and error correction, are respected.
+pub fn generate(builder: QrBuilder, s: &str, defect: Defect) -> Result<DefectiveQr, DefectError> {
+    let mut builder = builder;
+    builder.add_all(s)?;
+    let version = builder.version.unwrap();
+    let ecl = builder.ecl;
+    let mask = builder.mask.unwrap();
+
+    let mut codewords = ec::add(data::encode_segments(&builder.segments, version, ecl),
+                                version, ecl);
+    let ec_count = info::block_ec_count(version, ecl);
+    // Codewords reserved for misdecode protection aren't used for correction.
+    let correctable = (ec_count - info::misdecode_protection_count(version, ecl)) / 2;
+    let mut expected = Outcome::Decodes;
+    let description;
+    let data_mask;
+    match defect {
+        Defect::CodewordErrors { block, count } => {
+            let layout = info::group_block_count(version, ecl);
+            if block >= layout.len() || count > layout[block] + ec_count {
+                return Err(DefectError::Unsupported);
+            }
+            let positions = ec::block_positions(&layout, ec_count);
+            let damaged = positions.iter().enumerate()
+                .filter(|(_, (b, _))| *b == block)
+                .take(count);
+            for (i, _) in damaged {
+                for bit in (8 * i)..(8 * i + 8) {
+                    let v = codewords[bit];
+                    codewords.set(bit, !v);
+                }
+            }
+            if count > correctable {
+                expected = Outcome::Fails(DecodeError::TooManyErrors);
+            }
+            description = format!("{} of {} codewords in block {} are damaged, {} can be corrected",
+                                  count, layout[block] + ec_count, block, correctable);
+            data_mask = mask;
+        }
+        Defect::WrongMask => {
+            data_mask = Mask::new((mask.0 + 1) % 8);
+            expected = Outcome::Fails(DecodeError::TooManyErrors);
+            description = format!("The data is masked with mask {} but the format info says mask {}",
+                                  data_mask.0, mask.0);
+        }
+        Defect::FormatErrors(n) => {
+            if n > 15 {
+                return Err(DefectError::Unsupported);
+            }
+            let mut format = info::format_info(ecl, mask);
+            for i in 0..n {
+                let v = format[i];
+                format.set(i, !v);
+            }
+            expected = match info::decode_format_info(&format) {
+                Some((e, m, _)) if e == ecl && m == mask => Outcome::Decodes,
+                // Another valid format, the data is unmasked with the wrong mask.
+                Some(_) => Outcome::Fails(DecodeError::TooManyErrors),
+                None => Outcome::Fails(DecodeError::InvalidFormat),
+            };
+            description = format!("{} of 15 bits are flipped in both format info copies, \
+                                   3 can be corrected", n);
+            data_mask = mask;
+        }
+        Defect::MismatchedVersionInfo => {
+            if !version.extra_version_areas() {
+                return Err(DefectError::Unsupported);
+            }
+            description = "The bottom left version info copy holds another version, \
+                           the top right copy and the size agree".to_string();
+            data_mask = mask;
+        }
+        Defect::MissingAlignment => {
+            if version.0 < 2 {
+                return Err(DefectError::Unsupported);
+            }
+            description = format!("The {} alignment patterns are missing, only readers \
+                                   relying on them to locate modules fails",
+                                  builder::alignment_centers(version).len());
+            data_mask = mask;
+        }
+    }
+
+    let mut builder = QrBuilder::new().version(version).ecl(ecl);
+    builder.add_fun_patterns();
+    builder.add_raw_data(&codewords);
+    builder.mask_with(data_mask);
+    builder.mask = Some(mask);
+    builder.add_info();
+    let mut matrix = builder.matrix;
+
+    match defect {
+        Defect::FormatErrors(n) => {
+            let size = matrix.width;
+            // Same order as the bits are placed in.
+            let top_left = (0..8).filter(|x| *x != 6).map(|x| (x, 8))
+                .chain((0..9).rev().filter(|y| *y != 6).map(|y| (8, y)));
+            let split = ((size - 7)..size).rev().map(|y| (8, y))
+                .chain(((size - 8)..size).map(|x| (x, 8)));
+            for (x, y) in top_left.take(n).chain(split.take(n)) {
+                let dark = matrix.is_dark(x, y);
+                matrix.set(x, y, Module::Function(!dark));
+            }
+        }
+        Defect::MismatchedVersionInfo => {
+            let other = Version::new(if version.0 == 40 { 39 } else { version.0 + 1 });
+            let bv = info::version_info(other).unwrap();
+            let size = matrix.width;
+            let mut iter = bv.iter();
+            for x in 0..6 {
+                for y in (size - 11)..(size - 8) {
+                    matrix.set(x, y, Module::Function(iter.next().unwrap()));
+                }
+            }
+        }
+        Defect::MissingAlignment => {
+            for (cx, cy) in builder::alignment_centers(version) {
+                matrix.set_rect(cx - 2, cy - 2, cx + 2, cy + 2, Module::Function(false));
+            }
+        }
+        _ => {}
+    }
+
+    Ok(DefectiveQr { version, ecl, mask, defect, description, expected, matrix })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::decode;
+    use crate::mode::Mode;
+
+    // The reference decoder should agree with the expected outcome.
+    fn check(builder: QrBuilder, s: &str, defect: Defect) -> DefectiveQr {
+        let qr = generate(builder, s, defect).unwrap();
+        match qr.expected {
+            Outcome::Decodes => assert_eq!(decode(&qr.matrix).unwrap().text.as_deref(), Some(s),
+                                           "{}", qr.description),
+            Outcome::Fails(e) => assert_eq!(decode(&qr.matrix), Err(e), "{}", qr.description),
+        }
+        qr
+    }
+
+    #[test]
+    fn codeword_errors() {
+        let builder = QrBuilder::new().version(Version::new(5)).ecl(ECLevel::Q);
+        let s = "BLOCK ERRORS";
+        let qr = check(builder.clone(), s, Defect::CodewordErrors { block: 2, count: 9 });
+        assert_eq!(qr.expected, Outcome::Decodes);
+        assert_eq!(decode(&qr.matrix).unwrap().corrected, vec![0, 0, 9, 0]);
+        let qr = check(builder.clone(), s, Defect::CodewordErrors { block: 2, count: 10 });
+        assert_eq!(qr.expected, Outcome::Fails(DecodeError::TooManyErrors));
+        assert_eq!(qr.description, "10 of 34 codewords in block 2 are damaged, 9 can be corrected");
+        assert_eq!(generate(builder, s, Defect::CodewordErrors { block: 4, count: 1 }),
+                   Err(DefectError::Unsupported));
+
+        // 1-L reserves 3 of the 7 error correction codewords for misdecode
+        // protection, unlike the reference decoder which corrects 3 errors.
+        let builder = QrBuilder::new().version(Version::new(1)).ecl(ECLevel::L);
+        let qr = check(builder.clone(), s, Defect::CodewordErrors { block: 0, count: 2 });
+        assert_eq!(qr.expected, Outcome::Decodes);
+        let qr = generate(builder.clone(), s, Defect::CodewordErrors { block: 0, count: 3 }).unwrap();
+        assert_eq!(qr.expected, Outcome::Fails(DecodeError::TooManyErrors));
+        assert_eq!(qr.description, "3 of 26 codewords in block 0 are damaged, 2 can be corrected");
+        assert_eq!(generate(builder.mode(Mode::Numeric), s, Defect::WrongMask),
+                   Err(DefectError::Build(Error::UnsupportedMode)));
+    }
+
+    #[test]
+    fn format_errors() {
+        for n in 0..=15 {
+            let qr = check(QrBuilder::new(), "FORMAT", Defect::FormatErrors(n));
+            assert_eq!(qr.expected == Outcome::Decodes, n <= 3, "{} bits", n);
+        }
+    }
+
+    #[test]
+    fn wrong_mask() {
+        for mask in 0..8 {
+            let builder = QrBuilder::new().mask(Mask::new(mask));
+            let qr = check(builder, "WRONG MASK", Defect::WrongMask);
+            assert_eq!(qr.mask, Mask::new(mask));
+        }
+    }
+
+    #[test]
+    fn function_patterns() {
+        let builder = QrBuilder::new().version(Version::new(7));
+        let qr = check(builder.clone(), "VERSION", Defect::MismatchedVersionInfo);
+        assert_eq!(qr.expected, Outcome::Decodes);
+        let qr = check(builder, "ALIGNMENT", Defect::MissingAlignment);
+        assert_eq!(qr.description, "The 6 alignment patterns are missing, only readers \
+                                    relying on them to locate modules fails");
+        assert!(!qr.matrix.is_dark(22, 22));
+
+        let builder = QrBuilder::new().version(Version::new(1));
+        assert_eq!(generate(builder.clone(), "V1", Defect::MismatchedVersionInfo),
+                   Err(DefectError::Unsupported));
+        assert_eq!(generate(builder, "V1", Defect::MissingAlignment),
+                   Err(DefectError::Unsupported));
+    }
+}
@@ -0,0 +1,902 @@
+//! Locates QR codes in grayscale images.
+//!
+//! The steps are: binarize the image with a threshold local to each
+//! part of the image, find the three finder patterns by their 1:1:3:1:1
+//! dark/light ratio, estimate the version from their distances, refine
+//! the bottom right corner with the alignment pattern and sample the
+//! modules through a perspective transform.
+
+use crate::builder::QrBuilder;
+use crate::decode::{self, Decoded, DecodeError};
+use crate::matrix::{Matrix, Module};
+use crate::version::Version;
+
+use std::cmp;
+
+/// Errors when detecting.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DetectError {
+    /// The pixel buffer doesn't match the width and height.
+    InvalidImage,
+    /// No QR code could be located.
+    NotFound,
+    /// A QR code was located but it couldn't be decoded.
+    Decode(DecodeError),
+}
+
+/// A binarized image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    /// Width in pixels.
+    pub width: usize,
+
+    /// Height in pixels.
+    pub height: usize,
+
+    /// The pixels row by row, dark pixels are true.
+    pub pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    /// Is the pixel dark?
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+
+    // Pixels outside of the image are light.
+    fn is_dark_at(&self, x: isize, y: isize) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height &&
+            self.is_dark(x as usize, y as usize)
+    }
+}
+
+/// Binarize a grayscale image, one byte per pixel row by row.
+///
+/// The image is split into blocks of 8x8 pixels and each pixel is compared
+/// against the average of the surrounding 5x5 blocks, which handles uneven
+/// lighting. Blocks with low contrast borrows the level of their neighbors.
+/// Small images falls back to a single global threshold.
+pub fn binarize(pixels: &[u8], width: usize, height: usize) -> Result<Bitmap, DetectError> {
+    if width == 0 || height == 0 || pixels.len() != width * height {
+        return Err(DetectError::InvalidImage);
+    }
+    if width < 5 * BLOCK_SIZE || height < 5 * BLOCK_SIZE {
+        return Ok(binarize_global(pixels, width, height));
+    }
+
+    let blocks_x = width.div_ceil(BLOCK_SIZE);
+    let blocks_y = height.div_ceil(BLOCK_SIZE);
+    let mut levels = vec![0u32; blocks_x * blocks_y];
+    for by in 0..blocks_y {
+        // The last blocks overlaps the ones before to stay inside the image.
+        let y0 = cmp::min(by * BLOCK_SIZE, height - BLOCK_SIZE);
+        for bx in 0..blocks_x {
+            let x0 = cmp::min(bx * BLOCK_SIZE, width - BLOCK_SIZE);
+            let mut sum = 0u32;
+            let mut min = u8::MAX;
+            let mut max = 0u8;
+            for y in y0..(y0 + BLOCK_SIZE) {
+                for &p in &pixels[(y * width + x0)..(y * width + x0 + BLOCK_SIZE)] {
+                    sum += p as u32;
+                    min = cmp::min(min, p);
+                    max = cmp::max(max, p);
+                }
+            }
+            let mut level = sum / (BLOCK_SIZE * BLOCK_SIZE) as u32;
+            if max - min <= MIN_CONTRAST {
+                // Assume a flat block is light, unless it's darker than
+                // the blocks we've already seen next to it.
+                level = min.saturating_sub(MIN_CONTRAST) as u32;
+                if bx > 0 && by > 0 {
+                    let neighbors = (levels[(by - 1) * blocks_x + bx] +
+                                     2 * levels[by * blocks_x + bx - 1] +
+                                     levels[(by - 1) * blocks_x + bx - 1]) / 4;
+                    if (min as u32) < neighbors {
+                        level = neighbors;
+                    }
+                }
+            }
+            levels[by * blocks_x + bx] = level;
+        }
+    }
+
+    let mut res = Bitmap { width, height, pixels: vec![false; width * height] };
+    for by in 0..blocks_y {
+        let y0 = cmp::min(by * BLOCK_SIZE, height - BLOCK_SIZE);
+        let cy = cmp::min(cmp::max(by, 2), blocks_y - 3);
+        for bx in 0..blocks_x {
+            let x0 = cmp::min(bx * BLOCK_SIZE, width - BLOCK_SIZE);
+            let cx = cmp::min(cmp::max(bx, 2), blocks_x - 3);
+            let mut sum = 0;
+            for ny in (cy - 2)..=(cy + 2) {
+                for nx in (cx - 2)..=(cx + 2) {
+                    sum += levels[ny * blocks_x + nx];
+                }
+            }
+            let threshold = sum / 25;
+            for y in y0..(y0 + BLOCK_SIZE) {
+                for x in x0..(x0 + BLOCK_SIZE) {
+                    res.pixels[y * width + x] = (pixels[y * width + x] as u32) <= threshold;
+                }
+            }
+        }
+    }
+    Ok(res)
+}
+
+fn binarize_global(pixels: &[u8], width: usize, height: usize) -> Bitmap {
+    let min = *pixels.iter().min().unwrap() as u32;
+    let max = *pixels.iter().max().unwrap() as u32;
+    let threshold = (min + max) / 2;
+    Bitmap {
+        width,
+        height,
+        pixels: pixels.iter().map(|p| (*p as u32) < threshold).collect(),
+    }
+}
+
+/// A QR code found in an image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Found {
+    /// The decoded QR code.
+    pub decoded: Decoded,
+
+    /// The outer corners of the symbol in pixel coordinates, clockwise from
+    /// the top left corner as read, which may be anywhere if the image is rotated.
+    pub corners: [(f64, f64); 4],
+
+    /// The symbol is mirrored, as if seen from behind.
+    pub mirrored: bool,
+}
+
+/// Locate a QR code in a grayscale image and sample its modules.
+///
+/// The version is estimated from the distance between the finder patterns,
+/// use `read` to also decode the code, which corrects a wrong estimate.
+pub fn detect(pixels: &[u8], width: usize, height: usize) -> Result<Matrix, DetectError> {
+    let bitmap = binarize(pixels, width, height)?;
+    let finders = find_finders(&bitmap);
+    finder_triples(&finders).into_iter()
+        .filter_map(|[a, b, c]| Location::new(&bitmap, finders[a], finders[b], finders[c]))
+        .find_map(|location| sample(&bitmap, &location, location.version))
+        .ok_or(DetectError::NotFound)
+}
+
+/// Locate and decode a QR code in a grayscale image, one byte per pixel
+/// row by row.
+pub fn read(pixels: &[u8], width: usize, height: usize) -> Result<Decoded, DetectError> {
+    let bitmap = binarize(pixels, width, height)?;
+    let finders = find_finders(&bitmap);
+    let mut res = Err(DetectError::NotFound);
+    for [a, b, c] in finder_triples(&finders) {
+        let location = match Location::new(&bitmap, finders[a], finders[b], finders[c]) {
+            Some(location) => location,
+            None => continue,
+        };
+        match decode_location(&bitmap, &location) {
+            Ok(found) => return Ok(found.decoded),
+            Err(e) => {
+                if res == Err(DetectError::NotFound) {
+                    res = Err(DetectError::Decode(e));
+                }
+            }
+        }
+    }
+    res
+}
+
+/// Locate and decode all QR codes in a grayscale image, one byte per pixel
+/// row by row.
+///
+/// Each finder pattern belongs to at most one code, the combinations which
+/// fits a QR code best are tried first. Codes which can't be decoded are skipped.
+pub fn read_all(pixels: &[u8], width: usize, height: usize) -> Result<Vec<Found>, DetectError> {
+    let bitmap = binarize(pixels, width, height)?;
+    let finders = find_finders(&bitmap);
+    let mut used = vec![false; finders.len()];
+    let mut res = Vec::new();
+    for triple in finder_triples(&finders) {
+        if triple.iter().any(|i| used[*i]) {
+            continue;
+        }
+        let [a, b, c] = triple;
+        let location = match Location::new(&bitmap, finders[a], finders[b], finders[c]) {
+            Some(location) => location,
+            None => continue,
+        };
+        if let Ok(found) = decode_location(&bitmap, &location) {
+            for i in &triple {
+                used[*i] = true;
+            }
+            res.push(found);
+        }
+    }
+    Ok(res)
+}
+
+// Decodes at the estimated version, and the versions next to it
+// if the estimate was slightly off. Mirrored codes are read
+// with the top right and bottom left finders swapped.
+fn decode_location(bitmap: &Bitmap, location: &Location) -> Result<Found, DecodeError> {
+    let v = location.version.0;
+    let mut res = Err(DecodeError::InvalidSize);
+    for &mirrored in &[false, true] {
+        let location = if mirrored { location.mirror() } else { location.clone() };
+        for candidate in &[v, v + 1, v - 1] {
+            if !(1..=40).contains(candidate) {
+                continue;
+            }
+            let version = Version::new(*candidate);
+            let matrix = match sample(bitmap, &location, version) {
+                Some(matrix) => matrix,
+                None => continue,
+            };
+            match decode::decode(&matrix) {
+                Ok(decoded) => {
+                    let transform = location.transform(version).unwrap();
+                    let size = version.size() as f64;
+                    let corner = |x, y| {
+                        let p = transform.apply(Point::new(x, y));
+                        (p.x, p.y)
+                    };
+                    return Ok(Found {
+                        decoded,
+                        corners: [corner(0.0, 0.0), corner(size, 0.0),
+                                  corner(size, size), corner(0.0, size)],
+                        mirrored,
+                    });
+                }
+                Err(e) => {
+                    if *candidate == v && !mirrored {
+                        res = Err(e);
+                    }
+                }
+            }
+        }
+    }
+    res
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+impl Point {
+    fn new(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    fn dist(&self, p: Point) -> f64 {
+        (self.x - p.x).hypot(self.y - p.y)
+    }
+}
+
+// A finder pattern candidate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Finder {
+    center: Point,
+    // Estimated module size in pixels.
+    module: f64,
+    // Number of scans which found the pattern.
+    count: usize,
+}
+
+// Scans each row for the 1:1:3:1:1 pattern, verified by cross checks
+// through the center of the pattern.
+fn find_finders(bitmap: &Bitmap) -> Vec<Finder> {
+    let mut res: Vec<Finder> = Vec::new();
+    for y in 0..bitmap.height {
+        let runs = row_runs(bitmap, y);
+        for w in runs.windows(5) {
+            if !w[0].0 {
+                continue;
+            }
+            let counts = [w[0].2, w[1].2, w[2].2, w[3].2, w[4].2];
+            if !is_finder_ratio(&counts, 0.5) {
+                continue;
+            }
+            let x = w[2].1 as f64 + w[2].2 as f64 / 2.0;
+            if let Some(f) = cross_check_finder(bitmap, x, y as f64 + 0.5, &counts) {
+                match res.iter_mut().find(|c| c.center.dist(f.center) <= c.module.max(f.module) &&
+                                              (c.module - f.module).abs() <= c.module.max(1.0)) {
+                    Some(c) => {
+                        let n = c.count as f64;
+                        c.center = Point::new((c.center.x * n + f.center.x) / (n + 1.0),
+                                              (c.center.y * n + f.center.y) / (n + 1.0));
+                        c.module = (c.module * n + f.module) / (n + 1.0);
+                        c.count += 1;
+                    }
+                    None => res.push(f),
+                }
+            }
+        }
+    }
+    // A single hit is likely noise.
+    res.retain(|f| f.count >= 2);
+    res.sort_by_key(|f| cmp::Reverse(f.count));
+    res
+}
+
+// Runs of same colored pixels in a row, as (dark, start, length).
+fn row_runs(bitmap: &Bitmap, y: usize) -> Vec<(bool, usize, usize)> {
+    let mut res = Vec::new();
+    let mut start = 0;
+    for x in 1..=bitmap.width {
+        if x == bitmap.width || bitmap.is_dark(x, y) != bitmap.is_dark(start, y) {
+            res.push((bitmap.is_dark(start, y), start, x - start));
+            start = x;
+        }
+    }
+    res
+}
+
+// Each module in the pattern may deviate by `variance` of the module size.
+fn is_finder_ratio(counts: &[usize; 5], variance: f64) -> bool {
+    let total: usize = counts.iter().sum();
+    if total < 7 || counts.contains(&0) {
+        return false;
+    }
+    let module = total as f64 / 7.0;
+    let max = module * variance;
+    counts.iter().zip(&[1.0, 1.0, 3.0, 1.0, 1.0])
+        .all(|(c, r)| (module * r - *c as f64).abs() < max * r)
+}
+
+fn cross_check_finder(bitmap: &Bitmap, x: f64, y: f64, counts: &[usize; 5]) -> Option<Finder> {
+    let total: usize = counts.iter().sum();
+    let max = counts[2];
+
+    let (y, vertical) = cross_check(bitmap, Point::new(x, y), (0, 1), max)?;
+    let vertical_total: usize = vertical.iter().sum();
+    if 5 * (vertical_total as isize - total as isize).unsigned_abs() >= 2 * total ||
+       !is_finder_ratio(&vertical, 0.5) {
+        return None;
+    }
+
+    let (x, horizontal) = cross_check(bitmap, Point::new(x, y.y), (1, 0), vertical[2])?;
+    let horizontal_total: usize = horizontal.iter().sum();
+    if 5 * (horizontal_total as isize - total as isize).unsigned_abs() >= 2 * total ||
+       !is_finder_ratio(&horizontal, 0.5) {
+        return None;
+    }
+
+    // A diagonal rules out patterns which are only lines. Either will do,
+    // a single damaged module shouldn't hide the finder.
+    let max = cmp::max(vertical[2], horizontal[2]);
+    let diagonal = [(1, 1), (1, -1)].iter().any(|dir| {
+        cross_check(bitmap, x, *dir, max)
+            .is_some_and(|(_, counts)| is_finder_ratio(&counts, 0.75))
+    });
+    if !diagonal {
+        return None;
+    }
+
+    Some(Finder {
+        center: x,
+        module: (vertical_total + horizontal_total) as f64 / 14.0,
+        count: 1,
+    })
+}
+
+// Counts the dark, light, dark runs in both directions from a dark center.
+// Returns the center of the middle run and the run lengths,
+// the outer runs may not be longer than `max`.
+fn cross_check(bitmap: &Bitmap, p: Point, (dx, dy): (isize, isize),
+               max: usize) -> Option<(Point, [usize; 5])> {
+    let x = p.x as isize;
+    let y = p.y as isize;
+    if !bitmap.is_dark_at(x, y) {
+        return None;
+    }
+    let walk = |sign: isize| -> Option<(usize, usize, usize)> {
+        let mut runs = [0usize; 3];
+        let mut i = 1;
+        for (run, run_count) in runs.iter_mut().enumerate() {
+            let dark = run != 1;
+            while bitmap.is_dark_at(x + sign * i * dx, y + sign * i * dy) == dark {
+                *run_count += 1;
+                i += 1;
+                if run > 0 && *run_count > max {
+                    return None;
+                }
+                if !in_bounds(bitmap, x + sign * i * dx, y + sign * i * dy) {
+                    break;
+                }
+            }
+            if run > 0 && *run_count == 0 {
+                return None;
+            }
+        }
+        Some((runs[0], runs[1], runs[2]))
+    };
+    let (c_neg, l_neg, d_neg) = walk(-1)?;
+    let (c_pos, l_pos, d_pos) = walk(1)?;
+
+    // Middle of the center run, in pixel coordinates.
+    let offset = (c_pos as f64 - c_neg as f64) / 2.0;
+    let center = Point::new(if dx == 0 { p.x } else { x as f64 + 0.5 + offset * dx as f64 },
+                            if dy == 0 { p.y } else { y as f64 + 0.5 + offset * dy as f64 });
+    Some((center, [d_neg, l_neg, c_neg + c_pos + 1, l_pos, d_pos]))
+}
+
+fn in_bounds(bitmap: &Bitmap, x: isize, y: isize) -> bool {
+    x >= 0 && y >= 0 && (x as usize) < bitmap.width && (y as usize) < bitmap.height
+}
+
+// Indices of three finders ordered by how well they fit
+// the corners of a QR code, best first.
+fn finder_triples(finders: &[Finder]) -> Vec<[usize; 3]> {
+    let n = cmp::min(finders.len(), MAX_FINDERS);
+    let mut res = Vec::new();
+    for i in 0..n {
+        for j in (i + 1)..n {
+            for k in (j + 1)..n {
+                if let Some(score) = triple_score(finders[i], finders[j], finders[k]) {
+                    res.push((score, [i, j, k]));
+                }
+            }
+        }
+    }
+    res.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    res.into_iter().map(|(_, t)| t).collect()
+}
+
+// Lower is better. The finders should have about the same size and form
+// an isosceles right triangle, None if they're too far off.
+fn triple_score(a: Finder, b: Finder, c: Finder) -> Option<f64> {
+    let min_module = a.module.min(b.module).min(c.module);
+    let max_module = a.module.max(b.module).max(c.module);
+    if max_module > 1.5 * min_module {
+        return None;
+    }
+    let mut sides = [a.center.dist(b.center), b.center.dist(c.center), c.center.dist(a.center)];
+    sides.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    // The finders are at least 14 modules apart.
+    if sides[0] < 10.0 * min_module {
+        return None;
+    }
+    let score = (1.0 - sides[0] / sides[1]) +
+        (sides[2] / sides[0].hypot(sides[1]) - 1.0).abs() +
+        (1.0 - min_module / max_module);
+    if score > 0.5 {
+        return None;
+    }
+    Some(score)
+}
+
+// Where a QR code is located in the image.
+#[derive(Debug, Clone, PartialEq)]
+struct Location {
+    top_left: Point,
+    top_right: Point,
+    bottom_left: Point,
+    // The center of the bottom right alignment pattern, or the
+    // bottom right corner at the same offset as the finders if there's none.
+    bottom_right: Point,
+    alignment: bool,
+    version: Version,
+}
+
+impl Location {
+    fn new(bitmap: &Bitmap, a: Finder, b: Finder, c: Finder) -> Option<Location> {
+        // The top left finder is opposite of the longest side.
+        let (tl, mut tr, mut bl) = {
+            let ab = a.center.dist(b.center);
+            let bc = b.center.dist(c.center);
+            let ca = c.center.dist(a.center);
+            if bc >= ab && bc >= ca {
+                (a, b, c)
+            } else if ca >= ab && ca >= bc {
+                (b, c, a)
+            } else {
+                (c, a, b)
+            }
+        };
+        // With y pointing down the top right finder is clockwise from the bottom left.
+        let cross = (tr.center.x - tl.center.x) * (bl.center.y - tl.center.y) -
+                    (tr.center.y - tl.center.y) * (bl.center.x - tl.center.x);
+        if cross < 0.0 {
+            std::mem::swap(&mut tr, &mut bl);
+        }
+
+        let module = (tl.module + tr.module + bl.module) / 3.0;
+        let size = (tl.center.dist(tr.center) + tl.center.dist(bl.center)) / 2.0 / module + 7.0;
+        let v = ((size - 17.0) / 4.0).round();
+        if !(1.0..=40.0).contains(&v) {
+            return None;
+        }
+        let version = Version::new(v as usize);
+
+        let mut res = Location {
+            top_left: tl.center,
+            top_right: tr.center,
+            bottom_left: bl.center,
+            bottom_right: Point::new(tr.center.x + bl.center.x - tl.center.x,
+                                     tr.center.y + bl.center.y - tl.center.y),
+            alignment: false,
+            version,
+        };
+        if version.0 >= 2 {
+            // The alignment pattern is 3 modules closer to the center
+            // than the corner opposite of the top left finder.
+            let size = version.size() as f64;
+            let f = (size - 10.0) / (size - 7.0);
+            let estimate = Point::new(tl.center.x + f * (res.bottom_right.x - tl.center.x),
+                                      tl.center.y + f * (res.bottom_right.y - tl.center.y));
+            // Look further away if it's not close to the estimate.
+            let found = [4.0, 8.0, 16.0].iter()
+                .find_map(|radius| find_alignment(bitmap, estimate, module, radius * module));
+            if let Some(p) = found {
+                res.bottom_right = p;
+                res.alignment = true;
+            }
+        }
+        Some(res)
+    }
+
+    // Swaps the top right and bottom left finders, the code is then read
+    // as if seen from behind.
+    fn mirror(&self) -> Location {
+        Location {
+            top_right: self.bottom_left,
+            bottom_left: self.top_right,
+            ..self.clone()
+        }
+    }
+
+    // Maps module coordinates to the image.
+    fn transform(&self, version: Version) -> Option<Perspective> {
+        let size = version.size() as f64;
+        let corner = if self.alignment { size - 6.5 } else { size - 3.5 };
+        Perspective::new(
+            &[Point::new(3.5, 3.5), Point::new(size - 3.5, 3.5),
+              Point::new(3.5, size - 3.5), Point::new(corner, corner)],
+            &[self.top_left, self.top_right, self.bottom_left, self.bottom_right])
+    }
+}
+
+// Finds the light, dark, light pattern through the center of the
+// alignment pattern closest to the estimate.
+fn find_alignment(bitmap: &Bitmap, estimate: Point, module: f64, radius: f64) -> Option<Point> {
+    let radius = radius as isize;
+    let x0 = cmp::max(estimate.x as isize - radius, 0) as usize;
+    let x1 = cmp::min(estimate.x as isize + radius, bitmap.width as isize - 1) as usize;
+    let y0 = cmp::max(estimate.y as isize - radius, 0) as usize;
+    let y1 = cmp::min(estimate.y as isize + radius, bitmap.height as isize - 1) as usize;
+    if x0 >= x1 || y0 >= y1 {
+        return None;
+    }
+
+    let fits = |c: usize| (c as f64 - module).abs() < module / 2.0 + 0.5;
+    let mut res: Option<Point> = None;
+    for y in y0..=y1 {
+        let mut runs = Vec::new();
+        let mut start = x0;
+        for x in (x0 + 1)..=(x1 + 1) {
+            if x == x1 + 1 || bitmap.is_dark(x, y) != bitmap.is_dark(start, y) {
+                runs.push((bitmap.is_dark(start, y), start, x - start));
+                start = x;
+            }
+        }
+        for w in runs.windows(3) {
+            if w[0].0 || !fits(w[0].2) || !fits(w[1].2) || !fits(w[2].2) {
+                continue;
+            }
+            let x = w[1].1 as f64 + w[1].2 as f64 / 2.0;
+            let p = match cross_check_alignment(bitmap, Point::new(x, y as f64 + 0.5), module) {
+                Some(p) => p,
+                None => continue,
+            };
+            if res.is_none_or(|r| p.dist(estimate) < r.dist(estimate)) {
+                res = Some(p);
+            }
+        }
+    }
+    res
+}
+
+// Checks the light ring around the dark center vertically.
+fn cross_check_alignment(bitmap: &Bitmap, p: Point, module: f64) -> Option<Point> {
+    let x = p.x as isize;
+    let y = p.y as isize;
+    let max = (2.0 * module).ceil() as usize;
+    let walk = |sign: isize| -> Option<(usize, usize)> {
+        let mut dark = 0;
+        let mut i = 1;
+        while bitmap.is_dark_at(x, y + sign * i) {
+            dark += 1;
+            i += 1;
+            if dark > max {
+                return None;
+            }
+        }
+        let mut light = 0;
+        while in_bounds(bitmap, x, y + sign * i) && !bitmap.is_dark_at(x, y + sign * i) {
+            light += 1;
+            i += 1;
+            if light > max {
+                return None;
+            }
+        }
+        if light == 0 || !in_bounds(bitmap, x, y + sign * i) {
+            return None;
+        }
+        Some((dark, light))
+    };
+    let (d_neg, l_neg) = walk(-1)?;
+    let (d_pos, l_pos) = walk(1)?;
+    let fits = |c: usize| (c as f64 - module).abs() < module / 2.0 + 0.5;
+    if !fits(d_neg + d_pos + 1) || !fits(l_neg) || !fits(l_pos) {
+        return None;
+    }
+    Some(Point::new(p.x, y as f64 + 0.5 + (d_pos as f64 - d_neg as f64) / 2.0))
+}
+
+// Samples the center of each module.
+fn sample(bitmap: &Bitmap, location: &Location, version: Version) -> Option<Matrix> {
+    let transform = location.transform(version)?;
+    let mut builder = QrBuilder::new().version(version);
+    builder.add_fun_patterns();
+    let template = builder.matrix;
+
+    let size = version.size();
+    let mut res = Matrix::new(size);
+    for y in 0..size {
+        for x in 0..size {
+            let p = transform.apply(Point::new(x as f64 + 0.5, y as f64 + 0.5));
+            // Allow a pixel of rounding outside of the image.
+            if p.x < -1.0 || p.y < -1.0 ||
+               p.x > bitmap.width as f64 + 1.0 || p.y > bitmap.height as f64 + 1.0 {
+                return None;
+            }
+            let px = (p.x.max(0.0) as usize).min(bitmap.width - 1);
+            let py = (p.y.max(0.0) as usize).min(bitmap.height - 1);
+            let dark = bitmap.is_dark(px, py);
+            if template.is_fun(x, y) {
+                res.set(x, y, Module::Function(dark));
+            } else {
+                res.set(x, y, Module::Data(dark));
+            }
+        }
+    }
+    Some(res)
+}
+
+// A perspective transform, maps a quadrilateral onto another.
+#[derive(Debug, Clone, PartialEq)]
+struct Perspective([f64; 8]);
+
+impl Perspective {
+    // Solves for the transform mapping four points onto four others.
+    // None if three of the points are on a line.
+    fn new(from: &[Point; 4], to: &[Point; 4]) -> Option<Perspective> {
+        // x' = (a u + b v + c) / (g u + h v + 1)
+        // y' = (d u + e v + f) / (g u + h v + 1)
+        let mut m = [[0.0f64; 9]; 8];
+        for i in 0..4 {
+            let (u, v) = (from[i].x, from[i].y);
+            let (x, y) = (to[i].x, to[i].y);
+            m[2 * i] = [u, v, 1.0, 0.0, 0.0, 0.0, -u * x, -v * x, x];
+            m[2 * i + 1] = [0.0, 0.0, 0.0, u, v, 1.0, -u * y, -v * y, y];
+        }
+        // Gaussian elimination with partial pivoting.
+        for col in 0..8 {
+            let pivot = (col..8)
+                .max_by(|a, b| m[*a][col].abs().partial_cmp(&m[*b][col].abs()).unwrap())
+                .unwrap();
+            if m[pivot][col].abs() < 1e-9 {
+                return None;
+            }
+            m.swap(col, pivot);
+            for row in 0..8 {
+                if row != col {
+                    let f = m[row][col] / m[col][col];
+                    let pivot_row = m[col];
+                    for (a, b) in m[row].iter_mut().zip(pivot_row.iter()).skip(col) {
+                        *a -= f * b;
+                    }
+                }
+            }
+        }
+        let mut res = [0.0; 8];
+        for (i, r) in res.iter_mut().enumerate() {
+            *r = m[i][8] / m[i][i];
+        }
+        Some(Perspective(res))
+    }
+
+    fn apply(&self, p: Point) -> Point {
+        let t = &self.0;
+        let w = t[6] * p.x + t[7] * p.y + 1.0;
+        Point::new((t[0] * p.x + t[1] * p.y + t[2]) / w,
+                   (t[3] * p.x + t[4] * p.y + t[5]) / w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ec::ECLevel;
+    use crate::matrix::Module;
+    use crate::qr::Qr;
+
+    // Renders a matrix with a 4 module quiet zone, the module coordinates
+    // of each pixel are given by `f`. Light modules gets brighter to the right.
+    fn render<F>(matrix: &Matrix, width: usize, height: usize, f: F) -> Vec<u8>
+        where F: Fn(f64, f64) -> (f64, f64)
+    {
+        let size = matrix.width as f64;
+        let mut res = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (u, v) = f(x as f64 + 0.5, y as f64 + 0.5);
+                let (u, v) = (u - 4.0, v - 4.0);
+                let dark = u >= 0.0 && v >= 0.0 && u < size && v < size &&
+                    matrix.is_dark(u as usize, v as usize);
+                let light = 80 + (170 * x / width) as u8;
+                res.push(if dark { light - 70 } else { light });
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn binarize_gradient() {
+        // Stripes on a background going from dark to light.
+        let (w, h) = (64, 48);
+        let pixels: Vec<u8> = (0..h).flat_map(|_| (0..w).map(|x| {
+            let light = 100 + x as u8;
+            if (x / 4) % 2 == 0 { light - 60 } else { light }
+        })).collect();
+        let bitmap = binarize(&pixels, w, h).unwrap();
+        for x in 0..w {
+            assert_eq!(bitmap.is_dark(x, 20), (x / 4) % 2 == 0, "x = {}", x);
+        }
+        assert_eq!(binarize(&pixels, w, h + 1), Err(DetectError::InvalidImage));
+    }
+
+    #[test]
+    fn axis_aligned() {
+        let qr = Qr::new("HELLO WORLD").unwrap();
+        let size = (qr.size() + 8) * 4;
+        let pixels = render(&qr.matrix, size, size, |x, y| (x / 4.0, y / 4.0));
+        let matrix = detect(&pixels, size, size).unwrap();
+        assert_eq!(matrix.width, qr.size());
+        assert!((0..qr.size()).all(|y| (0..qr.size())
+            .all(|x| matrix.is_dark(x, y) == qr.matrix.is_dark(x, y))));
+        assert_eq!(read(&pixels, size, size).unwrap().text.unwrap(), "HELLO WORLD");
+    }
+
+    #[test]
+    fn damaged_diagonal() {
+        // A dark module in the light ring on one diagonal of each finder,
+        // the other diagonal still rules out lines.
+        let qr = Qr::new("HELLO WORLD").unwrap();
+        let mut matrix = qr.matrix.clone();
+        let n = matrix.width - 7;
+        for &(x, y) in &[(1, 1), (n + 1, 1), (1, n + 1)] {
+            matrix.set(x, y, Module::Function(true));
+        }
+        let size = (qr.size() + 8) * 4;
+        let pixels = render(&matrix, size, size, |x, y| (x / 4.0, y / 4.0));
+
+        // The top left finder is centered at module 3 after the quiet zone.
+        let bitmap = binarize(&pixels, size, size).unwrap();
+        let center = Point::new(30.0, 30.0);
+        assert!(cross_check(&bitmap, center, (1, 1), 12)
+            .is_none_or(|(_, counts)| !is_finder_ratio(&counts, 0.75)));
+        assert!(cross_check(&bitmap, center, (1, -1), 12)
+            .is_some_and(|(_, counts)| is_finder_ratio(&counts, 0.75)));
+        assert_eq!(read(&pixels, size, size).unwrap().text.unwrap(), "HELLO WORLD");
+    }
+
+    #[test]
+    fn rotated() {
+        let s = "https://github.com/treeman/rqr";
+        let qr = Qr::with_ecl(s, ECLevel::Q).unwrap();
+        let modules = (qr.size() + 8) as f64;
+        let scale = 5.0;
+        let size = (modules * scale * 1.5) as usize;
+        let c = size as f64 / 2.0;
+        for &degrees in &[30.0f64, 90.0, 200.0] {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            let pixels = render(&qr.matrix, size, size, |x, y| {
+                let (x, y) = (x - c, y - c);
+                ((cos * x + sin * y) / scale + modules / 2.0,
+                 (-sin * x + cos * y) / scale + modules / 2.0)
+            });
+            let decoded = read(&pixels, size, size).unwrap();
+            assert_eq!(decoded.text.unwrap(), s, "{} degrees", degrees);
+        }
+    }
+
+    #[test]
+    fn perspective() {
+        let s = "Tilted away from the camera";
+        let qr = Qr::with_version(s, Version::new(4)).unwrap();
+        let modules = (qr.size() + 8) as f64;
+        let (w, h) = (300, 260);
+        // The bottom edge is wider than the top edge.
+        let transform = Perspective::new(
+            &[Point::new(50.0, 25.0), Point::new(245.0, 30.0),
+              Point::new(25.0, 240.0), Point::new(280.0, 235.0)],
+            &[Point::new(0.0, 0.0), Point::new(modules, 0.0),
+              Point::new(0.0, modules), Point::new(modules, modules)]).unwrap();
+        let pixels = render(&qr.matrix, w, h, |x, y| {
+            let p = transform.apply(Point::new(x, y));
+            (p.x, p.y)
+        });
+        let decoded = read(&pixels, w, h).unwrap();
+        assert_eq!(decoded.version, Version::new(4));
+        assert_eq!(decoded.text.unwrap(), s);
+    }
+
+    #[test]
+    fn mirrored() {
+        let qr = Qr::new("Seen from behind").unwrap();
+        let size = (qr.size() + 8) * 4;
+        // Swapping the axes mirrors the code along the diagonal.
+        let pixels = render(&qr.matrix, size, size, |x, y| (y / 4.0, x / 4.0));
+        let found = read_all(&pixels, size, size).unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(found[0].mirrored);
+        assert_eq!(found[0].decoded.text.as_deref(), Some("Seen from behind"));
+    }
+
+    #[test]
+    fn multiple() {
+        let texts = ["Parcel 1 of 3", "Parcel 2 of 3", "Parcel 3 of 3"];
+        let qrs: Vec<Qr> = texts.iter().map(|s| Qr::new(s).unwrap()).collect();
+        let modules = (qrs[0].size() + 8) as f64;
+        let (w, h) = (480, 200);
+        // Side by side, the second upside down and the third mirrored.
+        let images = [
+            render(&qrs[0].matrix, w, h, |x, y| ((x - 10.0) / 4.0, (y - 20.0) / 4.0)),
+            render(&qrs[1].matrix, w, h, |x, y| (modules - (x - 170.0) / 4.0,
+                                                  modules - (y - 30.0) / 4.0)),
+            render(&qrs[2].matrix, w, h, |x, y| ((y - 40.0) / 4.0, (x - 330.0) / 4.0)),
+        ];
+        // Each image holds a single code on the same background.
+        let pixels: Vec<u8> = (0..(w * h))
+            .map(|i| images.iter().map(|img| img[i]).min().unwrap())
+            .collect();
+
+        let mut found = read_all(&pixels, w, h).unwrap();
+        assert_eq!(found.len(), 3);
+        found.sort_by(|a, b| a.corners[0].0.partial_cmp(&b.corners[0].0).unwrap());
+        for (f, s) in found.iter().zip(texts.iter()) {
+            assert_eq!(f.decoded.text.as_deref(), Some(*s));
+        }
+        assert_eq!(found.iter().map(|f| f.mirrored).collect::<Vec<_>>(),
+                   vec![false, false, true]);
+
+        // The top left corner of the first code is inside the quiet zone.
+        let (x, y) = found[0].corners[0];
+        assert!((x - 26.0).abs() < 2.0 && (y - 36.0).abs() < 2.0, "{:?}", found[0].corners);
+        // The second code is upside down, its top left corner is at the bottom right.
+        let (x, y) = found[1].corners[0];
+        let far = 4.0 * modules - 16.0;
+        assert!((x - (170.0 + far)).abs() < 2.0 && (y - (30.0 + far)).abs() < 2.0,
+                "{:?}", found[1].corners);
+    }
+
+    #[test]
+    fn not_found() {
+        let pixels = vec![200; 100 * 80];
+        assert_eq!(read(&pixels, 100, 80), Err(DetectError::NotFound));
+        assert_eq!(detect(&pixels, 100, 80), Err(DetectError::NotFound));
+        assert_eq!(read(&pixels, 100, 81), Err(DetectError::InvalidImage));
+        assert_eq!(read_all(&pixels, 100, 80), Ok(Vec::new()));
+    }
+}
+
+// Size of the blocks used for the local threshold.
+const BLOCK_SIZE: usize = 8;
+
+// Blocks with less contrast are considered to be a single color.
+const MIN_CONTRAST: u8 = 24;
+
+// Only the finders with the most hits are combined into codes.
+const MAX_FINDERS: usize = 24;
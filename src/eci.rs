@@ -0,0 +1,256 @@
+//! Extended Channel Interpretation, specifies the character set of byte data.
+
+use crate::data;
+use crate::mode;
+
+use bitvec::*;
+use encoding_rs::*;
+
+/// An ECI designator, must be inside [0, 999999] inclusive.
+///
+/// Byte mode data is interpreted as ISO-8859-1 by default, an ECI header
+/// tells the reader to use another character set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Eci(pub u32);
+
+impl Eci {
+    /// ISO-8859-1, the default interpretation.
+    pub const ISO_8859_1: Eci = Eci(3);
+    /// ISO-8859-2, Central European.
+    pub const ISO_8859_2: Eci = Eci(4);
+    /// ISO-8859-3, South European.
+    pub const ISO_8859_3: Eci = Eci(5);
+    /// ISO-8859-4, North European.
+    pub const ISO_8859_4: Eci = Eci(6);
+    /// ISO-8859-5, Cyrillic.
+    pub const ISO_8859_5: Eci = Eci(7);
+    /// ISO-8859-6, Arabic.
+    pub const ISO_8859_6: Eci = Eci(8);
+    /// ISO-8859-7, Greek.
+    pub const ISO_8859_7: Eci = Eci(9);
+    /// ISO-8859-8, Hebrew.
+    pub const ISO_8859_8: Eci = Eci(10);
+    /// ISO-8859-10, Nordic.
+    pub const ISO_8859_10: Eci = Eci(12);
+    /// ISO-8859-13, Baltic.
+    pub const ISO_8859_13: Eci = Eci(15);
+    /// ISO-8859-14, Celtic.
+    pub const ISO_8859_14: Eci = Eci(16);
+    /// ISO-8859-15, Western European with the euro sign.
+    pub const ISO_8859_15: Eci = Eci(17);
+    /// ISO-8859-16, South-Eastern European.
+    pub const ISO_8859_16: Eci = Eci(18);
+    /// Shift JIS.
+    pub const SHIFT_JIS: Eci = Eci(20);
+    /// Windows-1250.
+    pub const WINDOWS_1250: Eci = Eci(21);
+    /// Windows-1251.
+    pub const WINDOWS_1251: Eci = Eci(22);
+    /// Windows-1252.
+    pub const WINDOWS_1252: Eci = Eci(23);
+    /// Windows-1256.
+    pub const WINDOWS_1256: Eci = Eci(24);
+    /// UTF-16, big endian.
+    pub const UTF16_BE: Eci = Eci(25);
+    /// UTF-8.
+    pub const UTF8: Eci = Eci(26);
+    /// US-ASCII.
+    pub const ASCII: Eci = Eci(27);
+    /// Big5.
+    pub const BIG5: Eci = Eci(28);
+    /// GB 2312.
+    pub const GB2312: Eci = Eci(29);
+    /// EUC-KR.
+    pub const EUC_KR: Eci = Eci(30);
+
+    /// Create a new ECI designator.
+    /// Fails if larger than 999999.
+    pub fn new(v: u32) -> Eci {
+        assert!(v <= 999999);
+        Eci(v)
+    }
+
+    /// Returns the ECI needed to encode a string in byte mode.
+    /// Strings in ISO-8859-1 don't need one, others are encoded as UTF-8.
+    pub fn for_str(s: &str) -> Option<Eci> {
+        if mode::to_latin1(s).is_some() {
+            None
+        } else {
+            Some(Eci::UTF8)
+        }
+    }
+
+    /// Convert a string to bytes in the character set.
+    /// Returns None if the character set isn't supported or if there are
+    /// characters which can't be represented.
+    pub fn encode(&self, s: &str) -> Option<Vec<u8>> {
+        match *self {
+            Eci::ISO_8859_1 => mode::to_latin1(s),
+            Eci::UTF8 => Some(s.bytes().collect()),
+            Eci::UTF16_BE => Some(s.encode_utf16().flat_map(|c| c.to_be_bytes()).collect()),
+            Eci::ASCII if s.is_ascii() => Some(s.bytes().collect()),
+            Eci::ASCII => None,
+            _ => {
+                let (bytes, _, had_errors) = self.encoding()?.encode(s);
+                // GBK is a superset of GB 2312, the extra characters can't be used.
+                if had_errors || (*self == Eci::GB2312 && !is_gb2312(&bytes)) {
+                    None
+                } else {
+                    Some(bytes.into_owned())
+                }
+            }
+        }
+    }
+
+    /// Convert bytes in the character set to a string.
+    /// Returns None if the character set isn't supported or if the bytes
+    /// are malformed. GB 2312 is decoded as its superset GBK.
+    pub fn decode(&self, v: &[u8]) -> Option<String> {
+        match *self {
+            Eci::ISO_8859_1 => Some(v.iter().map(|b| *b as char).collect()),
+            Eci::UTF8 => String::from_utf8(v.to_vec()).ok(),
+            Eci::UTF16_BE if v.len().is_multiple_of(2) => {
+                let units: Vec<u16> = v.chunks(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect();
+                String::from_utf16(&units).ok()
+            }
+            Eci::UTF16_BE => None,
+            Eci::ASCII if v.is_ascii() => Some(v.iter().map(|b| *b as char).collect()),
+            Eci::ASCII => None,
+            _ => {
+                self.encoding()?
+                    .decode_without_bom_handling_and_without_replacement(v)
+                    .map(|x| x.into_owned())
+            }
+        }
+    }
+
+    /// BitVec representation of the designator.
+    /// Does not include the mode indicator.
+    pub fn to_bitvec(&self) -> BitVec {
+        let mut bv = BitVec::new();
+        // The designator is 1, 2 or 3 bytes long and the leading bits
+        // tells us how many bytes there are.
+        if self.0 < (1 << 7) {
+            data::append(&mut bv, self.0, 8);
+        } else if self.0 < (1 << 14) {
+            data::append(&mut bv, 0b10, 2);
+            data::append(&mut bv, self.0, 14);
+        } else {
+            data::append(&mut bv, 0b110, 3);
+            data::append(&mut bv, self.0, 21);
+        }
+        bv
+    }
+
+    // The designator of a BitVec from `to_bitvec`.
+    pub(crate) fn from_bitvec(bv: &BitVec) -> Eci {
+        // Skip the leading bits telling the length.
+        let skip = match bv.len() {
+            8 => 1,
+            16 => 2,
+            _ => 3,
+        };
+        Eci(bv.iter().skip(skip).fold(0, |acc, x| (acc << 1) | x as u32))
+    }
+
+    // Character sets handled by encoding_rs.
+    // ISO-8859-9 and ISO-8859-11 are missing as they're aliased to windows
+    // code pages which differs from the ISO standards.
+    fn encoding(&self) -> Option<&'static Encoding> {
+        match *self {
+            Eci::ISO_8859_2 => Some(ISO_8859_2),
+            Eci::ISO_8859_3 => Some(ISO_8859_3),
+            Eci::ISO_8859_4 => Some(ISO_8859_4),
+            Eci::ISO_8859_5 => Some(ISO_8859_5),
+            Eci::ISO_8859_6 => Some(ISO_8859_6),
+            Eci::ISO_8859_7 => Some(ISO_8859_7),
+            Eci::ISO_8859_8 => Some(ISO_8859_8),
+            Eci::ISO_8859_10 => Some(ISO_8859_10),
+            Eci::ISO_8859_13 => Some(ISO_8859_13),
+            Eci::ISO_8859_14 => Some(ISO_8859_14),
+            Eci::ISO_8859_15 => Some(ISO_8859_15),
+            Eci::ISO_8859_16 => Some(ISO_8859_16),
+            Eci::SHIFT_JIS => Some(SHIFT_JIS),
+            Eci::WINDOWS_1250 => Some(WINDOWS_1250),
+            Eci::WINDOWS_1251 => Some(WINDOWS_1251),
+            Eci::WINDOWS_1252 => Some(WINDOWS_1252),
+            Eci::WINDOWS_1256 => Some(WINDOWS_1256),
+            Eci::BIG5 => Some(BIG5),
+            Eci::GB2312 => Some(GBK),
+            Eci::EUC_KR => Some(EUC_KR),
+            _ => None,
+        }
+    }
+}
+
+// Is GBK data within GB 2312, ASCII or two bytes in the A1 to FE range
+// with the lead byte at most F7?
+fn is_gb2312(v: &[u8]) -> bool {
+    let mut it = v.iter();
+    while let Some(b) = it.next() {
+        if *b >= 0x80 {
+            match it.next() {
+                Some(t) if (0xA1..=0xF7).contains(b) && (0xA1..=0xFE).contains(t) => {}
+                _ => return false,
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn designator() {
+        assert_eq!(Eci::UTF8.to_bitvec(), bitvec![0, 0, 0, 1, 1, 0, 1, 0]);
+        assert_eq!(Eci::new(1000).to_bitvec().len(), 16);
+        assert_eq!(Eci::new(1000).to_bitvec()[..2], bitvec![1, 0][..]);
+        assert_eq!(Eci::new(999999).to_bitvec().len(), 24);
+        assert_eq!(Eci::new(999999).to_bitvec()[..3], bitvec![1, 1, 0][..]);
+    }
+
+    #[test]
+    fn from_bitvec() {
+        for v in [0, 26, 127, 128, 1000, 16383, 16384, 999999].iter() {
+            assert_eq!(Eci::from_bitvec(&Eci::new(*v).to_bitvec()), Eci::new(*v));
+        }
+    }
+
+    #[test]
+    fn for_str() {
+        assert_eq!(Eci::for_str("HELLO"), None);
+        assert_eq!(Eci::for_str("café"), None);
+        assert_eq!(Eci::for_str("☃"), Some(Eci::UTF8));
+    }
+
+    #[test]
+    fn encode() {
+        assert_eq!(Eci::ISO_8859_1.encode("café"), Some(vec![b'c', b'a', b'f', 0xE9]));
+        assert_eq!(Eci::ISO_8859_1.encode("☃"), None);
+        assert_eq!(Eci::UTF8.encode("é"), Some(vec![0xC3, 0xA9]));
+        assert_eq!(Eci::UTF16_BE.encode("é"), Some(vec![0x00, 0xE9]));
+        assert_eq!(Eci::ISO_8859_2.encode("ł"), Some(vec![0xB3]));
+        assert_eq!(Eci::ISO_8859_15.encode("€"), Some(vec![0xA4]));
+        assert_eq!(Eci::ASCII.encode("é"), None);
+        assert_eq!(Eci::GB2312.encode("a中"), Some(vec![b'a', 0xD6, 0xD0]));
+        // Only in GBK.
+        assert_eq!(Eci::GB2312.encode("€"), None);
+        assert_eq!(Eci::GB2312.encode("丂"), None);
+        // Unsupported character set.
+        assert_eq!(Eci::new(170).encode("a"), None);
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(Eci::ISO_8859_1.decode(&[b'c', b'a', b'f', 0xE9]), Some("café".to_string()));
+        assert_eq!(Eci::UTF8.decode(&[0xC3, 0xA9]), Some("é".to_string()));
+        assert_eq!(Eci::UTF8.decode(&[0xC3]), None);
+        assert_eq!(Eci::UTF16_BE.decode(&[0x00, 0xE9]), Some("é".to_string()));
+        assert_eq!(Eci::ISO_8859_15.decode(&[0xA4]), Some("€".to_string()));
+        assert_eq!(Eci::new(170).decode(b"a"), None);
+    }
+}
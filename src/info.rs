@@ -1,6 +1,6 @@
 //! Contains various QR specific encoding info.
 
-use crate::version::Version;
+use crate::version::{Version, MicroVersion, RmqrVersion};
 use crate::ec::ECLevel;
 use crate::data;
 use crate::mask::Mask;
@@ -34,6 +34,17 @@ pub fn block_ec_count(v: Version, ecl: ECLevel) -> usize {
     block_data(v, ecl).0
 }
 
+/// Returns error correction codewords per block reserved for misdecode
+/// protection, which readers following the specification don't correct with.
+pub fn misdecode_protection_count(v: Version, ecl: ECLevel) -> usize {
+    match (v.0, ecl) {
+        (1, ECLevel::L) => 3,
+        (1, ECLevel::M) | (2, ECLevel::L) => 2,
+        (1, _) | (3, ECLevel::L) => 1,
+        _ => 0,
+    }
+}
+
 /// Returns the format BitVec representation to be embedded.
 pub fn format_info(ecl: ECLevel, mask: Mask) -> BitVec {
     let x = FORMAT_INFO[mask.0][ecl as usize];
@@ -46,7 +57,6 @@ pub fn format_info(ecl: ECLevel, mask: Mask) -> BitVec {
 /// Valid for larger versions only.
 pub fn version_info(v: Version) -> Option<BitVec> {
     if v.extra_version_areas() {
-        println!("{}", v.0 - 7);
         let x = VERSION_INFO[v.0 - 7];
         let mut bv = BitVec::with_capacity(18);
         data::append(&mut bv, x, 18);
@@ -56,10 +66,140 @@ pub fn version_info(v: Version) -> Option<BitVec> {
     }
 }
 
-fn block_data(v: Version, ecl: ECLevel) -> (usize, usize, usize, usize, usize) {
+/// Finds the format info nearest to 15 bits read from a matrix.
+/// Returns the error correction, mask and the number of bits which differs,
+/// or None if more than 3 bits differs, the most the BCH code can correct.
+pub fn decode_format_info(bv: &BitVec) -> Option<(ECLevel, Mask, usize)> {
+    assert_eq!(bv.len(), 15);
+    let read = to_u32(bv);
+    [ECLevel::L, ECLevel::M, ECLevel::Q, ECLevel::H].iter()
+        .flat_map(|ecl| (0..8).map(move |m| (*ecl, Mask::new(m))))
+        .map(|(ecl, mask)| {
+            let dist = (FORMAT_INFO[mask.0][ecl as usize] as u32 ^ read).count_ones();
+            (ecl, mask, dist as usize)
+        })
+        .min_by_key(|(_, _, dist)| *dist)
+        .filter(|(_, _, dist)| *dist <= 3)
+}
+
+/// Finds the version info nearest to 18 bits read from a matrix.
+/// Returns the version and the number of bits which differs,
+/// or None if more than 3 bits differs, the most the BCH code can correct.
+pub fn decode_version_info(bv: &BitVec) -> Option<(Version, usize)> {
+    assert_eq!(bv.len(), 18);
+    let read = to_u32(bv);
+    VERSION_INFO.iter().enumerate()
+        .map(|(i, x)| (Version::new(i + 7), (x ^ read).count_ones() as usize))
+        .min_by_key(|(_, dist)| *dist)
+        .filter(|(_, dist)| *dist <= 3)
+}
+
+fn to_u32(bv: &BitVec) -> u32 {
+    bv.iter().fold(0, |acc, b| (acc << 1) | b as u32)
+}
+
+/// Returns the data codewords for a Micro QR version and error correction.
+/// The last data codeword is only 4 bits in M1 and M3.
+pub fn micro_data_codewords(v: MicroVersion, ecl: ECLevel) -> usize {
+    micro_block_data(v, ecl).0
+}
+
+/// Returns the total data bits possible for a Micro QR version and error correction.
+pub fn micro_data_bits(v: MicroVersion, ecl: ECLevel) -> usize {
+    let bits = 8 * micro_data_codewords(v, ecl);
+    if v.0 == 1 || v.0 == 3 {
+        bits - 4
+    } else {
+        bits
+    }
+}
+
+/// Returns the error correction codewords for a Micro QR version.
+/// Micro QR codes only have a single block.
+pub fn micro_ec_count(v: MicroVersion, ecl: ECLevel) -> usize {
+    micro_block_data(v, ecl).1
+}
+
+/// Returns the Micro QR format BitVec representation to be embedded.
+/// The mask is the Micro QR mask reference, 0 to 3.
+pub fn micro_format_info(v: MicroVersion, ecl: ECLevel, mask: Mask) -> BitVec {
+    // The symbol number identifies both version and error correction level.
+    let symbol = match (v.0, ecl) {
+        (1, _) => 0,
+        (2, ECLevel::L) => 1,
+        (2, _) => 2,
+        (3, ECLevel::L) => 3,
+        (3, _) => 4,
+        (4, ECLevel::L) => 5,
+        (4, ECLevel::M) => 6,
+        _ => 7,
+    };
+    let x = MICRO_FORMAT_INFO[symbol][mask.0];
+    let mut bv = BitVec::with_capacity(15);
+    data::append(&mut bv, x as u32, 15);
+    bv
+}
+
+/// Returns the total data codewords for a rMQR version and error correction.
+pub fn rmqr_total_codewords(v: RmqrVersion, ecl: ECLevel) -> usize {
+    let d = rmqr_block_data(v, ecl);
+    d.1 * d.2 + d.3 * d.4
+}
+
+/// Returns the total data bits possible for a rMQR version and error correction.
+pub fn rmqr_total_bits(v: RmqrVersion, ecl: ECLevel) -> usize {
+    8 * rmqr_total_codewords(v, ecl)
+}
+
+/// Returns a vector of codewords counts per block for a rMQR version.
+pub fn rmqr_group_block_count(v: RmqrVersion, ecl: ECLevel) -> Vec<usize> {
+    let data = rmqr_block_data(v, ecl);
+    let mut v = Vec::new();
+    v.extend((0..data.1).map(|_| data.2));
+    v.extend((0..data.3).map(|_| data.4));
+    v
+}
+
+/// Returns error correction codewords per block for a rMQR version.
+pub fn rmqr_block_ec_count(v: RmqrVersion, ecl: ECLevel) -> usize {
+    rmqr_block_data(v, ecl).0
+}
+
+/// Returns the rMQR format BitVec representations to be embedded,
+/// first the one next to the finder pattern and then the one next to the sub-finder.
+///
+/// The 6 data bits, error correction level and version indicator,
+/// are extended with a (18, 6) BCH code and masked differently for each side.
+pub fn rmqr_format_info(v: RmqrVersion, ecl: ECLevel) -> (BitVec, BitVec) {
+    let data = (if ecl == ECLevel::H { 1 << 5 } else { 0 }) | v.0 as u32;
+    let mut rem = data << 12;
+    for i in (12..18).rev() {
+        if rem & (1 << i) != 0 {
+            rem ^= 0x1F25 << (i - 12);
+        }
+    }
+    let x = (data << 12) | rem;
+    let mut finder = BitVec::with_capacity(18);
+    data::append(&mut finder, x ^ 0x1FAB2, 18);
+    let mut sub_finder = BitVec::with_capacity(18);
+    data::append(&mut sub_finder, x ^ 0x20A7B, 18);
+    (finder, sub_finder)
+}
+
+fn micro_block_data(v: MicroVersion, ecl: ECLevel) -> (usize, usize) {
+    assert!(v.supports_ecl(ecl), "Unsupported ECLevel {:?} for M{}", ecl, v.0);
+    MICRO_BLOCK_INFO[v.index()][ecl as usize]
+}
+
+fn block_data(v: Version, ecl: ECLevel) -> BlockInfo {
     BLOCK_INFO[v.index()][ecl as usize]
 }
 
+fn rmqr_block_data(v: RmqrVersion, ecl: ECLevel) -> BlockInfo {
+    assert!(RmqrVersion::supports_ecl(ecl), "Unsupported ECLevel {:?} for rMQR", ecl);
+    RMQR_BLOCK_INFO[v.0][if ecl == ECLevel::H { 1 } else { 0 }]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -83,6 +223,55 @@ mod tests {
                    Some(bitvec![0, 0, 0, 1, 1, 1, 1, 1, 0,
                                 0, 1, 0, 0, 1, 0, 1, 0, 0]));
     }
+
+    #[test]
+    fn decode_info() {
+        let mut bv = format_info(ECLevel::Q, Mask::new(5));
+        assert_eq!(decode_format_info(&bv), Some((ECLevel::Q, Mask::new(5), 0)));
+        for i in &[0, 7, 14] {
+            let b = bv[*i];
+            bv.set(*i, !b);
+        }
+        assert_eq!(decode_format_info(&bv), Some((ECLevel::Q, Mask::new(5), 3)));
+
+        let mut bv = version_info(Version::new(32)).unwrap();
+        assert_eq!(decode_version_info(&bv), Some((Version::new(32), 0)));
+        for i in 0..3 {
+            let b = bv[i];
+            bv.set(i, !b);
+        }
+        assert_eq!(decode_version_info(&bv), Some((Version::new(32), 3)));
+        // Version info words differs in at least 8 bits.
+        let b = bv[3];
+        bv.set(3, !b);
+        assert_eq!(decode_version_info(&bv), None);
+    }
+
+    #[test]
+    fn micro() {
+        assert_eq!(micro_data_bits(MicroVersion::new(1), ECLevel::L), 20);
+        assert_eq!(micro_data_bits(MicroVersion::new(3), ECLevel::M), 68);
+        assert_eq!(micro_data_bits(MicroVersion::new(4), ECLevel::Q), 80);
+        assert_eq!(micro_ec_count(MicroVersion::new(2), ECLevel::M), 6);
+
+        assert_eq!(micro_format_info(MicroVersion::new(1), ECLevel::L, Mask::new(0)),
+                   bitvec![1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 1]);
+        assert_eq!(micro_format_info(MicroVersion::new(4), ECLevel::Q, Mask::new(3)),
+                   bitvec![0, 1, 1, 1, 0, 1, 1, 1, 0, 1, 1, 1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn rmqr() {
+        assert_eq!(rmqr_total_bits(RmqrVersion::new(7, 43), ECLevel::M), 48);
+        assert_eq!(rmqr_group_block_count(RmqrVersion::new(13, 99), ECLevel::H),
+                   vec![11, 12, 12]);
+        assert_eq!(rmqr_block_ec_count(RmqrVersion::new(17, 139), ECLevel::M), 20);
+
+        // Version R7x43 with ECLevel::M has all data bits zero, leaving only the masks.
+        let (finder, sub_finder) = rmqr_format_info(RmqrVersion::new(7, 43), ECLevel::M);
+        assert_eq!(finder, bitvec![0, 1, 1, 1, 1, 1, 1, 0, 1, 0, 1, 0, 1, 1, 0, 0, 1, 0]);
+        assert_eq!(sub_finder, bitvec![1, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 1, 1, 1, 1, 0, 1, 1]);
+    }
 }
 
 
@@ -93,7 +282,9 @@ mod tests {
 // 2. Num codewords in each of group 1's block
 // 3. Num blocks in group  2
 // 4. Num codewords in each of group 2's block
-static BLOCK_INFO: [[(usize,  usize, usize, usize, usize); 4]; 40] = [
+type BlockInfo = (usize, usize, usize, usize, usize);
+
+static BLOCK_INFO: [[BlockInfo; 4]; 40] = [
     [(7,  1,  19,  0,  0),   (10, 1,  16,  0,  0),
      (13, 1,  13,  0,  0),   (17, 1,  9,   0,  0)],
     [(10, 1,  34,  0,  0),   (16, 1,  28,  0,  0),
@@ -176,6 +367,15 @@ static BLOCK_INFO: [[(usize,  usize, usize, usize, usize); 4]; 40] = [
      (30, 34, 24,  34, 25),  (30, 20, 15,  61, 16)],
 ];
 
+// Micro QR data and EC codewords, version (M1..M4) x ECLevel (L, M, Q).
+// Unsupported combinations are zero.
+static MICRO_BLOCK_INFO: [[(usize, usize); 3]; 4] = [
+    [(3,  2),  (0,  0),  (0,  0)],
+    [(5,  5),  (4,  6),  (0,  0)],
+    [(11, 6),  (9,  8),  (0,  0)],
+    [(16, 8),  (14, 10), (10, 14)],
+];
+
 // Format information for mask x ECLevel.
 static FORMAT_INFO: [[u16; 4]; 8] = [
     [0b111011111000100, 0b101010000010010, 0b011010101011111, 0b001011010001001],
@@ -188,6 +388,18 @@ static FORMAT_INFO: [[u16; 4]; 8] = [
     [0b110100101110110, 0b100101010100000, 0b010101111101101, 0b000100000111011]
 ];
 
+// Micro QR format information for symbol number x mask.
+static MICRO_FORMAT_INFO: [[u16; 4]; 8] = [
+    [0b100010001000101, 0b100000101110010, 0b100111000101011, 0b100101100011100],
+    [0b101010110101110, 0b101000010011001, 0b101111111000000, 0b101101011110111],
+    [0b110011110010011, 0b110001010100100, 0b110110111111101, 0b110100011001010],
+    [0b111011001111000, 0b111001101001111, 0b111110000010110, 0b111100100100001],
+    [0b000011011011110, 0b000001111101001, 0b000110010110000, 0b000100110000111],
+    [0b001011100110101, 0b001001000000010, 0b001110101011011, 0b001100001101100],
+    [0b010010100001000, 0b010000000111111, 0b010111101100110, 0b010101001010001],
+    [0b011010011100011, 0b011000111010100, 0b011111010001101, 0b011101110111010]
+];
+
 // Version information, start indexing from version 7 and up.
 static VERSION_INFO: [u32; 34] = [
     0b000111110010010100,
@@ -225,3 +437,40 @@ static VERSION_INFO: [u32; 34] = [
     0b100111010101000001,
     0b101000110001101001
 ];
+
+// rMQR EC codewords and block information, with the same layout as BLOCK_INFO.
+// Version indicator (0..31) x ECLevel (M, H).
+static RMQR_BLOCK_INFO: [[BlockInfo; 2]; 32] = [
+    [(7,  1, 6,  0, 0),   (10, 1, 3,  0, 0)], // R7x43
+    [(9,  1, 12, 0, 0),   (14, 1, 7,  0, 0)], // R7x59
+    [(12, 1, 20, 0, 0),   (22, 1, 10, 0, 0)], // R7x77
+    [(16, 1, 28, 0, 0),   (30, 1, 14, 0, 0)], // R7x99
+    [(12, 2, 22, 0, 0),   (22, 2, 12, 0, 0)], // R7x139
+    [(9,  1, 12, 0, 0),   (14, 1, 7,  0, 0)], // R9x43
+    [(12, 1, 21, 0, 0),   (22, 1, 11, 0, 0)], // R9x59
+    [(18, 1, 31, 0, 0),   (16, 1, 8,  1, 9)], // R9x77
+    [(12, 2, 21, 0, 0),   (22, 2, 11, 0, 0)], // R9x99
+    [(18, 1, 31, 1, 32),  (22, 3, 11, 0, 0)], // R9x139
+    [(8,  1, 7,  0, 0),   (10, 1, 5,  0, 0)], // R11x27
+    [(12, 1, 19, 0, 0),   (20, 1, 11, 0, 0)], // R11x43
+    [(16, 1, 31, 0, 0),   (16, 1, 7,  1, 8)], // R11x59
+    [(12, 1, 21, 1, 22),  (22, 1, 11, 1, 12)], // R11x77
+    [(16, 1, 28, 1, 29),  (30, 1, 14, 1, 15)], // R11x99
+    [(16, 3, 28, 0, 0),   (30, 3, 14, 0, 0)], // R11x139
+    [(9,  1, 12, 0, 0),   (14, 1, 7,  0, 0)], // R13x27
+    [(14, 1, 27, 0, 0),   (28, 1, 13, 0, 0)], // R13x43
+    [(11, 2, 19, 0, 0),   (20, 2, 10, 0, 0)], // R13x59
+    [(16, 1, 26, 1, 27),  (28, 1, 14, 1, 15)], // R13x77
+    [(20, 1, 36, 1, 37),  (26, 1, 11, 2, 12)], // R13x99
+    [(20, 2, 35, 1, 36),  (28, 2, 13, 2, 14)], // R13x139
+    [(18, 1, 33, 0, 0),   (18, 1, 7,  1, 8)], // R15x43
+    [(26, 1, 48, 0, 0),   (24, 2, 13, 0, 0)], // R15x59
+    [(18, 1, 33, 1, 34),  (24, 2, 10, 1, 11)], // R15x77
+    [(24, 2, 44, 0, 0),   (22, 4, 12, 0, 0)], // R15x99
+    [(24, 2, 42, 1, 43),  (26, 1, 13, 4, 14)], // R15x139
+    [(22, 1, 39, 0, 0),   (20, 1, 10, 1, 11)], // R17x43
+    [(16, 2, 28, 0, 0),   (30, 2, 14, 0, 0)], // R17x59
+    [(22, 2, 39, 0, 0),   (28, 1, 12, 2, 13)], // R17x77
+    [(20, 2, 33, 1, 34),  (26, 4, 14, 0, 0)], // R17x99
+    [(20, 4, 38, 0, 0),   (26, 2, 12, 4, 13)], // R17x139
+];
@@ -3,15 +3,15 @@
 //! and it was a pretty good project to explore rust with. I followed an
 //! [excellent tutorial](https://www.thonky.com/qr-code-tutorial/).
 //!
-//! This library supports strings encoded in numeric, alphanumeric and byte mode.
+//! This library supports strings encoded in numeric, alphanumeric, byte and kanji mode.
 //! It supports all versions, meaning different sizes, of a standard QR code with
-//! the different error correction levels.
+//! the different error correction levels, as well as Micro QR and rMQR codes.
 //!
 //! # QR code as string output
 //!
 //! ```
 //! extern crate rqr;
-//! use rqr::{Qr, StringRenderer};
+//! use rqr::{Qr, Renderer, StringRenderer};
 //!
 //! fn main() {
 //!     let qr = Qr::new("HELLO WORLD").unwrap();
@@ -23,7 +23,7 @@
 //! # SVG generation
 //!
 //! ```
-//! use rqr::{Qr, SvgRenderer, Color, ECLevel};
+//! use rqr::{Qr, Renderer, SvgRenderer, Color, ECLevel};
 //!
 //! fn main() {
 //!     let qr = Qr::with_ecl("HELLO WORLD", ECLevel::Q).unwrap();
@@ -53,20 +53,136 @@
 //!     .unwrap();
 //! ```
 //!
+//! # Segments
+//!
+//! Input is split into segments of different modes to minimize the size.
+//! It's possible to specify the segments yourself:
+//!
+//! ```
+//! use rqr::{QrBuilder, Eci};
+//!
+//! let qr = QrBuilder::new()
+//!     .push_alphanumeric("ORDER-").unwrap()
+//!     .push_numeric("12345678901234").unwrap()
+//!     .push_eci(Eci::UTF8)
+//!     .push_bytes("café".as_bytes())
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! # Decoding
+//!
+//! A matrix can be decoded back to its data, correcting errors along the way:
+//!
+//! ```
+//! use rqr::{Qr, decode};
+//!
+//! let qr = Qr::new("HELLO WORLD").unwrap();
+//! let decoded = decode(&qr.matrix).unwrap();
+//! assert_eq!(decoded.text.as_deref(), Some("HELLO WORLD"));
+//! ```
+//!
+//! Codes can also be read from grayscale images, one byte per pixel,
+//! either a single code or all codes in the image:
+//!
+//! ```no_run
+//! # let (pixels, width, height) = (vec![255u8; 100 * 100], 100, 100);
+//! let decoded = rqr::detect::read(&pixels, width, height);
+//! for found in rqr::detect::read_all(&pixels, width, height).unwrap() {
+//!     println!("{:?} at {:?}", found.decoded.text, found.corners);
+//! }
+//! ```
+//!
+//! # Micro QR
+//!
+//! Micro QR codes M1 to M4 are smaller, for tiny markings with little data:
+//!
+//! ```
+//! use rqr::{MicroQr, MicroVersion, Renderer, StringRenderer};
+//!
+//! let qr = MicroQr::new("01234567").unwrap();
+//! assert_eq!(qr.version, MicroVersion::new(2));
+//! let s = StringRenderer::new().quiet_zone(true).render_micro(&qr);
+//! ```
+//!
+//! # rMQR
+//!
+//! Rectangular Micro QR codes fit long and narrow spaces, from R7x43 to R17x139:
+//!
+//! ```
+//! use rqr::{Renderer, Rmqr, RmqrVersion, SvgRenderer};
+//!
+//! let qr = Rmqr::with_height("HELLO WORLD", 7).unwrap();
+//! assert_eq!(qr.version, RmqrVersion::new(7, 59));
+//! let s = SvgRenderer::new().dimensions(630, 110).render_rmqr(&qr);
+//! ```
+//!
+//! # Structured Append
+//!
+//! Messages too long for a single code can be split over up to 16 codes:
+//!
+//! ```
+//! use rqr::{QrBuilder, Version};
+//!
+//! let manifest: String = (0..200).map(|i| ((i % 26) as u8 + b'a') as char).collect();
+//! let qrs = QrBuilder::new()
+//!     .version(Version::new(5))
+//!     .into_structured_append(&manifest)
+//!     .unwrap();
+//! assert_eq!(qrs.len(), 4);
+//! ```
+//!
+//! # Compatibility
+//!
+//! Valid codes may still scan poorly, for example with low contrast colors.
+//! The `compat` module lists reader compatibility warnings:
+//!
+//! ```
+//! use rqr::{compat, Color, Qr, Renderer, SvgRenderer};
+//!
+//! let qr = Qr::new("https://example.com").unwrap();
+//! let renderer = SvgRenderer::new().dark_module(Color::hex(0xaaaaaa));
+//! for warning in compat::check_svg(&qr, &renderer).warnings {
+//!     println!("{}", warning.description());
+//! }
+//! ```
+//!
 //! More fine grained control is provided by the builder and the underlying matrix.
 
+// Lookup tables are kept at the bottom of their modules, after the tests.
+#![allow(clippy::items_after_test_module)]
+
 pub mod builder;
 pub use builder::*;
 
+pub mod compat;
+
+pub mod damage;
+pub use damage::{BlockBudget, Corner, Damage, DamageReport};
+
 pub mod data;
 pub use data::*;
 
+pub mod decode;
+pub use decode::{decode, Decoded, DecodeError, FormatInfo, VersionInfo};
+
+pub mod defect;
+pub use defect::{Defect, DefectError, DefectiveQr, Outcome};
+
+pub mod detect;
+pub use detect::{detect, Bitmap, DetectError, Found};
+
 pub mod ec;
-pub use ec::*;
+pub use ec::{add, ECLevel, ReedSolomon};
+
+pub mod eci;
+pub use eci::Eci;
 
 pub mod info;
 pub use info::*;
 
+pub mod lint;
+
 pub mod mask;
 pub use mask::*;
 
@@ -76,12 +192,17 @@ pub use matrix::{Module, Matrix};
 pub mod mode;
 pub use mode::Mode;
 
+#[cfg(feature = "png")]
+pub mod png;
+#[cfg(feature = "png")]
+pub use png::PngRenderer;
+
 pub mod qr;
-pub use qr::Qr;
+pub use qr::{Qr, MicroQr, Rmqr};
 
 pub mod render;
 pub use render::*;
 
 pub mod version;
-pub use version::Version;
+pub use version::{Version, MicroVersion, RmqrVersion};
 
@@ -0,0 +1,202 @@
+//! Checks a finished matrix against the QR specification.
+//!
+//! Unlike the assertions in the matrix and builder it never fails,
+//! all problems are collected in a report instead. Useful for matrices
+//! built step by step or imported from elsewhere.
+
+use crate::builder::{self, QrBuilder};
+use crate::decode;
+use crate::matrix::{Matrix, Module};
+use crate::version::Version;
+
+/// A function pattern.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// One of the three finder patterns.
+    Finder,
+    /// The light border between a finder and the data.
+    Separator,
+    /// The alternating row and column between the finders.
+    Timing,
+    /// One of the alignment patterns.
+    Alignment,
+    /// The single dark module next to the bottom left finder.
+    DarkModule,
+}
+
+/// A problem found in a matrix.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// The matrix doesn't have the size of the version.
+    InvalidSize,
+    /// A module in a function pattern has the wrong color.
+    WrongModule { pattern: Pattern, x: usize, y: usize },
+    /// A module hasn't been assigned.
+    Unknown { x: usize, y: usize },
+    /// A module is still reserved, the info hasn't been added.
+    Reserved { x: usize, y: usize },
+    /// A format info copy has bit errors, or the copies differ.
+    FormatInfo,
+    /// The version info is missing, has bit errors or doesn't match the version.
+    VersionInfo,
+}
+
+/// The problems found in a matrix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// Version the matrix was checked against.
+    pub version: Version,
+
+    /// All problems found, empty if the matrix is valid.
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    /// Does the matrix follow the specification?
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Number of wrong modules in a function pattern.
+    pub fn wrong_modules(&self, pattern: Pattern) -> usize {
+        self.issues.iter()
+            .filter(|i| matches!(i, Issue::WrongModule { pattern: p, .. } if *p == pattern))
+            .count()
+    }
+}
+
+/// Check a QR code matrix of a version.
+pub fn validate(matrix: &Matrix, version: Version) -> Report {
+    let mut issues = Vec::new();
+    let size = version.size();
+    if matrix.width != size || matrix.height != size || matrix.modules.len() != size * size {
+        issues.push(Issue::InvalidSize);
+        return Report { version, issues };
+    }
+
+    let mut builder = QrBuilder::new().version(version);
+    builder.add_fun_patterns();
+    let template = builder.matrix;
+
+    for y in 0..size {
+        for x in 0..size {
+            match matrix.get(x, y) {
+                Module::Unknown => issues.push(Issue::Unknown { x, y }),
+                Module::Reserved => issues.push(Issue::Reserved { x, y }),
+                _ => {}
+            }
+            if let Module::Function(dark) = template.get(x, y) {
+                if matrix.is_dark(x, y) != *dark {
+                    let pattern = pattern_at(version, x, y);
+                    issues.push(Issue::WrongModule { pattern, x, y });
+                }
+            }
+        }
+    }
+
+    match decode::read_format_info(matrix) {
+        Ok(info) if info.consistent() &&
+                    info.copies.iter().all(|c| matches!(c, Some((_, _, 0)))) => {}
+        _ => issues.push(Issue::FormatInfo),
+    }
+
+    if version.extra_version_areas() {
+        match decode::read_version_info(matrix) {
+            Ok(info) if info.copies.iter().all(|c| *c == Some((version, 0))) => {}
+            _ => issues.push(Issue::VersionInfo),
+        }
+    }
+
+    Report { version, issues }
+}
+
+// The function pattern of a module, which must be one.
+fn pattern_at(version: Version, x: usize, y: usize) -> Pattern {
+    let size = version.size();
+    let in_corner = |w: usize| {
+        (y < w && (x < w || x >= size - w)) || (x < w && y >= size - w)
+    };
+    if in_corner(7) {
+        Pattern::Finder
+    } else if in_corner(8) {
+        Pattern::Separator
+    } else if (x, y) == version.dark_module_pos() {
+        Pattern::DarkModule
+    } else if builder::alignment_centers(version).iter()
+        .any(|(cx, cy)| x + 2 >= *cx && x <= cx + 2 && y + 2 >= *cy && y <= cy + 2)
+    {
+        // Alignment patterns may cross the timing patterns.
+        Pattern::Alignment
+    } else {
+        Pattern::Timing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defect::{self, Defect};
+    use crate::qr::Qr;
+
+    #[test]
+    fn valid() {
+        let qr = Qr::new("HELLO WORLD").unwrap();
+        assert!(validate(&qr.matrix, qr.version).is_valid());
+        assert!(qr.matrix.validate(qr.version).is_valid());
+        let qr = Qr::with_version("VERSION 7", Version::new(7)).unwrap();
+        assert!(validate(&qr.matrix, qr.version).is_valid());
+    }
+
+    #[test]
+    fn wrong_modules() {
+        let qr = Qr::with_version("HELLO WORLD", Version::new(2)).unwrap();
+        let mut matrix = qr.matrix.clone();
+        let mut flip = |x, y| {
+            let m = *matrix.get(x, y);
+            matrix.set(x, y, !m);
+        };
+        flip(3, 3);
+        flip(7, 2);
+        flip(10, 6);
+        flip(18, 18);
+        flip(8, 17);
+        let report = validate(&matrix, qr.version);
+        assert_eq!(report.issues, vec![
+            Issue::WrongModule { pattern: Pattern::Separator, x: 7, y: 2 },
+            Issue::WrongModule { pattern: Pattern::Finder, x: 3, y: 3 },
+            Issue::WrongModule { pattern: Pattern::Timing, x: 10, y: 6 },
+            Issue::WrongModule { pattern: Pattern::DarkModule, x: 8, y: 17 },
+            Issue::WrongModule { pattern: Pattern::Alignment, x: 18, y: 18 },
+        ]);
+        assert_eq!(report.wrong_modules(Pattern::Finder), 1);
+    }
+
+    #[test]
+    fn incomplete() {
+        let mut builder = QrBuilder::new().version(Version::new(1));
+        builder.add_fun_patterns();
+        let report = validate(&builder.matrix, Version::new(1));
+        // Data is missing and the format info is reserved.
+        let unknown = report.issues.iter().filter(|i| matches!(i, Issue::Unknown { .. })).count();
+        let reserved = report.issues.iter().filter(|i| matches!(i, Issue::Reserved { .. })).count();
+        assert_eq!(unknown, 208);
+        assert_eq!(reserved, 30);
+        assert!(report.issues.contains(&Issue::FormatInfo));
+
+        assert_eq!(validate(&Matrix::new(22), Version::new(1)).issues, vec![Issue::InvalidSize]);
+        let qr = Qr::new("HELLO WORLD").unwrap();
+        assert_eq!(validate(&qr.matrix, Version::new(2)).issues, vec![Issue::InvalidSize]);
+    }
+
+    #[test]
+    fn defects() {
+        let builder = QrBuilder::new().version(Version::new(7));
+        let qr = defect::generate(builder.clone(), "DEFECT", Defect::FormatErrors(2)).unwrap();
+        assert_eq!(validate(&qr.matrix, qr.version).issues, vec![Issue::FormatInfo]);
+        let qr = defect::generate(builder.clone(), "DEFECT", Defect::MismatchedVersionInfo).unwrap();
+        assert_eq!(validate(&qr.matrix, qr.version).issues, vec![Issue::VersionInfo]);
+        let qr = defect::generate(builder, "DEFECT", Defect::MissingAlignment).unwrap();
+        // Each of the 6 alignment patterns has 17 dark modules.
+        assert_eq!(validate(&qr.matrix, qr.version).wrong_modules(Pattern::Alignment), 102);
+    }
+}
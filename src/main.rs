@@ -3,7 +3,7 @@ extern crate clap;
 use clap::{App, Arg, ArgMatches};
 
 extern crate rqr;
-use rqr::{Qr, SvgRenderer, StringRenderer, Color};
+use rqr::{Qr, Renderer, SvgRenderer, TerminalRenderer, Color};
 
 fn main() {
     let matches = App::new("rqr cli")
@@ -27,6 +27,9 @@ fn main() {
                 .takes_value(true)
                 .long("fg")
                 .help("Foreground color to use for svg output"))
+        .arg(Arg::with_name("invert")
+                .long("invert")
+                .help("Invert string output, for terminals with a dark background"))
         .arg(Arg::with_name("width")
                 .takes_value(true)
                 .long("width")
@@ -90,18 +93,14 @@ fn output_svg(qr: &Qr, matches: &ArgMatches) {
         r = r.dimensions(w, w);
     }
 
-    let s = r.render(&qr);
+    let s = r.render(qr);
     println!("{}", s);
 }
 
-fn output_string(qr: &Qr, _matches: &ArgMatches) {
-    // Pretty print in terminal.
-    let s = StringRenderer::new()
-        .dark_module('\u{2588}')
-        .light_module(' ')
-        .quiet_zone(true)
-        .module_dimensions(2, 1)
-        .render(&qr);
-    println!("{}", s);
+fn output_string(qr: &Qr, matches: &ArgMatches) {
+    // Pretty print in terminal, two rows of modules per line.
+    let s = TerminalRenderer::new()
+        .invert(matches.is_present("invert"))
+        .render(qr);
+    print!("{}", s);
 }
-
@@ -18,7 +18,7 @@ impl Mask {
         Mask(v)
     }
 
-    fn fun(&self) -> Box<Fn(usize, usize) -> bool> {
+    fn fun(&self) -> Box<dyn Fn(usize, usize) -> bool> {
         match self.0 {
             0 => Box::new(move |x, y| (x + y) % 2 == 0),
             1 => Box::new(move |_, y| y % 2 ==0),
@@ -36,7 +36,7 @@ impl Mask {
 /// Evaluates masks.
 /// Returns the mask with the lowest score and a matrix with the mask applied.
 pub fn mask(matrix: &Matrix) -> (Mask, Matrix) {
-    let mut min_score = u16::max_value();
+    let mut min_score = u16::MAX;
     let mut res = None;
     for v in 0..8 {
         let mask = Mask::new(v);
@@ -50,6 +50,46 @@ pub fn mask(matrix: &Matrix) -> (Mask, Matrix) {
     res.unwrap()
 }
 
+/// Evaluates the four Micro QR masks.
+/// Returns the mask with the highest score and a matrix with the mask applied.
+/// The mask is the Micro QR mask reference, 0 to 3.
+pub fn micro_mask(matrix: &Matrix) -> (Mask, Matrix) {
+    let mut max_score = 0;
+    let mut res = None;
+    for v in 0..4 {
+        let mask = Mask::new(v);
+        let masked = apply_micro_mask(mask, matrix);
+        let score = evaluate_micro(&masked);
+        if res.is_none() || score > max_score {
+            max_score = score;
+            res = Some((mask, masked));
+        }
+    }
+    res.unwrap()
+}
+
+/// Apply a Micro QR mask, 0 to 3, to a matrix.
+/// They're a subset of the QR masks.
+pub fn apply_micro_mask(mask: Mask, matrix: &Matrix) -> Matrix {
+    assert!(mask.0 <= 3);
+    apply_mask(Mask::new(MICRO_MASKS[mask.0]), matrix)
+}
+
+/// Evaluate the Micro QR mask score of a matrix, higher is better.
+///
+/// Only the dark modules along the right and bottom edges are counted,
+/// as the others already contain the timing patterns.
+pub fn evaluate_micro(matrix: &Matrix) -> u16 {
+    let size = matrix.width;
+    let right = (1..size).filter(|y| matrix.is_dark(size - 1, *y)).count() as u16;
+    let bottom = (1..size).filter(|x| matrix.is_dark(*x, size - 1)).count() as u16;
+    if right <= bottom {
+        right * 16 + bottom
+    } else {
+        bottom * 16 + right
+    }
+}
+
 /// Apply a mask of a specific type to a matrix.
 pub fn apply_mask(mask: Mask, matrix: &Matrix) -> Matrix {
     apply_mask_fun(mask.fun(), matrix)
@@ -69,7 +109,7 @@ pub fn evaluate(matrix: &Matrix) -> u16 {
 // 5 in a row/col should give a score of 3, each extra gives a score of 1.
 fn evaluate_5_in_line(matrix: &Matrix) -> u16 {
     let mut res = 0;
-    for i in 0..matrix.size {
+    for i in 0..matrix.width {
         res += eval_5_col(matrix, i);
         res += eval_5_row(matrix, i);
     }
@@ -80,7 +120,7 @@ fn eval_5_row(matrix: &Matrix, y: usize) -> u16 {
     let mut res = 0;
     let mut from = 0;
     let mut curr = matrix.is_dark(0, y);
-    for x in 1..matrix.size {
+    for x in 1..matrix.width {
         if matrix.is_dark(x, y) == curr {
             res += diff_5(from, x)
         } else {
@@ -95,7 +135,7 @@ fn eval_5_col(matrix: &Matrix, x: usize) -> u16 {
     let mut res = 0;
     let mut from = 0;
     let mut curr = matrix.is_dark(x, 0);
-    for y in 1..matrix.size {
+    for y in 1..matrix.width {
         if matrix.is_dark(x, y) == curr {
             res += diff_5(from, y)
         } else {
@@ -120,8 +160,8 @@ fn diff_5(from: usize, to: usize) -> u16 {
 // Each 2x2 square of the same color gives a score of 3.
 fn evaluate_2x2(matrix: &Matrix) -> u16 {
     let mut squares = 0;
-    for x in 0..matrix.size - 1 {
-        for y in 0..matrix.size - 1 {
+    for x in 0..matrix.width - 1 {
+        for y in 0..matrix.width - 1 {
             let square = [
                 matrix.is_dark(x, y),
                 matrix.is_dark(x + 1, y),
@@ -140,7 +180,7 @@ fn evaluate_2x2(matrix: &Matrix) -> u16 {
 // Each dark/light pattern found gives a score of 40.
 fn evaluate_dl_pattern(matrix: &Matrix) -> u16 {
     let mut count = 0;
-    for i in 0..matrix.size {
+    for i in 0..matrix.width {
         count += count_dl_row(matrix, i);
         count += count_dl_col(matrix, i);
     }
@@ -148,16 +188,16 @@ fn evaluate_dl_pattern(matrix: &Matrix) -> u16 {
 }
 
 fn count_dl_row(matrix: &Matrix, y: usize) -> u16 {
-    let mut row = BitVec::with_capacity(matrix.size);
-    for x in 0..matrix.size {
+    let mut row = BitVec::with_capacity(matrix.width);
+    for x in 0..matrix.width {
         row.push(!matrix.is_dark(x, y));
     }
     count_dl_patterns(&row)
 }
 
 fn count_dl_col(matrix: &Matrix, x: usize) -> u16 {
-    let mut col = BitVec::with_capacity(matrix.size);
-    for y in 0..matrix.size {
+    let mut col = BitVec::with_capacity(matrix.width);
+    for y in 0..matrix.width {
         col.push(!matrix.is_dark(x, y));
     }
     count_dl_patterns(&col)
@@ -190,7 +230,7 @@ fn count_dl_patterns(bv: &BitVec) -> u16 {
 
 // Calculates a score depending on the light/dark ratio.
 fn evaluate_bw(matrix: &Matrix) -> u16 {
-    let total = matrix.size * matrix.size;
+    let total = matrix.width * matrix.width;
     let dark = matrix.modules.iter().filter(|x| x.is_dark()).count();
     let ratio = ((dark as f32) / (total as f32) * 100.0) as i16;
     let low_5 = ratio - ratio % 5;
@@ -200,10 +240,13 @@ fn evaluate_bw(matrix: &Matrix) -> u16 {
     cmp::min(a, b) as u16
 }
 
-fn apply_mask_fun(f: Box<Fn(usize, usize) -> bool>, matrix: &Matrix) -> Matrix {
+// The QR masks corresponding to the Micro QR masks.
+static MICRO_MASKS: [usize; 4] = [1, 4, 6, 7];
+
+fn apply_mask_fun(f: Box<dyn Fn(usize, usize) -> bool>, matrix: &Matrix) -> Matrix {
     let mut res = matrix.clone();
-    for y in 0..res.size {
-        for x in 0..res.size {
+    for y in 0..res.height {
+        for x in 0..res.width {
             if matrix.is_data(x, y) && f(x, y) {
                 res.flip(x, y);
             }
@@ -266,5 +309,22 @@ X-X-X-.-X-X-X-X-X-X-X
         assert_eq!(evaluate_bw(&builder.matrix), 10);
         assert_eq!(evaluate(&builder.matrix), 445);
     }
+
+    #[test]
+    fn micro_mask_evaluation() {
+        let mut matrix = Matrix::new(11);
+        // 3 dark modules along the right edge and 5 along the bottom.
+        for i in 1..4 {
+            matrix.set_fun(10, i, true);
+        }
+        for i in 1..6 {
+            matrix.set_fun(i, 10, true);
+        }
+        assert_eq!(evaluate_micro(&matrix), 3 * 16 + 5);
+        // The top right and bottom left corners are part of the timing patterns.
+        matrix.set_fun(10, 0, true);
+        matrix.set_fun(0, 10, true);
+        assert_eq!(evaluate_micro(&matrix), 3 * 16 + 5);
+    }
 }
 
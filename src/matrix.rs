@@ -7,6 +7,9 @@
 //! If interfacing with the matrix directly take care not to violate
 //! assumptions, like overwriting existing data.
 
+use crate::lint::{self, Report};
+use crate::version::Version;
+
 use std::ops::Not;
 
 /// The type of a module.
@@ -40,19 +43,12 @@ impl Module {
     /// Is the module a function module?
     /// This includes reserved modules as well.
     pub fn is_fun(&self) -> bool {
-        match self {
-            Module::Unknown => false,
-            Module::Data(_) => false,
-            _ => true,
-        }
+        !matches!(self, Module::Unknown | Module::Data(_))
     }
 
     /// Is the module a Data module?
     pub fn is_data(&self) -> bool {
-        match self {
-            Module::Data(_) => true,
-            _ => false,
-        }
+        matches!(self, Module::Data(_))
     }
 }
 
@@ -69,29 +65,49 @@ impl Not for Module {
 }
 
 /// Matrix is a 2-dimensional grid holding the QR modules.
+/// It's square for QR and Micro QR codes, but rectangular for rMQR codes.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Matrix {
-    /// Size defines the width and height of the matrix.
-    pub size: usize,
+    /// Width of the matrix.
+    pub width: usize,
+
+    /// Height of the matrix.
+    pub height: usize,
 
     /// The modules.
     pub modules: Vec<Module>,
 }
 
 impl Matrix {
-    /// Create a new matrix, modules initialized to Unknown.
+    /// Create a new square matrix, modules initialized to Unknown.
     pub fn new(size: usize) -> Matrix {
+        Matrix::new_rect(size, size)
+    }
+
+    /// Create a new rectangular matrix, modules initialized to Unknown.
+    pub fn new_rect(width: usize, height: usize) -> Matrix {
         Matrix {
-            size: size,
-            modules: vec![Module::Unknown; size * size],
+            width,
+            height,
+            modules: vec![Module::Unknown; width * height],
         }
     }
 
+    /// Is the matrix square, like for QR and Micro QR codes?
+    pub fn is_square(&self) -> bool {
+        self.width == self.height
+    }
+
+    /// The width and height of a square matrix, None for rectangular matrices.
+    pub fn size(&self) -> Option<usize> {
+        if self.is_square() { Some(self.width) } else { None }
+    }
+
     /// Map (x,y) coords to linear index.
     pub fn index(&self, x: usize, y: usize) -> usize {
-        assert!(x < self.size);
-        assert!(y < self.size);
-        self.size * y + x
+        assert!(x < self.width);
+        assert!(y < self.height);
+        self.width * y + x
     }
 
     /// Get module.
@@ -177,6 +193,12 @@ impl Matrix {
         }
     }
 
+    /// Check the matrix against the QR specification for a version,
+    /// without failing on problems.
+    pub fn validate(&self, v: Version) -> Report {
+        lint::validate(self, v)
+    }
+
     /// Return true if there's any module other than Unknown in square.
     pub fn any_in_square(&self, x: usize, y: usize, w: usize) -> bool {
         self.any_in_rect(x, y, x + w - 1, y + w - 1)
@@ -3,6 +3,7 @@
 use regex::Regex;
 use bitvec::*;
 use lazy_static::lazy_static;
+use encoding_rs::SHIFT_JIS;
 
 /// Encoding modes.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -13,20 +14,37 @@ pub enum Mode {
     /// the characters $%*+-./: and space.
     Alphanumeric,
     /// Byte mode supports the ISO-8859-1 character set.
-    /// It is possible to use this mode to encode unicode,
+    /// Other character sets are supported by preceding the data with an ECI,
     /// but it depends heavily on the reader if it's supported or not.
     Byte,
-    //ECI, // specifies the character set directly (like UTF-8)
-    //Kanji, // more efficient storage than ECI
+    /// Kanji mode encodes double-byte Shift JIS characters in 13 bits each.
+    /// Much more compact than storing Japanese text as UTF-8 in byte mode.
+    Kanji,
+    /// Extended Channel Interpretation specifies the character set directly
+    /// (like UTF-8) of the byte mode data that follows.
+    /// It holds no data of its own and is never inferred from a string.
+    Eci,
+    /// Structured Append header, marks the symbol as one of several
+    /// symbols which together holds a message.
+    StructuredAppend,
+    /// FNC1 in first position, marks the data as formatted per the
+    /// GS1 General Specifications.
+    Fnc1First,
+    /// FNC1 in second position, marks the data as formatted per an industry
+    /// application, identified by the application indicator that follows.
+    Fnc1Second,
 }
 
 impl Mode {
     /// Create Mode from string, decide from content.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Mode {
         if Mode::in_numeric(s) {
             Mode::Numeric
         } else if Mode::in_alphanumeric(s) {
             Mode::Alphanumeric
+        } else if Mode::in_kanji(s) {
+            Mode::Kanji
         } else if Mode::in_byte(s) {
             Mode::Byte
         } else {
@@ -41,6 +59,39 @@ impl Mode {
             Mode::Numeric => Mode::in_numeric(s),
             Mode::Alphanumeric => Mode::in_alphanumeric(s),
             Mode::Byte => Mode::in_byte(s),
+            Mode::Kanji => Mode::in_kanji(s),
+            Mode::Eci => false,
+            Mode::StructuredAppend => false,
+            Mode::Fnc1First => false,
+            Mode::Fnc1Second => false,
+        }
+    }
+
+    /// Returns the number of characters the char count indicator should hold.
+    /// Byte mode counts bytes, in ISO-8859-1 if possible and UTF-8 otherwise,
+    /// and Kanji mode counts double-byte characters.
+    pub fn char_count(&self, s: &str) -> usize {
+        match self {
+            Mode::Byte if to_latin1(s).is_some() => s.chars().count(),
+            Mode::Kanji => s.chars().count(),
+            _ => s.len(),
+        }
+    }
+
+    /// Returns the number of data bits needed to encode a number of characters,
+    /// excluding mode indicator and char count.
+    pub fn data_bit_len(&self, char_count: usize) -> usize {
+        match self {
+            // Groups of three digits uses 10 bits, the remainder 4 or 7 bits.
+            Mode::Numeric => 10 * (char_count / 3) + [0, 4, 7][char_count % 3],
+            // Pairs of characters uses 11 bits, the remainder 6 bits.
+            Mode::Alphanumeric => 11 * (char_count / 2) + 6 * (char_count % 2),
+            Mode::Byte => 8 * char_count,
+            Mode::Kanji => 13 * char_count,
+            Mode::Eci => 0,
+            Mode::StructuredAppend => 0,
+            Mode::Fnc1First => 0,
+            Mode::Fnc1Second => 0,
         }
     }
 
@@ -50,6 +101,26 @@ impl Mode {
             Mode::Numeric => bitvec![0, 0, 0, 1],
             Mode::Alphanumeric => bitvec![0, 0, 1, 0],
             Mode::Byte => bitvec![0, 1, 0, 0],
+            Mode::Kanji => bitvec![1, 0, 0, 0],
+            Mode::Eci => bitvec![0, 1, 1, 1],
+            Mode::StructuredAppend => bitvec![0, 0, 1, 1],
+            Mode::Fnc1First => bitvec![0, 1, 0, 1],
+            Mode::Fnc1Second => bitvec![1, 0, 0, 1],
+        }
+    }
+
+    /// Returns the 3 bit encoding used in rMQR codes.
+    /// Fails for Structured Append, which rMQR doesn't support.
+    pub fn to_rmqr_bitvec(&self) -> BitVec {
+        match self {
+            Mode::Numeric => bitvec![0, 0, 1],
+            Mode::Alphanumeric => bitvec![0, 1, 0],
+            Mode::Byte => bitvec![0, 1, 1],
+            Mode::Kanji => bitvec![1, 0, 0],
+            Mode::Eci => bitvec![1, 1, 1],
+            Mode::StructuredAppend => panic!("Structured Append isn't available in rMQR"),
+            Mode::Fnc1First => bitvec![1, 0, 1],
+            Mode::Fnc1Second => bitvec![1, 1, 0],
         }
     }
 
@@ -67,6 +138,46 @@ impl Mode {
     pub fn in_byte(_s: &str) -> bool {
         true
     }
+
+    /// Returns true if contents can be represented by the kanji mode.
+    /// Every character must map to a double-byte Shift JIS value
+    /// in the 0x8140-0x9FFC or 0xE040-0xEBBF ranges.
+    pub fn in_kanji(s: &str) -> bool {
+        if s.is_empty() {
+            return false;
+        }
+        match to_shift_jis(s) {
+            Some(bytes) => {
+                bytes.len() == 2 * s.chars().count() &&
+                bytes.chunks(2).all(|c| in_kanji_range(c[0], c[1]))
+            }
+            None => false,
+        }
+    }
+}
+
+/// Convert a string to ISO-8859-1 bytes.
+/// Returns None if there are characters outside of the character set.
+pub fn to_latin1(s: &str) -> Option<Vec<u8>> {
+    s.chars()
+        .map(|c| if (c as u32) <= 0xFF { Some(c as u8) } else { None })
+        .collect()
+}
+
+/// Convert a string to Shift JIS bytes.
+/// Returns None if there are characters which can't be represented.
+pub fn to_shift_jis(s: &str) -> Option<Vec<u8>> {
+    let (bytes, _, had_errors) = SHIFT_JIS.encode(s);
+    if had_errors {
+        None
+    } else {
+        Some(bytes.into_owned())
+    }
+}
+
+fn in_kanji_range(msb: u8, lsb: u8) -> bool {
+    let v = ((msb as u16) << 8) | (lsb as u16);
+    (0x8140..=0x9FFC).contains(&v) || (0xE040..=0xEBBF).contains(&v)
 }
 
 lazy_static! {
@@ -86,11 +197,37 @@ mod tests {
                    Mode::Alphanumeric);
         assert_eq!(Mode::from_str("☃"),
                    Mode::Byte);
+        assert_eq!(Mode::from_str("点茗"),
+                   Mode::Kanji);
+        // Mixing in ASCII forces byte mode.
+        assert_eq!(Mode::from_str("点A"),
+                   Mode::Byte);
+    }
+
+    #[test]
+    fn kanji() {
+        assert!(Mode::in_kanji("日本語"));
+        assert!(!Mode::in_kanji(""));
+        // Half-width katakana is a single Shift JIS byte.
+        assert!(!Mode::in_kanji("ｱ"));
+        assert_eq!(to_shift_jis("点茗"), Some(vec![0x93, 0x5F, 0xE4, 0xAA]));
+        assert_eq!(Mode::Kanji.char_count("点茗"), 2);
+        assert_eq!(Mode::Byte.char_count("点茗"), 6);
+    }
+
+    #[test]
+    fn latin1() {
+        assert_eq!(to_latin1("café"), Some(vec![b'c', b'a', b'f', 0xE9]));
+        assert_eq!(to_latin1("☃"), None);
+        assert_eq!(Mode::Byte.char_count("café"), 4);
     }
 
     #[test]
     fn internal() {
         assert_eq!(Mode::Numeric.to_bitvec(), bitvec![0, 0, 0, 1]);
+        assert_eq!(Mode::Kanji.to_bitvec(), bitvec![1, 0, 0, 0]);
+        assert_eq!(Mode::Numeric.data_bit_len(7), 24);
+        assert_eq!(Mode::Alphanumeric.data_bit_len(11), 61);
     }
 }
 
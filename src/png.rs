@@ -0,0 +1,512 @@
+//! Renders the QR code to PNG, without external dependencies.
+//!
+//! The image data is compressed with fixed Huffman codes, only repeating
+//! the previous pixel or the pixel above. QR codes are mostly long runs
+//! so it compresses well while staying simple.
+
+use crate::decode;
+use crate::matrix::Matrix;
+use crate::mode;
+use crate::render::{Color, Palette, RenderOptions, Renderer};
+
+use lazy_static::lazy_static;
+
+/// The pixel format of the image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorType {
+    /// 1-bit grayscale, dark modules are black and light modules white.
+    /// The colors are ignored.
+    Gray1,
+    /// 8-bit grayscale, the luma of the colors.
+    Gray8,
+    /// 8-bit RGBA, the colors as is and fully opaque.
+    Rgba,
+}
+
+/// An error from adding a text chunk with an invalid keyword.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeywordError;
+
+/// A renderer for converting a QR code into a PNG image.
+/// ```
+/// # use rqr::*;
+/// let qr = Qr::new("HELLO WORLD").unwrap();
+/// let png = PngRenderer::new()
+///     .color_type(png::ColorType::Rgba)
+///     .dark_module(Color::hex(0x770000))
+///     .module_dimensions(4, 4)
+///     .render(&qr);
+/// ```
+pub struct PngRenderer {
+    options: RenderOptions,
+    palette: Palette<Color>,
+    color_type: ColorType,
+    metadata: bool,
+    text: Vec<(String, String)>,
+}
+
+impl Default for PngRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PngRenderer {
+    /// Create a new renderer.
+    pub fn new() -> Self {
+        Self {
+            options: RenderOptions { module_dimensions: Some((8, 8)), ..RenderOptions::new() },
+            palette: Palette::new(Color::new(255, 255, 255), Color::new(0, 0, 0)),
+            color_type: ColorType::Gray1,
+            metadata: false,
+            text: Vec::new(),
+        }
+    }
+
+    /// Set the pixel format.
+    pub fn color_type(mut self, v: ColorType) -> Self {
+        self.color_type = v;
+        self
+    }
+
+    /// Set if the payload and the settings of the QR code should be
+    /// stored as text chunks, if the matrix can be decoded.
+    pub fn metadata(mut self, v: bool) -> Self {
+        self.metadata = v;
+        self
+    }
+
+    /// Add a text chunk. The keyword must be 1 to 79 printable Latin-1 characters,
+    /// without leading, trailing or consecutive spaces.
+    pub fn text(mut self, keyword: &str, text: &str) -> Result<Self, KeywordError> {
+        let printable = keyword.chars()
+            .all(|c| matches!(c as u32, 32..=126 | 161..=255));
+        let len = keyword.chars().count();
+        if !printable || !(1..80).contains(&len) || keyword.starts_with(' ') ||
+                keyword.ends_with(' ') || keyword.contains("  ") {
+            return Err(KeywordError);
+        }
+        self.text.push((keyword.to_string(), text.to_string()));
+        Ok(self)
+    }
+
+    // Pixel rows of the image, each preceded by the filter type.
+    fn scanlines(&self, matrix: &Matrix, qz: usize, width: usize, height: usize) -> Vec<u8> {
+        let stride = match self.color_type {
+            ColorType::Gray1 => width.div_ceil(8),
+            ColorType::Gray8 => width,
+            ColorType::Rgba => 4 * width,
+        };
+        let (module_w, module_h) = self.options.module_size();
+        let mut res = Vec::with_capacity((stride + 1) * height);
+        for py in 0..height {
+            res.push(0);
+            let start = res.len();
+            res.resize(start + stride, 0);
+            let row = &mut res[start..];
+            for px in 0..width {
+                let (x, y) = (px / module_w, py / module_h);
+                let dark = x >= qz && y >= qz && x - qz < matrix.width && y - qz < matrix.height &&
+                           matrix.is_dark(x - qz, y - qz);
+                let color = if dark { self.palette.dark } else { self.palette.light };
+                match self.color_type {
+                    ColorType::Gray1 => if !dark {
+                        row[px / 8] |= 0x80 >> (px % 8);
+                    },
+                    ColorType::Gray8 => row[px] = color.luma(),
+                    ColorType::Rgba => row[(4 * px)..(4 * px + 4)]
+                        .copy_from_slice(&[color.r, color.g, color.b, 255]),
+                }
+            }
+        }
+        res
+    }
+}
+
+impl Renderer for PngRenderer {
+    type Output = Vec<u8>;
+    type Module = Color;
+
+    fn options(&self) -> &RenderOptions {
+        &self.options
+    }
+
+    fn options_mut(&mut self) -> &mut RenderOptions {
+        &mut self.options
+    }
+
+    fn palette(&self) -> &Palette<Color> {
+        &self.palette
+    }
+
+    fn palette_mut(&mut self) -> &mut Palette<Color> {
+        &mut self.palette
+    }
+
+    fn render_with_qz(&self, matrix: &Matrix, qz: usize) -> Vec<u8> {
+        let (module_w, module_h) = self.options.module_size();
+        let width = (matrix.width + 2 * qz) * module_w;
+        let height = (matrix.height + 2 * qz) * module_h;
+
+        let mut res = PNG_SIGNATURE.to_vec();
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+        ihdr.extend_from_slice(match self.color_type {
+            ColorType::Gray1 => &[1, 0],
+            ColorType::Gray8 => &[8, 0],
+            ColorType::Rgba => &[8, 6],
+        });
+        // Deflate compression, adaptive filtering and no interlacing.
+        ihdr.extend_from_slice(&[0, 0, 0]);
+        write_chunk(&mut res, b"IHDR", &ihdr);
+
+        let mut text = Vec::new();
+        if self.metadata {
+            if let Ok(decoded) = decode::decode(matrix) {
+                if let Some(payload) = decoded.text {
+                    text.push(("QR payload".to_string(), payload));
+                }
+                text.push(("QR settings".to_string(),
+                           format!("version {}, ecl {:?}, mask {}",
+                                   decoded.version.0, decoded.ecl, decoded.mask.0)));
+            }
+        }
+        for (keyword, s) in text.iter().chain(self.text.iter()) {
+            // Keywords are checked to be in Latin-1 when added.
+            let mut data = mode::to_latin1(keyword).unwrap();
+            data.push(0);
+            match mode::to_latin1(s) {
+                Some(latin1) => {
+                    data.extend_from_slice(&latin1);
+                    write_chunk(&mut res, b"tEXt", &data);
+                }
+                None => {
+                    // International text is UTF-8, uncompressed and without a language.
+                    data.extend_from_slice(&[0, 0, 0, 0]);
+                    data.extend_from_slice(s.as_bytes());
+                    write_chunk(&mut res, b"iTXt", &data);
+                }
+            }
+        }
+
+        let scanlines = self.scanlines(matrix, qz, width, height);
+        let bpp = if self.color_type == ColorType::Rgba { 4 } else { 1 };
+        let stride = scanlines.len() / height;
+        write_chunk(&mut res, b"IDAT", &zlib(&scanlines, &[bpp, stride]));
+        write_chunk(&mut res, b"IEND", &[]);
+        res
+    }
+}
+
+fn write_chunk(res: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    res.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = res.len();
+    res.extend_from_slice(kind);
+    res.extend_from_slice(data);
+    let crc = crc32(&res[start..]);
+    res.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(v: &[u8]) -> u32 {
+    !v.iter().fold(!0u32, |crc, b| CRC_TABLE[((crc ^ *b as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+fn adler32(v: &[u8]) -> u32 {
+    let (a, b) = v.iter().fold((1u32, 0u32), |(a, b), x| {
+        let a = (a + *x as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
+
+// Compress to a zlib stream, as a single deflate block with fixed Huffman codes.
+// Only matches at the given distances are searched for.
+fn zlib(v: &[u8], distances: &[usize]) -> Vec<u8> {
+    let mut w = BitWriter::default();
+    // Final block with fixed Huffman codes.
+    w.write(1, 1);
+    w.write(1, 2);
+    let mut i = 0;
+    while i < v.len() {
+        let best = distances.iter()
+            .filter(|d| **d <= i && **d <= MAX_DISTANCE)
+            .map(|d| {
+                let len = (0..MAX_LENGTH.min(v.len() - i))
+                    .take_while(|k| v[i + k] == v[i + k - d])
+                    .count();
+                (len, *d)
+            })
+            .max();
+        match best {
+            Some((len, d)) if len >= 3 => {
+                w.length(len);
+                w.distance(d);
+                i += len;
+            }
+            _ => {
+                w.literal(v[i] as usize);
+                i += 1;
+            }
+        }
+    }
+    w.literal(256);
+
+    let mut res = vec![0x78, 0x01];
+    res.extend_from_slice(&w.finish());
+    res.extend_from_slice(&adler32(v).to_be_bytes());
+    res
+}
+
+// Writes bits least significant bit first, as deflate expects.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u32,
+    len: usize,
+}
+
+impl BitWriter {
+    fn write(&mut self, v: u32, len: usize) {
+        self.acc |= v << self.len;
+        self.len += len;
+        while self.len >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    // Huffman codes are written most significant bit first.
+    fn code(&mut self, code: u32, len: usize) {
+        self.write(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn literal(&mut self, v: usize) {
+        match v {
+            0..=143 => self.code(0x30 + v as u32, 8),
+            144..=255 => self.code(0x190 + (v - 144) as u32, 9),
+            256..=279 => self.code((v - 256) as u32, 7),
+            _ => self.code(0xC0 + (v - 280) as u32, 8),
+        }
+    }
+
+    fn length(&mut self, len: usize) {
+        let i = LENGTH_BASE.iter().rposition(|base| *base <= len).unwrap();
+        self.literal(257 + i);
+        self.write((len - LENGTH_BASE[i]) as u32, LENGTH_EXTRA[i]);
+    }
+
+    fn distance(&mut self, d: usize) {
+        let i = DISTANCE_BASE.iter().rposition(|base| *base <= d).unwrap();
+        self.code(i as u32, 5);
+        self.write((d - DISTANCE_BASE[i]) as u32, DISTANCE_EXTRA[i]);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qr::{MicroQr, Qr};
+
+    // Split into chunks, checking the signature and checksums.
+    fn chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+        assert_eq!(&png[0..8], PNG_SIGNATURE);
+        let mut res = Vec::new();
+        let mut i = 8;
+        while i < png.len() {
+            let len = u32::from_be_bytes([png[i], png[i + 1], png[i + 2], png[i + 3]]) as usize;
+            let chunk = &png[(i + 4)..(i + 8 + len)];
+            let crc = &png[(i + 8 + len)..(i + 12 + len)];
+            assert_eq!(crc32(chunk).to_be_bytes(), crc);
+            res.push((String::from_utf8(chunk[0..4].to_vec()).unwrap(), chunk[4..].to_vec()));
+            i += 12 + len;
+        }
+        res
+    }
+
+    // Inflates a zlib stream with fixed Huffman blocks, like `zlib` writes.
+    fn inflate(v: &[u8]) -> Vec<u8> {
+        let mut pos = 16;
+        let mut bit = |n: usize| {
+            let r = (0..n).fold(0, |acc, k| {
+                let b = (v[(pos + k) / 8] >> ((pos + k) % 8)) & 1;
+                acc | ((b as usize) << k)
+            });
+            pos += n;
+            r
+        };
+        assert_eq!((bit(1), bit(2)), (1, 1));
+        let mut res: Vec<u8> = Vec::new();
+        loop {
+            // Read a literal/length code, most significant bit first.
+            let mut code = 0;
+            let mut len = 0;
+            let sym = loop {
+                code = (code << 1) | bit(1);
+                len += 1;
+                match (len, code) {
+                    (7, 0..=0x17) => break 256 + code,
+                    (8, 0x30..=0xBF) => break code - 0x30,
+                    (8, 0xC0..=0xC7) => break 280 + code - 0xC0,
+                    (9, _) => break 144 + code - 0x190,
+                    _ => {}
+                }
+            };
+            match sym {
+                0..=255 => res.push(sym as u8),
+                256 => break,
+                _ => {
+                    let i = sym - 257;
+                    let len = LENGTH_BASE[i] + bit(LENGTH_EXTRA[i]);
+                    let d = (0..5).fold(0, |acc, _| (acc << 1) | bit(1));
+                    let d = DISTANCE_BASE[d] + bit(DISTANCE_EXTRA[d]);
+                    for _ in 0..len {
+                        res.push(res[res.len() - d]);
+                    }
+                }
+            }
+        }
+        assert_eq!(&v[(v.len() - 4)..], adler32(&res).to_be_bytes());
+        res
+    }
+
+    #[test]
+    fn compression() {
+        // Rows of 37 bytes with runs, some rows repeated.
+        let data: Vec<u8> = (0..2000).map(|i| ((i % 37 / 5 + i / 37 / 3) % 2 * 255) as u8)
+            .collect();
+        let z = zlib(&data, &[1, 37]);
+        assert!(z.len() < data.len() / 4);
+        assert_eq!(inflate(&z), data);
+        assert_eq!(inflate(&zlib(&[], &[1])), Vec::<u8>::new());
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+
+    #[test]
+    fn gray1() {
+        let qr = Qr::new("HELLO WORLD").unwrap();
+        let png = PngRenderer::new().module_dimensions(2, 2).render(&qr);
+        let chunks = chunks(&png);
+        let kinds: Vec<&str> = chunks.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(kinds, vec!["IHDR", "IDAT", "IEND"]);
+        // 29 modules with the quiet zone, 2 pixels each, 1-bit grayscale.
+        assert_eq!(chunks[0].1, vec![0, 0, 0, 58, 0, 0, 0, 58, 1, 0, 0, 0, 0]);
+
+        let pixels = inflate(&chunks[1].1);
+        // Each row is a filter byte and 8 bytes.
+        assert_eq!(pixels.len(), 58 * 9);
+        let is_dark = |x: usize, y: usize| pixels[y * 9 + 1 + x / 8] & (0x80 >> (x % 8)) == 0;
+        for y in 0..58 {
+            for x in 0..58 {
+                let (mx, my) = (x / 2, y / 2);
+                let dark = mx >= 4 && my >= 4 && mx < 25 && my < 25 &&
+                           qr.matrix.is_dark(mx - 4, my - 4);
+                assert_eq!(is_dark(x, y), dark, "{} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn colors() {
+        let qr = MicroQr::new("1").unwrap();
+        let png = PngRenderer::new()
+            .color_type(ColorType::Rgba)
+            .light_module(Color::hex(0xffeedd))
+            .dark_module(Color::hex(0x112233))
+            .module_dimensions(1, 1)
+            .quiet_zone(false)
+            .render_micro(&qr);
+        let header = &chunks(&png)[0].1;
+        assert_eq!(&header[0..10], &[0, 0, 0, 11, 0, 0, 0, 11, 8, 6]);
+        let pixels = inflate(&chunks(&png)[1].1);
+        assert_eq!(pixels.len(), 11 * (1 + 4 * 11));
+        assert_eq!(&pixels[1..5], &[0x11, 0x22, 0x33, 255]);
+        assert_eq!(&pixels[(1 + 4 * 7)..(1 + 4 * 8)], &[0xff, 0xee, 0xdd, 255]);
+
+        let png = PngRenderer::new()
+            .color_type(ColorType::Gray8)
+            .dark_module(Color::hex(0x336699))
+            .module_dimensions(1, 1)
+            .quiet_zone(false)
+            .render_micro(&qr);
+        let pixels = inflate(&chunks(&png)[1].1);
+        let dark = Color::hex(0x336699).luma();
+        assert_eq!(&pixels[0..9], &[0, dark, dark, dark, dark, dark, dark, dark, 255]);
+    }
+
+    #[test]
+    fn text() {
+        let qr = Qr::new("HELLO WORLD").unwrap();
+        let png = PngRenderer::new()
+            .metadata(true)
+            .text("Author", "Mårten").unwrap()
+            .text("Comment", "QR → PNG").unwrap()
+            .text("Beskrivning på svenska", "Åäö").unwrap()
+            .render(&qr);
+        let text: Vec<(String, Vec<u8>)> = chunks(&png).into_iter()
+            .filter(|(k, _)| k != "IHDR" && k != "IDAT" && k != "IEND")
+            .collect();
+        let settings = format!("QR settings\0version 1, ecl {:?}, mask {}", qr.ecl, qr.mask.0);
+        assert_eq!(text, vec![
+            ("tEXt".to_string(), b"QR payload\0HELLO WORLD".to_vec()),
+            ("tEXt".to_string(), settings.into_bytes()),
+            ("tEXt".to_string(), b"Author\0M\xe5rten".to_vec()),
+            ("iTXt".to_string(), "Comment\0\0\0\0\0QR → PNG".as_bytes().to_vec()),
+            ("tEXt".to_string(), b"Beskrivning p\xe5 svenska\0\xc5\xe4\xf6".to_vec()),
+        ]);
+
+        // Invalid keywords.
+        for keyword in ["", " Author", "Author ", "QR  payload", "Författare→", "\u{7f}",
+                        &"x".repeat(80)].iter() {
+            assert!(PngRenderer::new().text(keyword, "x").is_err(), "{:?}", keyword);
+        }
+        assert!(PngRenderer::new().text(&"å".repeat(79), "x").is_ok());
+    }
+}
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+// Longest match and farthest distance deflate can refer to.
+const MAX_LENGTH: usize = 258;
+const MAX_DISTANCE: usize = 32768;
+
+// Length codes 257 to 285.
+static LENGTH_BASE: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+static LENGTH_EXTRA: [usize; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+// Distance codes 0 to 29.
+static DISTANCE_BASE: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+static DISTANCE_EXTRA: [usize; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+lazy_static! {
+    static ref CRC_TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        for (n, entry) in table.iter_mut().enumerate() {
+            *entry = (0..8).fold(n as u32, |c, _| {
+                if c & 1 == 1 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 }
+            });
+        }
+        table
+    };
+}
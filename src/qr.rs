@@ -1,7 +1,9 @@
 //! Provides a simple and safe API.
 
-use crate::version::Version;
+use crate::data::{Fnc1, StructuredAppend};
+use crate::version::{Version, MicroVersion, RmqrVersion};
 use crate::ec::ECLevel;
+use crate::eci::Eci;
 use crate::mode::Mode;
 use crate::mask::Mask;
 use crate::matrix::Matrix;
@@ -20,8 +22,19 @@ pub struct Qr {
     pub ecl: ECLevel,
 
     /// Encoding mode.
+    /// If the data is split into several segments it's the mode
+    /// of the segment holding the most characters.
     pub mode: Mode,
 
+    /// Character set of byte mode data, if specified.
+    pub eci: Option<Eci>,
+
+    /// Position in a sequence of QR codes holding a message together, if any.
+    pub structured_append: Option<StructuredAppend>,
+
+    /// FNC1 mode, if the data is formatted per GS1 or another industry standard.
+    pub fnc1: Option<Fnc1>,
+
     /// The modules.
     pub matrix: Matrix,
 
@@ -35,6 +48,23 @@ impl Qr {
         QrBuilder::new().into(s)
     }
 
+    /// Create a new QR from a string, decoded and compared against the string.
+    /// Fails with `Error::VerificationFailed` if it doesn't match.
+    pub fn new_verified(s: &str) -> Result<Qr, Error> {
+        QrBuilder::new().verify(true).into(s)
+    }
+
+    /// Create a new QR from binary data, encoded in byte mode.
+    pub fn from_bytes(v: &[u8]) -> Result<Qr, Error> {
+        QrBuilder::new().into_bytes(v)
+    }
+
+    /// Create QR codes from a string, split over up to 16 codes using
+    /// Structured Append if it doesn't fit in a single code.
+    pub fn split(s: &str) -> Result<Vec<Qr>, Error> {
+        QrBuilder::new().into_structured_append(s)
+    }
+
     /// Create a new QR with specified error correction.
     pub fn with_ecl(s: &str, ecl: ECLevel) -> Result<Qr, Error> {
         QrBuilder::new().ecl(ecl).into(s)
@@ -51,3 +81,106 @@ impl Qr {
     }
 }
 
+
+/// A Micro QR code.
+///
+/// A smaller QR code with a single finder, suitable for small amounts of data.
+/// It needs a quiet zone of 2 modules instead of 4.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MicroQr {
+    /// Version of the Micro QR code, M1 to M4.
+    pub version: MicroVersion,
+
+    /// Error correction level.
+    pub ecl: ECLevel,
+
+    /// Encoding mode.
+    /// If the data is split into several segments it's the mode
+    /// of the segment holding the most characters.
+    pub mode: Mode,
+
+    /// The modules.
+    pub matrix: Matrix,
+
+    /// The applied Micro QR mask, 0 to 3.
+    pub mask: Mask,
+}
+
+impl MicroQr {
+    /// Create a new Micro QR from a string.
+    pub fn new(s: &str) -> Result<MicroQr, Error> {
+        MicroQrBuilder::new().into(s)
+    }
+
+    /// Create a new Micro QR with specified error correction.
+    pub fn with_ecl(s: &str, ecl: ECLevel) -> Result<MicroQr, Error> {
+        MicroQrBuilder::new().ecl(ecl).into(s)
+    }
+
+    /// Create a new Micro QR with a specified version.
+    pub fn with_version(s: &str, v: MicroVersion) -> Result<MicroQr, Error> {
+        MicroQrBuilder::new().version(v).into(s)
+    }
+
+    /// Returns the size of the Micro QR code.
+    pub fn size(&self) -> usize {
+        self.version.size()
+    }
+}
+
+
+/// A rectangular Micro QR (rMQR) code.
+///
+/// A rectangular code for long and narrow spaces, with a finder in the top left
+/// and a sub-finder in the bottom right. It needs a quiet zone of 2 modules.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Rmqr {
+    /// Version of the rMQR code, which specifies the width and height.
+    pub version: RmqrVersion,
+
+    /// Error correction level, M or H.
+    pub ecl: ECLevel,
+
+    /// Encoding mode.
+    /// If the data is split into several segments it's the mode
+    /// of the segment holding the most characters.
+    pub mode: Mode,
+
+    /// Character set of byte mode data, if specified.
+    pub eci: Option<Eci>,
+
+    /// The modules.
+    pub matrix: Matrix,
+}
+
+impl Rmqr {
+    /// Create a new rMQR from a string.
+    pub fn new(s: &str) -> Result<Rmqr, Error> {
+        RmqrBuilder::new().into(s)
+    }
+
+    /// Create a new rMQR with specified error correction.
+    pub fn with_ecl(s: &str, ecl: ECLevel) -> Result<Rmqr, Error> {
+        RmqrBuilder::new().ecl(ecl).into(s)
+    }
+
+    /// Create a new rMQR with a specified version.
+    pub fn with_version(s: &str, v: RmqrVersion) -> Result<Rmqr, Error> {
+        RmqrBuilder::new().version(v).into(s)
+    }
+
+    /// Create a new rMQR with a specified height, 7 to 17.
+    pub fn with_height(s: &str, height: usize) -> Result<Rmqr, Error> {
+        RmqrBuilder::new().height(height).into(s)
+    }
+
+    /// Returns the width of the rMQR code.
+    pub fn width(&self) -> usize {
+        self.version.width()
+    }
+
+    /// Returns the height of the rMQR code.
+    pub fn height(&self) -> usize {
+        self.version.height()
+    }
+}
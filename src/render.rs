@@ -1,121 +1,250 @@
 //! Renders the QR code to different outputs.
 //!
-//! Outputs to a string representation and svg are supported.
+//! Outputs to a string representation, compact terminal output, svg,
+//! uncompressed raster images and raw pixels are supported, other outputs can be added by implementing `Renderer`.
 use crate::matrix::{Matrix, Module};
-use crate::qr::Qr;
+use crate::qr::{Qr, MicroQr, Rmqr};
 
 use std::str::FromStr;
 use std::num::ParseIntError;
-use std::u8;
+
+/// A backend rendering QR codes to some output.
+///
+/// Implementors hold the shared `RenderOptions` and a `Palette` and only
+/// render a matrix with a given quiet zone, the setters and the quiet zone of
+/// the different symbols are handled here. The setters aren't available on
+/// trait objects, but the options are, so a renderer can be configured and
+/// then chosen at runtime:
+/// ```
+/// # use rqr::*;
+/// let svg = true;
+/// let mut renderer: Box<dyn Renderer<Output = String>> = if svg {
+///     Box::new(SvgRenderer::new().dark_module(Color::hex(0x770000)))
+/// } else {
+///     Box::new(StringRenderer::new())
+/// };
+/// renderer.options_mut().quiet_zone = false;
+/// let s = renderer.render(&Qr::new("HELLO WORLD").unwrap());
+/// ```
+pub trait Renderer {
+    /// The rendered output, like a string.
+    type Output;
+
+    /// How a module is drawn, like a character or a color.
+    type Module where Self: Sized;
+
+    /// The options shared by all renderers.
+    fn options(&self) -> &RenderOptions;
+
+    /// The options shared by all renderers, mutably.
+    fn options_mut(&mut self) -> &mut RenderOptions;
+
+    /// The light and dark module.
+    fn palette(&self) -> &Palette<Self::Module> where Self: Sized;
+
+    /// The light and dark module, mutably.
+    fn palette_mut(&mut self) -> &mut Palette<Self::Module> where Self: Sized;
+
+    /// Set the light module.
+    /// Will also be used for the quiet zone, if relevant.
+    fn light_module(mut self, v: Self::Module) -> Self where Self: Sized {
+        self.palette_mut().light = v;
+        self
+    }
+
+    /// Set the dark module.
+    fn dark_module(mut self, v: Self::Module) -> Self where Self: Sized {
+        self.palette_mut().dark = v;
+        self
+    }
+
+    /// Set if quiet zone should be produced.
+    fn quiet_zone(mut self, v: bool) -> Self where Self: Sized {
+        self.options_mut().quiet_zone = v;
+        self
+    }
+
+    /// Set the module dimensions, in the unit of the output.
+    fn module_dimensions(mut self, w: usize, h: usize) -> Self where Self: Sized {
+        assert!(w > 0 && h > 0);
+        self.options_mut().module_dimensions = Some((w, h));
+        self
+    }
+
+    /// Is a quiet zone produced?
+    fn has_quiet_zone(&self) -> bool {
+        self.options().quiet_zone
+    }
+
+    /// Render matrix with a quiet zone of `qz` modules on all sides,
+    /// 0 if the quiet zone is disabled.
+    fn render_with_qz(&self, matrix: &Matrix, qz: usize) -> Self::Output;
+
+    /// Render QR.
+    fn render(&self, qr: &Qr) -> Self::Output {
+        self.render_matrix(&qr.matrix)
+    }
+
+    /// Render Micro QR, with a smaller quiet zone.
+    fn render_micro(&self, qr: &MicroQr) -> Self::Output {
+        self.render_with_qz(&qr.matrix, if self.has_quiet_zone() { 2 } else { 0 })
+    }
+
+    /// Render rMQR, with a smaller quiet zone.
+    fn render_rmqr(&self, qr: &Rmqr) -> Self::Output {
+        self.render_with_qz(&qr.matrix, if self.has_quiet_zone() { 2 } else { 0 })
+    }
+
+    /// Render matrix.
+    fn render_matrix(&self, matrix: &Matrix) -> Self::Output {
+        self.render_with_qz(matrix, if self.has_quiet_zone() { 4 } else { 0 })
+    }
+}
+
+/// The options shared by all renderers, set through `Renderer`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// The module dimensions, if set.
+    pub module_dimensions: Option<(usize, usize)>,
+
+    /// Is a quiet zone produced?
+    pub quiet_zone: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderOptions {
+    /// Create new options with a quiet zone and unset module dimensions.
+    pub fn new() -> Self {
+        Self {
+            module_dimensions: None,
+            quiet_zone: true,
+        }
+    }
+
+    /// The module dimensions, 1 by 1 if not set.
+    pub fn module_size(&self) -> (usize, usize) {
+        self.module_dimensions.unwrap_or((1, 1))
+    }
+}
+
+/// The light and dark module of a renderer, like characters or colors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Palette<M> {
+    /// The light module, also used for the quiet zone.
+    pub light: M,
+
+    /// The dark module.
+    pub dark: M,
+}
+
+impl<M> Palette<M> {
+    /// Create a new palette.
+    pub fn new(light: M, dark: M) -> Self {
+        Self { light, dark }
+    }
+}
 
 /// A string renderer for converting a QR code into a representation
 /// suitable for text output.
 pub struct StringRenderer {
-    light: char,
-    dark: char,
-    module_w: usize,
-    module_h: usize,
-    qz: bool,
+    options: RenderOptions,
+    palette: Palette<char>,
+}
+
+impl Default for StringRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl StringRenderer {
     /// Create a new renderer.
     pub fn new() -> Self {
         Self {
-            light: '.',
-            dark: '#',
-            module_w: 1,
-            module_h: 1,
-            qz: false,
+            options: RenderOptions { quiet_zone: false, ..RenderOptions::new() },
+            palette: Palette::new('.', '#'),
         }
     }
+}
 
-    /// Set the light module character.
-    pub fn light_module(mut self, v: char) -> Self {
-        self.light = v;
-        self
-    }
+impl Renderer for StringRenderer {
+    type Output = String;
+    type Module = char;
 
-    /// Set the dark module character.
-    pub fn dark_module(mut self, v: char) -> Self {
-        self.dark = v;
-        self
+    fn options(&self) -> &RenderOptions {
+        &self.options
     }
 
-    /// Set if quiet zone should be produced.
-    pub fn quiet_zone(mut self, v: bool) -> Self {
-        self.qz = v;
-        self
+    fn options_mut(&mut self) -> &mut RenderOptions {
+        &mut self.options
     }
 
-    /// Set the module dimensions, in character count per module.
-    pub fn module_dimensions(mut self, w: usize, h: usize) -> Self {
-        assert!(w > 0 && h > 0);
-        self.module_w = w;
-        self.module_h = h;
-        self
+    fn palette(&self) -> &Palette<char> {
+        &self.palette
     }
 
-    /// Render QR to string.
-    pub fn render(&self, qr: &Qr) -> String {
-        self.render_matrix(&qr.matrix)
+    fn palette_mut(&mut self) -> &mut Palette<char> {
+        &mut self.palette
     }
 
-    /// Render matrix to string.
-    pub fn render_matrix(&self, matrix: &Matrix) -> String {
-        let mut res = String::with_capacity(matrix.size * matrix.size);
-        self.qz_lines(&mut res);
-        for y in 0..matrix.size {
+    fn render_with_qz(&self, matrix: &Matrix, qz: usize) -> String {
+        let (module_w, module_h) = self.options.module_size();
+        let mut res = String::with_capacity(matrix.width * matrix.height);
+        self.qz_lines(&mut res, qz);
+        for y in 0..matrix.height {
             // Duplicate rows for larger module dimensions.
-            for _ in 0..self.module_h {
-                let mut s = String::with_capacity(matrix.size + 1);
-                self.qz_chars(&mut s);
-                for x in 0..matrix.size {
+            for _ in 0..module_h {
+                let mut s = String::with_capacity(matrix.width + 1);
+                self.qz_chars(&mut s, qz);
+                for x in 0..matrix.width {
                     let c = if matrix.is_dark(x, y) {
-                        self.dark
+                        self.palette.dark
                     } else {
-                        self.light
+                        self.palette.light
                     };
                     // Duplicate chars for larger module dimensions.
-                    for _ in 0..self.module_w {
+                    for _ in 0..module_w {
                         s.push(c);
                     }
                 }
-                self.qz_chars(&mut s);
+                self.qz_chars(&mut s, qz);
                 s.push('\n');
                 res.push_str(&s);
             }
         }
-        self.qz_lines(&mut res);
+        self.qz_lines(&mut res, qz);
         res
     }
+}
 
+impl StringRenderer {
     // Append empty lines for quiet zone padding.
-    fn qz_lines(&self, s: &mut String) {
-        if self.qz {
-            for _ in 0..(4*self.module_h) {
-                s.push_str("\n");
-            }
+    fn qz_lines(&self, s: &mut String, qz: usize) {
+        for _ in 0..(qz * self.options.module_size().1) {
+            s.push('\n');
         }
     }
 
     // Append whitespace chars for quiet zone padding.
-    fn qz_chars(&self, s: &mut String) {
-        if self.qz {
-            for _ in 0..(4*self.module_w) {
-                s.push(' ');
-            }
+    fn qz_chars(&self, s: &mut String, qz: usize) {
+        for _ in 0..(qz * self.options.module_size().0) {
+            s.push(' ');
         }
     }
 }
 
 /// Convert to string, with chars for the different underlying representations.
 pub fn to_dbg_string(matrix: &Matrix) -> String {
-    let mut res = String::with_capacity(matrix.size * matrix.size);
+    let mut res = String::with_capacity(matrix.width * matrix.height);
     res.push('\n');
-    for y in 0..matrix.size {
-        let mut s = String::with_capacity(matrix.size + 1);
-        for x in 0..matrix.size {
+    for y in 0..matrix.height {
+        let mut s = String::with_capacity(matrix.width + 1);
+        for x in 0..matrix.width {
             let c = match matrix.get(x, y) {
                 Module::Unknown => '?',
                 Module::Reserved => '*',
@@ -205,7 +334,31 @@ impl Color {
     /// assert_eq!(Color::hex(0xff7312).to_hex_str(), "#ff7312");
     /// ```
     pub fn to_hex_str(&self) -> String {
-        String::from(format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b))
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    /// Relative luminance per WCAG, from 0.0 for black to 1.0 for white.
+    pub fn luminance(&self) -> f64 {
+        let linear = |c: u8| {
+            let c = c as f64 / 255.0;
+            if c <= 0.03928 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        };
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g) + 0.0722 * linear(self.b)
+    }
+
+    /// Gray value per ITU-R BT.601, from 0 for black to 255 for white.
+    pub fn luma(&self) -> u8 {
+        ((299 * self.r as u32 + 587 * self.g as u32 + 114 * self.b as u32 + 500) / 1000) as u8
+    }
+
+    /// Contrast ratio per WCAG, from 1.0 for equal colors to 21.0 for black on white.
+    /// ```
+    /// # use rqr::*;
+    /// assert_eq!(Color::hex(0x000000).contrast(&Color::hex(0xffffff)), 21.0);
+    /// ```
+    pub fn contrast(&self, other: &Color) -> f64 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
     }
 }
 
@@ -233,69 +386,67 @@ impl From <ParseIntError> for ParseColorError {
 
 /// A string renderer for converting a QR code into svg.
 pub struct SvgRenderer {
-    light: Color,
-    dark: Color,
+    options: RenderOptions,
+    palette: Palette<Color>,
     w: usize,
     h: usize,
-    qz: bool,
+}
+
+impl Default for SvgRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SvgRenderer {
     /// Create a new renderer.
     pub fn new() -> Self {
         Self {
-            light: Color::new(255, 255, 255),
-            dark: Color::new(0, 0, 0),
+            options: RenderOptions::new(),
+            palette: Palette::new(Color::new(255, 255, 255), Color::new(0, 0, 0)),
             w: 200,
             h: 200,
-            qz: true,
         }
     }
 
-    /// Set the light module color.
-    /// Will also be the color of the quiet zone, if relevant.
-    pub fn light_module(mut self, v: Color) -> Self {
-        self.light = v;
+    /// Set the dimensions of the output, in pixels.
+    /// Includes the quiet zone, if relevant.
+    pub fn dimensions(mut self, w: usize, h: usize) -> Self {
+        self.w = w;
+        self.h = h;
+        self.options.module_dimensions = None;
         self
     }
+}
 
-    /// Set the dark module color.
-    pub fn dark_module(mut self, v: Color) -> Self {
-        self.dark = v;
-        self
+impl Renderer for SvgRenderer {
+    type Output = String;
+    type Module = Color;
+
+    fn options(&self) -> &RenderOptions {
+        &self.options
     }
 
-    /// Set if quiet zone should be produced.
-    pub fn quiet_zone(mut self, v: bool) -> Self {
-        self.qz = v;
-        self
+    fn options_mut(&mut self) -> &mut RenderOptions {
+        &mut self.options
     }
 
-    /// Set the dimensions of the output, in pixels.
-    /// Includes the quiet zone, if relevant.
-    pub fn dimensions(mut self, w: usize, h: usize) -> Self {
-        self.w = w;
-        self.h = h;
-        self
+    fn palette(&self) -> &Palette<Color> {
+        &self.palette
     }
 
-    /// Render QR.
-    pub fn render(&self, qr: &Qr) -> String {
-        self.render_matrix(&qr.matrix)
+    fn palette_mut(&mut self) -> &mut Palette<Color> {
+        &mut self.palette
     }
 
-    /// Render matrix.
-    pub fn render_matrix(&self, matrix: &Matrix) -> String {
-        let cell_count = if self.qz { matrix.size + 8 } else { matrix.size };
-        // If not divided evenly adjust upwards and treat specified
-        // width and height as minimums.
-        let cell_w = ((self.w as f64) / (cell_count as f64)).ceil() as usize;
-        let cell_h = ((self.h as f64) / (cell_count as f64)).ceil() as usize;
+    fn render_with_qz(&self, matrix: &Matrix, qz: usize) -> String {
+        let (cell_w, cell_h) = cell_dimensions(matrix, qz, (self.w, self.h),
+                                               self.options.module_dimensions);
         // We might grow larger so readjust dimensions.
-        let w = cell_w * cell_count;
-        let h = cell_h * cell_count;
+        let w = cell_w * (matrix.width + 2 * qz);
+        let h = cell_h * (matrix.height + 2 * qz);
 
-        let mut res = String::from(format!(
+        let mut res = format!(
 "<?xml version=\"1.0\" standalone=\"yes\"?>
 <svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.1\"
     viewBox=\"0 0 {w} {h}\" shape-rendering=\"crispEdges\">
@@ -303,14 +454,14 @@ impl SvgRenderer {
 <path fill=\"{dark}\" d=\"",
         w = w,
         h = h,
-        light = self.light.to_hex_str(),
-        dark = self.dark.to_hex_str()));
+        light = self.palette.light.to_hex_str(),
+        dark = self.palette.dark.to_hex_str());
 
-        for y in 0..matrix.size {
-            let yp = if self.qz { (y + 4) * cell_h } else { y * cell_h };
+        for y in 0..matrix.height {
+            let yp = (y + qz) * cell_h;
 
-            for x in 0..matrix.size {
-                let xp = if self.qz { (x + 4) * cell_w } else { x * cell_w };
+            for x in 0..matrix.width {
+                let xp = (x + qz) * cell_w;
 
                 if matrix.is_dark(x, y) {
                     res.push_str(format!("M{x} {y}h{w}v{h}H{x}V{y}",
@@ -327,6 +478,502 @@ impl SvgRenderer {
 }
 
 
+// Size of a module in pixels, given the minimum output dimensions
+// or the module dimensions.
+fn cell_dimensions(matrix: &Matrix, qz: usize, dimensions: (usize, usize),
+                   module: Option<(usize, usize)>) -> (usize, usize) {
+    match module {
+        Some(module) => module,
+        // If not divided evenly adjust upwards and treat specified
+        // width and height as minimums.
+        None => {
+            let cell_count_w = matrix.width + 2 * qz;
+            let cell_count_h = matrix.height + 2 * qz;
+            (((dimensions.0 as f64) / (cell_count_w as f64)).ceil() as usize,
+             ((dimensions.1 as f64) / (cell_count_h as f64)).ceil() as usize)
+        }
+    }
+}
+
+/// Uncompressed raster image formats.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RasterFormat {
+    /// Plain PBM (P1), black and white as ASCII digits, wrapped at 70 characters.
+    PbmAscii,
+    /// Binary PBM (P4), black and white packed 8 pixels per byte.
+    Pbm,
+    /// Binary PGM (P5), 8-bit grayscale from the luma of the colors.
+    Pgm,
+    /// Binary PPM (P6), 8-bit RGB.
+    Ppm,
+    /// 24-bit BMP.
+    Bmp,
+}
+
+/// A renderer for converting a QR code into an uncompressed raster image.
+///
+/// Scaling and quiet zone follow `SvgRenderer`. PBM is black and white
+/// and ignores the colors.
+pub struct RasterRenderer {
+    format: RasterFormat,
+    options: RenderOptions,
+    palette: Palette<Color>,
+    w: usize,
+    h: usize,
+}
+
+impl RasterRenderer {
+    /// Create a new renderer for a format.
+    pub fn new(format: RasterFormat) -> Self {
+        Self {
+            format,
+            options: RenderOptions::new(),
+            palette: Palette::new(Color::new(255, 255, 255), Color::new(0, 0, 0)),
+            w: 200,
+            h: 200,
+        }
+    }
+
+    /// Set the dimensions of the output, in pixels.
+    /// Includes the quiet zone, if relevant.
+    pub fn dimensions(mut self, w: usize, h: usize) -> Self {
+        self.w = w;
+        self.h = h;
+        self.options.module_dimensions = None;
+        self
+    }
+}
+
+impl Renderer for RasterRenderer {
+    type Output = Vec<u8>;
+    type Module = Color;
+
+    fn options(&self) -> &RenderOptions {
+        &self.options
+    }
+
+    fn options_mut(&mut self) -> &mut RenderOptions {
+        &mut self.options
+    }
+
+    fn palette(&self) -> &Palette<Color> {
+        &self.palette
+    }
+
+    fn palette_mut(&mut self) -> &mut Palette<Color> {
+        &mut self.palette
+    }
+
+    fn render_with_qz(&self, matrix: &Matrix, qz: usize) -> Vec<u8> {
+        let (cell_w, cell_h) = cell_dimensions(matrix, qz, (self.w, self.h),
+                                               self.options.module_dimensions);
+        let w = cell_w * (matrix.width + 2 * qz);
+        let h = cell_h * (matrix.height + 2 * qz);
+        let is_dark = |px: usize, py: usize| {
+            let (x, y) = (px / cell_w, py / cell_h);
+            x >= qz && y >= qz && x - qz < matrix.width && y - qz < matrix.height &&
+                matrix.is_dark(x - qz, y - qz)
+        };
+        let color = |px, py| if is_dark(px, py) { self.palette.dark } else { self.palette.light };
+
+        let mut res = match self.format {
+            RasterFormat::PbmAscii => format!("P1\n{} {}\n", w, h),
+            RasterFormat::Pbm => format!("P4\n{} {}\n", w, h),
+            RasterFormat::Pgm => format!("P5\n{} {}\n255\n", w, h),
+            RasterFormat::Ppm => format!("P6\n{} {}\n255\n", w, h),
+            RasterFormat::Bmp => String::new(),
+        }.into_bytes();
+
+        match self.format {
+            RasterFormat::PbmAscii => {
+                // Lines should be at most 70 characters, 35 pixels with spaces.
+                for py in 0..h {
+                    let row: Vec<&str> = (0..w)
+                        .map(|px| if is_dark(px, py) { "1" } else { "0" })
+                        .collect();
+                    for line in row.chunks(35) {
+                        res.extend_from_slice(line.join(" ").as_bytes());
+                        res.push(b'\n');
+                    }
+                }
+            }
+            RasterFormat::Pbm => {
+                for py in 0..h {
+                    let start = res.len();
+                    res.resize(start + w.div_ceil(8), 0);
+                    for px in (0..w).filter(|px| is_dark(*px, py)) {
+                        res[start + px / 8] |= 0x80 >> (px % 8);
+                    }
+                }
+            }
+            RasterFormat::Pgm => {
+                for py in 0..h {
+                    res.extend((0..w).map(|px| color(px, py).luma()));
+                }
+            }
+            RasterFormat::Ppm => {
+                for py in 0..h {
+                    for px in 0..w {
+                        let c = color(px, py);
+                        res.extend_from_slice(&[c.r, c.g, c.b]);
+                    }
+                }
+            }
+            RasterFormat::Bmp => {
+                // Rows are padded to 4 bytes.
+                let stride = (3 * w).div_ceil(4) * 4;
+                let size = BMP_HEADER_LEN + stride * h;
+                res.extend_from_slice(b"BM");
+                for v in &[size as u32, 0, BMP_HEADER_LEN as u32, 40, w as u32, h as u32] {
+                    res.extend_from_slice(&v.to_le_bytes());
+                }
+                // 1 plane and 24 bits per pixel.
+                res.extend_from_slice(&[1, 0, 24, 0]);
+                // No compression and 72 dpi.
+                for v in &[0, (stride * h) as u32, 2835, 2835, 0, 0] {
+                    res.extend_from_slice(&v.to_le_bytes());
+                }
+                // Rows are stored bottom up, pixels as BGR.
+                for py in (0..h).rev() {
+                    let start = res.len();
+                    for px in 0..w {
+                        let c = color(px, py);
+                        res.extend_from_slice(&[c.b, c.g, c.r]);
+                    }
+                    res.resize(start + stride, 0);
+                }
+            }
+        }
+        res
+    }
+}
+
+/// Pixel formats of a pixel buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// One byte per pixel, the luma of the colors.
+    Luma,
+    /// Four bytes per pixel, the colors as is and fully opaque.
+    Rgba,
+}
+
+impl PixelFormat {
+    /// Number of bytes per pixel.
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Luma => 1,
+            PixelFormat::Rgba => 4,
+        }
+    }
+}
+
+/// The dimensions of a pixel buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PixelLayout {
+    /// Width in pixels.
+    pub width: usize,
+
+    /// Height in pixels.
+    pub height: usize,
+
+    /// Number of bytes from the start of a row to the start of the next.
+    pub stride: usize,
+}
+
+impl PixelLayout {
+    /// Number of bytes needed to hold the pixels.
+    pub fn buffer_len(&self) -> usize {
+        self.stride * self.height
+    }
+}
+
+/// Rendered pixels, rows from top to bottom without padding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pixels {
+    /// Dimensions of the pixels.
+    pub layout: PixelLayout,
+
+    /// Pixel format.
+    pub format: PixelFormat,
+
+    /// The pixels.
+    pub data: Vec<u8>,
+}
+
+/// An error from rendering into a buffer which is too small,
+/// or with a stride shorter than a row.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BufferSizeError;
+
+/// A renderer for converting a QR code into raw pixels,
+/// like for uploading to a texture.
+/// ```
+/// # use rqr::*;
+/// let qr = Qr::new("HELLO WORLD").unwrap();
+/// let renderer = PixelRenderer::new(PixelFormat::Rgba).module_dimensions(4, 4);
+/// let layout = renderer.layout(&qr);
+/// let mut buf = vec![0; layout.buffer_len()];
+/// renderer.render_into(&qr, &mut buf, layout.stride).unwrap();
+/// assert_eq!(renderer.render(&qr).data, buf);
+/// ```
+pub struct PixelRenderer {
+    format: PixelFormat,
+    options: RenderOptions,
+    palette: Palette<Color>,
+}
+
+impl PixelRenderer {
+    /// Create a new renderer for a pixel format.
+    pub fn new(format: PixelFormat) -> Self {
+        Self {
+            format,
+            options: RenderOptions::new(),
+            palette: Palette::new(Color::new(255, 255, 255), Color::new(0, 0, 0)),
+        }
+    }
+
+    /// The dimensions of a rendered QR, with rows without padding.
+    pub fn layout(&self, qr: &Qr) -> PixelLayout {
+        self.layout_with_qz(&qr.matrix, self.qz_of(4))
+    }
+
+    /// The dimensions of a rendered Micro QR, with rows without padding.
+    pub fn layout_micro(&self, qr: &MicroQr) -> PixelLayout {
+        self.layout_with_qz(&qr.matrix, self.qz_of(2))
+    }
+
+    /// The dimensions of a rendered rMQR, with rows without padding.
+    pub fn layout_rmqr(&self, qr: &Rmqr) -> PixelLayout {
+        self.layout_with_qz(&qr.matrix, self.qz_of(2))
+    }
+
+    /// The dimensions of a rendered matrix, with rows without padding.
+    pub fn layout_matrix(&self, matrix: &Matrix) -> PixelLayout {
+        self.layout_with_qz(matrix, self.qz_of(4))
+    }
+
+    /// Render QR into a buffer, where rows start `stride` bytes apart.
+    /// Bytes between the rows are left as is.
+    pub fn render_into(&self, qr: &Qr, buf: &mut [u8], stride: usize)
+            -> Result<PixelLayout, BufferSizeError> {
+        self.render_into_with_qz(&qr.matrix, self.qz_of(4), buf, stride)
+    }
+
+    /// Render Micro QR into a buffer, like `render_into`.
+    pub fn render_micro_into(&self, qr: &MicroQr, buf: &mut [u8], stride: usize)
+            -> Result<PixelLayout, BufferSizeError> {
+        self.render_into_with_qz(&qr.matrix, self.qz_of(2), buf, stride)
+    }
+
+    /// Render rMQR into a buffer, like `render_into`.
+    pub fn render_rmqr_into(&self, qr: &Rmqr, buf: &mut [u8], stride: usize)
+            -> Result<PixelLayout, BufferSizeError> {
+        self.render_into_with_qz(&qr.matrix, self.qz_of(2), buf, stride)
+    }
+
+    /// Render matrix into a buffer, like `render_into`.
+    pub fn render_matrix_into(&self, matrix: &Matrix, buf: &mut [u8], stride: usize)
+            -> Result<PixelLayout, BufferSizeError> {
+        self.render_into_with_qz(matrix, self.qz_of(4), buf, stride)
+    }
+
+    // The quiet zone of a symbol with quiet zone `qz`, 0 if disabled.
+    fn qz_of(&self, qz: usize) -> usize {
+        if self.options.quiet_zone { qz } else { 0 }
+    }
+
+    fn render_into_with_qz(&self, matrix: &Matrix, qz: usize, buf: &mut [u8], stride: usize)
+            -> Result<PixelLayout, BufferSizeError> {
+        let layout = PixelLayout { stride, ..self.layout_with_qz(matrix, qz) };
+        let row_len = layout.width * self.format.bytes_per_pixel();
+        if stride < row_len || buf.len() < stride * (layout.height - 1) + row_len {
+            return Err(BufferSizeError);
+        }
+        self.fill(matrix, qz, buf, layout);
+        Ok(layout)
+    }
+
+    fn layout_with_qz(&self, matrix: &Matrix, qz: usize) -> PixelLayout {
+        let (module_w, module_h) = self.options.module_size();
+        let width = (matrix.width + 2 * qz) * module_w;
+        PixelLayout {
+            width,
+            height: (matrix.height + 2 * qz) * module_h,
+            stride: width * self.format.bytes_per_pixel(),
+        }
+    }
+
+    fn fill(&self, matrix: &Matrix, qz: usize, buf: &mut [u8], layout: PixelLayout) {
+        let bpp = self.format.bytes_per_pixel();
+        let pixel = |c: Color| match self.format {
+            PixelFormat::Luma => [c.luma(), 0, 0, 0],
+            PixelFormat::Rgba => [c.r, c.g, c.b, 255],
+        };
+        let (light, dark) = (pixel(self.palette.light), pixel(self.palette.dark));
+        let (module_w, module_h) = self.options.module_size();
+        for py in 0..layout.height {
+            let row = &mut buf[(py * layout.stride)..(py * layout.stride + layout.width * bpp)];
+            for (px, p) in row.chunks_mut(bpp).enumerate() {
+                let (x, y) = (px / module_w, py / module_h);
+                let is_dark = x >= qz && y >= qz && x - qz < matrix.width &&
+                              y - qz < matrix.height && matrix.is_dark(x - qz, y - qz);
+                p.copy_from_slice(&(if is_dark { dark } else { light })[..bpp]);
+            }
+        }
+    }
+}
+
+impl Renderer for PixelRenderer {
+    type Output = Pixels;
+    type Module = Color;
+
+    fn options(&self) -> &RenderOptions {
+        &self.options
+    }
+
+    fn options_mut(&mut self) -> &mut RenderOptions {
+        &mut self.options
+    }
+
+    fn palette(&self) -> &Palette<Color> {
+        &self.palette
+    }
+
+    fn palette_mut(&mut self) -> &mut Palette<Color> {
+        &mut self.palette
+    }
+
+    fn render_with_qz(&self, matrix: &Matrix, qz: usize) -> Pixels {
+        let layout = self.layout_with_qz(matrix, qz);
+        let mut data = vec![0; layout.buffer_len()];
+        self.fill(matrix, qz, &mut data, layout);
+        Pixels { layout, format: self.format, data }
+    }
+}
+
+/// A renderer for compact terminal output, packing two rows of modules
+/// into each line with half block characters.
+/// ```
+/// # use rqr::*;
+/// let qr = Qr::new("HELLO WORLD").unwrap();
+/// // For terminals with light text on a dark background.
+/// let s = TerminalRenderer::new().invert(true).render(&qr);
+/// assert_eq!(s.lines().count(), 15);
+/// ```
+pub struct TerminalRenderer {
+    options: RenderOptions,
+    palette: Palette<Color>,
+    invert: bool,
+    ansi: bool,
+}
+
+impl Default for TerminalRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TerminalRenderer {
+    /// Create a new renderer.
+    pub fn new() -> Self {
+        Self {
+            options: RenderOptions::new(),
+            palette: Palette::new(Color::new(255, 255, 255), Color::new(0, 0, 0)),
+            invert: false,
+            ansi: false,
+        }
+    }
+
+    /// Set if the light modules should be drawn instead of the dark ones,
+    /// for terminals with light text on a dark background.
+    /// Ignored with ANSI colors.
+    pub fn invert(mut self, v: bool) -> Self {
+        self.invert = v;
+        self
+    }
+
+    /// Set if the module colors should be drawn with ANSI escape codes,
+    /// independent of the terminal theme. Requires 24-bit color support.
+    pub fn ansi_colors(mut self, v: bool) -> Self {
+        self.ansi = v;
+        self
+    }
+}
+
+impl Renderer for TerminalRenderer {
+    type Output = String;
+    type Module = Color;
+
+    fn options(&self) -> &RenderOptions {
+        &self.options
+    }
+
+    fn options_mut(&mut self) -> &mut RenderOptions {
+        &mut self.options
+    }
+
+    fn palette(&self) -> &Palette<Color> {
+        &self.palette
+    }
+
+    fn palette_mut(&mut self) -> &mut Palette<Color> {
+        &mut self.palette
+    }
+
+    fn render_with_qz(&self, matrix: &Matrix, qz: usize) -> String {
+        let (module_w, module_h) = self.options.module_size();
+        let w = (matrix.width + 2 * qz) * module_w;
+        let h = (matrix.height + 2 * qz) * module_h;
+        // Rows past the bottom are None.
+        let is_dark = |px: usize, py: usize| {
+            if py >= h {
+                return None;
+            }
+            let (x, y) = (px / module_w, py / module_h);
+            Some(x >= qz && y >= qz && x - qz < matrix.width && y - qz < matrix.height &&
+                 matrix.is_dark(x - qz, y - qz))
+        };
+
+        let mut res = String::new();
+        for py in (0..h).step_by(2) {
+            let mut current = None;
+            for px in 0..w {
+                let (top, bottom) = (is_dark(px, py).unwrap(), is_dark(px, py + 1));
+                if self.ansi {
+                    let color = |dark| if dark { self.palette.dark } else { self.palette.light };
+                    let colors = (color(top), bottom.map(color));
+                    // Only change colors when needed.
+                    if current != Some(colors) {
+                        let (fg, bg) = colors;
+                        res.push_str(&format!("\x1b[38;2;{};{};{}m", fg.r, fg.g, fg.b));
+                        match bg {
+                            Some(bg) => res.push_str(&format!("\x1b[48;2;{};{};{}m",
+                                                              bg.r, bg.g, bg.b)),
+                            None => res.push_str("\x1b[49m"),
+                        }
+                        current = Some(colors);
+                    }
+                    res.push('\u{2580}');
+                } else {
+                    let top = top != self.invert;
+                    let bottom = bottom.is_some_and(|b| b != self.invert);
+                    res.push(match (top, bottom) {
+                        (true, true) => '\u{2588}',
+                        (true, false) => '\u{2580}',
+                        (false, true) => '\u{2584}',
+                        (false, false) => ' ',
+                    });
+                }
+            }
+            if self.ansi {
+                res.push_str("\x1b[0m");
+            }
+            res.push('\n');
+        }
+        res
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -372,6 +1019,287 @@ XXXXXXXXXXXXXX~~~~XX~~XX~~~~~~~~~~~~~~XX~~
         assert_eq!(s, expected);
     }
 
+    #[test]
+    fn micro_quiet_zone() {
+        let qr = MicroQr::new("1").unwrap();
+        let s = StringRenderer::new().quiet_zone(true).render_micro(&qr);
+        let lines: Vec<&str> = s.split('\n').collect();
+        // 2 modules of quiet zone on all sides.
+        assert_eq!(lines.len(), 2 + 11 + 2 + 1);
+        assert_eq!(lines[2].len(), 2 + 11 + 2);
+        assert!(lines[2].starts_with("  #######"));
+    }
+
+    #[test]
+    fn rmqr_rectangle() {
+        let qr = Rmqr::with_height("123", 7).unwrap();
+        let s = StringRenderer::new().quiet_zone(true).render_rmqr(&qr);
+        let lines: Vec<&str> = s.split('\n').collect();
+        assert_eq!(lines.len(), 2 + 7 + 2 + 1);
+        assert_eq!(lines[2].len(), 2 + 43 + 2);
+
+        let s = SvgRenderer::new().dimensions(470, 110).render_rmqr(&qr);
+        assert!(s.contains("viewBox=\"0 0 470 110\""));
+    }
+
+    // Reads back the pixels of a raster image, as rows of dark or light.
+    fn read_raster(format: RasterFormat, v: &[u8]) -> Vec<Vec<bool>> {
+        if format == RasterFormat::Bmp {
+            let u32_at = |i: usize| u32::from_le_bytes([v[i], v[i + 1], v[i + 2], v[i + 3]]);
+            assert_eq!(&v[0..2], b"BM");
+            assert_eq!(u32_at(2) as usize, v.len());
+            let (w, h) = (u32_at(18) as usize, u32_at(22) as usize);
+            let stride = (3 * w).div_ceil(4) * 4;
+            return (0..h).rev()
+                .map(|y| (0..w).map(|x| v[54 + y * stride + 3 * x] < 128).collect())
+                .collect();
+        }
+        let text = String::from_utf8_lossy(v);
+        let header: Vec<&str> = text.split_whitespace().take(4).collect();
+        let (w, h): (usize, usize) = (header[1].parse().unwrap(), header[2].parse().unwrap());
+        let data = |fields: usize| {
+            // The data follows a single whitespace after the last header field.
+            let mut pos = 0;
+            for _ in 0..fields {
+                pos += v[pos..].iter().position(|b| b.is_ascii_whitespace()).unwrap() + 1;
+            }
+            &v[pos..]
+        };
+        match format {
+            RasterFormat::PbmAscii => {
+                let bits: Vec<bool> = text.split_whitespace().skip(3).map(|b| b == "1").collect();
+                bits.chunks(w).map(|row| row.to_vec()).collect()
+            }
+            RasterFormat::Pbm => {
+                let stride = w.div_ceil(8);
+                let data = data(3);
+                assert_eq!(data.len(), stride * h);
+                data.chunks(stride)
+                    .map(|row| (0..w).map(|x| row[x / 8] & (0x80 >> (x % 8)) != 0).collect())
+                    .collect()
+            }
+            RasterFormat::Pgm => data(4).chunks(w).map(|row| row.iter().map(|b| *b < 128).collect())
+                .collect(),
+            RasterFormat::Ppm => data(4).chunks(3 * w)
+                .map(|row| row.chunks(3).map(|c| c[1] < 128).collect())
+                .collect(),
+            RasterFormat::Bmp => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn raster_renderer() {
+        let qr = Qr::new("HELLO WORLD").unwrap();
+        for format in &[RasterFormat::PbmAscii, RasterFormat::Pbm, RasterFormat::Pgm,
+                        RasterFormat::Ppm, RasterFormat::Bmp] {
+            // Not evenly divided, rounded up to 7 pixels per module like svg.
+            let v = RasterRenderer::new(*format).dimensions(200, 200).render(&qr);
+            let pixels = read_raster(*format, &v);
+            assert_eq!(pixels.len(), 7 * 29, "{:?}", format);
+            if *format == RasterFormat::PbmAscii {
+                let text = String::from_utf8(v.clone()).unwrap();
+                assert!(text.lines().all(|line| line.len() <= 70));
+                // Rows of 203 pixels are split over 6 lines.
+                assert_eq!(text.lines().count(), 2 + 6 * 7 * 29);
+            }
+            for (y, row) in pixels.iter().enumerate() {
+                assert_eq!(row.len(), 7 * 29);
+                for (x, dark) in row.iter().enumerate() {
+                    let (mx, my) = (x / 7, y / 7);
+                    let expected = mx >= 4 && my >= 4 && mx < 25 && my < 25 &&
+                                   qr.matrix.is_dark(mx - 4, my - 4);
+                    assert_eq!(*dark, expected, "{:?} {} {}", format, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn raster_colors() {
+        let qr = MicroQr::new("1").unwrap();
+        let renderer = |format| RasterRenderer::new(format)
+            .light_module(Color::hex(0xffeedd))
+            .dark_module(Color::hex(0x112233))
+            .module_dimensions(3, 1)
+            .quiet_zone(false);
+        let v = renderer(RasterFormat::Ppm).render_micro(&qr);
+        assert!(v.starts_with(b"P6\n33 11\n255\n"));
+        assert_eq!(&v[13..16], &[0x11, 0x22, 0x33]);
+        assert_eq!(&v[(13 + 3 * 21)..(13 + 3 * 22)], &[0xff, 0xee, 0xdd]);
+        let v = renderer(RasterFormat::Pgm).render_micro(&qr);
+        assert_eq!(&v[13..14], &[Color::hex(0x112233).luma()]);
+        // 33 pixels of 3 bytes padded to 100 bytes a row.
+        let v = renderer(RasterFormat::Bmp).render_micro(&qr);
+        assert_eq!(v.len(), 54 + 100 * 11);
+        assert_eq!(&v[(54 + 100 * 10)..(54 + 100 * 10 + 3)], &[0x33, 0x22, 0x11]);
+        // PBM ignores colors.
+        let v = renderer(RasterFormat::PbmAscii).quiet_zone(true).render_micro(&qr);
+        assert!(v.starts_with(b"P1\n45 15\n0 0 0"));
+    }
+
+    #[test]
+    fn pixel_renderer() {
+        let qr = Qr::new("HELLO WORLD").unwrap();
+        let renderer = PixelRenderer::new(PixelFormat::Luma)
+            .dark_module(Color::hex(0x336699))
+            .module_dimensions(2, 3);
+        let pixels = renderer.render(&qr);
+        let layout = PixelLayout { width: 58, height: 87, stride: 58 };
+        assert_eq!(pixels.layout, layout);
+        assert_eq!(renderer.layout(&qr), layout);
+        assert_eq!(pixels.data.len(), 58 * 87);
+        for y in 0..87 {
+            for x in 0..58 {
+                let (mx, my) = (x / 2, y / 3);
+                let dark = mx >= 4 && my >= 4 && mx < 25 && my < 25 &&
+                           qr.matrix.is_dark(mx - 4, my - 4);
+                let expected = if dark { Color::hex(0x336699).luma() } else { 255 };
+                assert_eq!(pixels.data[y * 58 + x], expected);
+            }
+        }
+
+        // Rows are padded, the padding is untouched.
+        let renderer = PixelRenderer::new(PixelFormat::Rgba)
+            .light_module(Color::hex(0xffeedd))
+            .quiet_zone(false);
+        let mut buf = vec![7; 100 * 20 + 84];
+        let layout = renderer.render_into(&qr, &mut buf, 100).unwrap();
+        assert_eq!(layout, PixelLayout { width: 21, height: 21, stride: 100 });
+        assert_eq!(&buf[0..4], &[0, 0, 0, 255]);
+        assert_eq!(&buf[28..32], &[0xff, 0xee, 0xdd, 255]);
+        assert_eq!(&buf[84..100], &[7; 16]);
+        assert_eq!(renderer.render_into(&qr, &mut buf, 83), Err(BufferSizeError));
+        assert_eq!(renderer.render_into(&qr, &mut buf[1..], 100), Err(BufferSizeError));
+
+        let micro = MicroQr::new("1").unwrap();
+        let pixels = renderer.render_micro(&micro);
+        assert_eq!(pixels.layout, PixelLayout { width: 11, height: 11, stride: 44 });
+        assert_eq!(renderer.layout_micro(&micro), pixels.layout);
+        let mut buf = vec![7; 100 * 10 + 44];
+        let layout = renderer.render_micro_into(&micro, &mut buf, 100).unwrap();
+        assert_eq!(layout, PixelLayout { width: 11, height: 11, stride: 100 });
+        assert_eq!(&buf[0..44], &pixels.data[0..44]);
+        assert_eq!(&buf[44..100], &[7; 56]);
+
+        // Micro QR and rMQR have a quiet zone of 2, a plain matrix of 4.
+        let renderer = PixelRenderer::new(PixelFormat::Luma);
+        let rmqr = Rmqr::with_height("123", 7).unwrap();
+        let layout = renderer.layout_rmqr(&rmqr);
+        assert_eq!(layout, PixelLayout { width: rmqr.matrix.width + 4, height: 11,
+                                         stride: rmqr.matrix.width + 4 });
+        let mut buf = vec![0; layout.buffer_len()];
+        renderer.render_rmqr_into(&rmqr, &mut buf, layout.stride).unwrap();
+        assert_eq!(renderer.render_rmqr(&rmqr).data, buf);
+        let layout = renderer.layout_matrix(&rmqr.matrix);
+        assert_eq!((layout.width, layout.height), (rmqr.matrix.width + 8, 15));
+        let mut buf = vec![0; layout.buffer_len()];
+        renderer.render_matrix_into(&rmqr.matrix, &mut buf, layout.stride).unwrap();
+        assert_eq!(renderer.render_matrix(&rmqr.matrix).data, buf);
+        assert_eq!(renderer.render_matrix_into(&rmqr.matrix, &mut buf[1..], layout.stride),
+                   Err(BufferSizeError));
+    }
+
+    #[test]
+    fn terminal_renderer() {
+        let qr = Qr::new("HELLO WORLD").unwrap();
+        let s = TerminalRenderer::new().quiet_zone(false).render(&qr);
+        let lines: Vec<Vec<char>> = s.lines().map(|l| l.chars().collect()).collect();
+        // 21 rows packed into 11 lines, the last one half empty.
+        assert_eq!(lines.len(), 11);
+        assert!(lines.iter().all(|l| l.len() == 21));
+        for (i, line) in lines.iter().enumerate() {
+            for (x, c) in line.iter().enumerate() {
+                let top = qr.matrix.is_dark(x, 2 * i);
+                let bottom = 2 * i + 1 < 21 && qr.matrix.is_dark(x, 2 * i + 1);
+                let expected = match (top, bottom) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                };
+                assert_eq!(*c, expected, "{} {}", x, i);
+            }
+        }
+        // The bottom of the finders and the separator below.
+        assert_eq!(lines[3][0..8], ['▀', '▀', '▀', '▀', '▀', '▀', '▀', ' ']);
+
+        let s = TerminalRenderer::new().invert(true).render(&qr);
+        let lines: Vec<&str> = s.lines().collect();
+        // The quiet zone is drawn when inverted.
+        assert_eq!(lines.len(), 15);
+        assert_eq!(lines[0], "█".repeat(29));
+        assert!(lines[2].starts_with("████ ▄▄▄▄▄ █"));
+        assert_eq!(lines[14], "▀".repeat(29));
+    }
+
+    #[test]
+    fn terminal_ansi_colors() {
+        let qr = MicroQr::new("1").unwrap();
+        let s = TerminalRenderer::new()
+            .ansi_colors(true)
+            .light_module(Color::hex(0xffeedd))
+            .dark_module(Color::hex(0x112233))
+            .quiet_zone(false)
+            .render_micro(&qr);
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(lines.len(), 6);
+        // Colors are only changed when needed, over the top of the finder.
+        let (dark, light) = ("17;34;51", "255;238;221");
+        let colors = |fg, bg| format!("\x1b[38;2;{}m\x1b[48;2;{}m", fg, bg);
+        assert!(lines[0].starts_with(&format!("{}▀{}▀▀▀▀▀{}▀{}▀",
+                                              colors(dark, dark), colors(dark, light),
+                                              colors(dark, dark), colors(light, light))));
+        assert!(lines.iter().all(|l| l.ends_with("\x1b[0m")));
+        // The last line has no bottom row.
+        assert!(lines[5].contains("\x1b[49m"));
+    }
+
+    #[test]
+    fn svg_module_dimensions() {
+        let qr = Qr::new("HELLO WORLD").unwrap();
+        let s = SvgRenderer::new().module_dimensions(3, 2).render(&qr);
+        assert!(s.contains("viewBox=\"0 0 87 58\""));
+        let s = SvgRenderer::new().quiet_zone(false).module_dimensions(3, 2).render(&qr);
+        assert!(s.contains("viewBox=\"0 0 63 42\""));
+        // The last setter wins.
+        let s = SvgRenderer::new().module_dimensions(3, 2).dimensions(290, 290).render(&qr);
+        assert!(s.contains("viewBox=\"0 0 290 290\""));
+    }
+
+    #[test]
+    fn renderer_trait() {
+        // Custom renderers get the quiet zone of each symbol.
+        struct Count(RenderOptions, Palette<()>);
+        impl Renderer for Count {
+            type Output = usize;
+            type Module = ();
+            fn options(&self) -> &RenderOptions { &self.0 }
+            fn options_mut(&mut self) -> &mut RenderOptions { &mut self.0 }
+            fn palette(&self) -> &Palette<()> { &self.1 }
+            fn palette_mut(&mut self) -> &mut Palette<()> { &mut self.1 }
+            fn render_with_qz(&self, matrix: &Matrix, qz: usize) -> usize {
+                (matrix.width + 2 * qz) * (matrix.height + 2 * qz)
+            }
+        }
+        let qr = Qr::new("HELLO WORLD").unwrap();
+        let count = Count(RenderOptions::new(), Palette::new((), ()));
+        assert_eq!(count.render(&qr), 29 * 29);
+        let count = count.quiet_zone(false);
+        assert_eq!(count.render(&qr), 21 * 21);
+        assert_eq!(count.quiet_zone(true).render_micro(&MicroQr::new("1").unwrap()), 15 * 15);
+
+        let mut renderers: Vec<Box<dyn Renderer<Output = String>>> = vec![
+            Box::new(StringRenderer::new()),
+            Box::new(SvgRenderer::new()),
+        ];
+        assert!(renderers.iter().all(|r| !r.render(&qr).is_empty()));
+        // The options can be set on trait objects.
+        for r in renderers.iter_mut() {
+            r.options_mut().quiet_zone = false;
+        }
+        assert!(renderers.iter().all(|r| !r.has_quiet_zone()));
+    }
+
     #[test]
     fn svg_renderer() {
         let mut builder = QrBuilder::new()
@@ -401,3 +1329,5 @@ XXXXXXXXXXXXXX~~~~XX~~XX~~~~~~~~~~~~~~XX~~
     }
 }
 
+// Size of the BMP file and info headers.
+const BMP_HEADER_LEN: usize = 54;
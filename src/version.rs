@@ -2,6 +2,8 @@
 
 use crate::mode::Mode;
 use crate::ec::ECLevel;
+use crate::data::{self, Segment};
+use crate::info;
 
 /// QR code version, defines the size
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -10,52 +12,87 @@ pub struct Version(pub usize);
 impl Version {
     /// Create a new version, must be in the [1..40] range.
     pub fn new(v: usize) -> Version {
-        assert!(v >= 1 && v <= 40);
+        assert!((1..=40).contains(&v));
         Version(v)
     }
 
     /// Calculate the minimal required version to hold the string
     /// in the given mode with the required error correction level.
     pub fn minimal(s: &str, mode: Mode, e: ECLevel) -> Option<Version> {
-        let len = s.len();
-        for v in 0..40 {
-            let capacity = CAPACITIES[v][e as usize][mode as usize];
-            if len <= capacity {
-                return Some(Version(v + 1));
-            }
-        }
-        None
+        let data_len = mode.data_bit_len(mode.char_count(s));
+        Version::minimal_by(e, |v| 4 + v.char_count_len(mode) + data_len)
+    }
+
+    /// Calculate the minimal required version to hold the segments
+    /// with the required error correction level.
+    pub fn minimal_for_segments(segments: &[Segment], e: ECLevel) -> Option<Version> {
+        Version::minimal_by(e, |v| data::segments_bit_len(segments, v))
+    }
+
+    /// Calculate the minimal required version to hold a number of bits
+    /// with the required error correction level.
+    /// The bit length may depend on the version, as char count lengths differs.
+    pub fn minimal_by<F>(e: ECLevel, bit_len: F) -> Option<Version>
+        where F: Fn(Version) -> usize
+    {
+        (1..41).map(Version::new).find(|v| bit_len(*v) <= info::total_bits(*v, e))
     }
 
-    /// Return the data capacity.
+    /// Return the data capacity, the number of characters a single segment can hold.
     pub fn capacity(&self, mode: Mode, e: ECLevel) -> usize {
-        CAPACITIES[self.index()][e as usize][mode as usize]
+        let bits = info::total_bits(*self, e) - 4 - self.char_count_len(mode);
+        match mode {
+            Mode::Numeric => 3 * (bits / 10) + [0, 0, 0, 0, 1, 1, 1, 2, 2, 2][bits % 10],
+            Mode::Alphanumeric => 2 * (bits / 11) + if bits % 11 >= 6 { 1 } else { 0 },
+            Mode::Byte => bits / 8,
+            Mode::Kanji => bits / 13,
+            Mode::Eci => 0,
+            Mode::StructuredAppend => 0,
+            Mode::Fnc1First => 0,
+            Mode::Fnc1Second => 0,
+        }
     }
 
     /// Return the size of the QR code.
     pub fn size(&self) -> usize {
-        (((self.index()) * 4) + 21)
+        (self.index() * 4) + 21
     }
 
     /// Returns the required len of the char count bit representation.
+    /// Modes without a char count, like ECI and FNC1, has a length of 0.
     pub fn char_count_len(&self, mode: Mode) -> usize {
         if self.0 >= 1 && self.0 <= 9 {
             match mode {
                 Mode::Numeric => 10,
                 Mode::Alphanumeric => 9,
                 Mode::Byte => 8,
+                Mode::Kanji => 8,
+                Mode::Eci => 0,
+                Mode::StructuredAppend => 0,
+                Mode::Fnc1First => 0,
+                Mode::Fnc1Second => 0,
             }
         } else if self.0 <= 26 {
             match mode {
                 Mode::Numeric => 12,
                 Mode::Alphanumeric => 11,
                 Mode::Byte => 16,
+                Mode::Kanji => 10,
+                Mode::Eci => 0,
+                Mode::StructuredAppend => 0,
+                Mode::Fnc1First => 0,
+                Mode::Fnc1Second => 0,
             }
         } else if self.0 <= 40 {
             match mode {
                 Mode::Numeric => 14,
                 Mode::Alphanumeric => 13,
                 Mode::Byte => 16,
+                Mode::Kanji => 12,
+                Mode::Eci => 0,
+                Mode::StructuredAppend => 0,
+                Mode::Fnc1First => 0,
+                Mode::Fnc1Second => 0,
             }
         } else {
             panic!("Malformed version {}", self.0);
@@ -74,7 +111,176 @@ impl Version {
 
     /// Return the version value - 1, suitable for indexing.
     pub fn index(&self) -> usize {
-        (self.0 - 1)
+        self.0 - 1
+    }
+}
+
+/// Micro QR code version, M1 to M4.
+///
+/// Micro QR codes are smaller than QR codes and only has a single finder.
+/// Not all modes and error correction levels are available in every version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MicroVersion(pub usize);
+
+impl MicroVersion {
+    /// Create a new Micro QR version, must be in the [1..4] range for M1 to M4.
+    pub fn new(v: usize) -> MicroVersion {
+        assert!((1..=4).contains(&v));
+        MicroVersion(v)
+    }
+
+    /// Calculate the minimal required version to hold the segments
+    /// with the required error correction level.
+    pub fn minimal_for_segments(segments: &[Segment], e: ECLevel) -> Option<MicroVersion> {
+        MicroVersion::minimal_by(e, |v| {
+            if segments.iter().all(|x| v.supports_mode(x.mode)) {
+                Some(data::micro_segments_bit_len(segments, v))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Calculate the minimal required version to hold a number of bits
+    /// with the required error correction level.
+    /// The bit length may be None if the version can't encode the data.
+    pub fn minimal_by<F>(e: ECLevel, bit_len: F) -> Option<MicroVersion>
+        where F: Fn(MicroVersion) -> Option<usize>
+    {
+        (1..5).map(MicroVersion::new).find(|v| {
+            v.supports_ecl(e) &&
+            bit_len(*v).is_some_and(|x| x <= info::micro_data_bits(*v, e))
+        })
+    }
+
+    /// Returns true if the error correction level is available.
+    /// M1 only supports error detection, which is represented by ECLevel::L.
+    pub fn supports_ecl(&self, e: ECLevel) -> bool {
+        match e {
+            ECLevel::L => true,
+            ECLevel::M => self.0 >= 2,
+            ECLevel::Q => self.0 == 4,
+            ECLevel::H => false,
+        }
+    }
+
+    /// Returns true if the mode is available.
+    /// M1 only supports numeric mode and M2 numeric and alphanumeric mode.
+    /// ECI, FNC1 and Structured Append are not available in Micro QR codes.
+    pub fn supports_mode(&self, mode: Mode) -> bool {
+        match mode {
+            Mode::Numeric => true,
+            Mode::Alphanumeric => self.0 >= 2,
+            Mode::Byte | Mode::Kanji => self.0 >= 3,
+            _ => false,
+        }
+    }
+
+    /// Return the size of the Micro QR code.
+    pub fn size(&self) -> usize {
+        2 * self.0 + 9
+    }
+
+    /// Returns the len of the mode indicator, 0 to 3 bits.
+    pub fn mode_indicator_len(&self) -> usize {
+        self.0 - 1
+    }
+
+    /// Returns the required len of the char count bit representation.
+    /// Modes not supported by the version has a length of 0.
+    pub fn char_count_len(&self, mode: Mode) -> usize {
+        if !self.supports_mode(mode) {
+            return 0;
+        }
+        match mode {
+            Mode::Numeric => self.0 + 2,
+            Mode::Alphanumeric => self.0 + 1,
+            Mode::Byte => self.0 + 1,
+            Mode::Kanji => self.0,
+            _ => 0,
+        }
+    }
+
+    /// Returns the len of the terminator, 3 to 9 bits.
+    pub fn terminator_len(&self) -> usize {
+        2 * self.0 + 1
+    }
+
+    /// Return the version value - 1, suitable for indexing.
+    pub fn index(&self) -> usize {
+        self.0 - 1
+    }
+}
+
+/// rMQR code version, one of 32 rectangular sizes from R7x43 to R17x139.
+///
+/// The value is the version indicator, 0 to 31, ordered by height and then width.
+/// Only ECLevel::M and ECLevel::H are available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RmqrVersion(pub usize);
+
+impl RmqrVersion {
+    /// Create a new rMQR version from its height and width.
+    /// Fails if there's no such size.
+    pub fn new(height: usize, width: usize) -> RmqrVersion {
+        let i = RMQR_SIZES.iter().position(|x| *x == (height, width));
+        RmqrVersion(i.unwrap_or_else(|| panic!("Malformed rMQR size R{}x{}", height, width)))
+    }
+
+    /// Returns all versions.
+    pub fn all() -> impl Iterator<Item = RmqrVersion> {
+        (0..32).map(RmqrVersion)
+    }
+
+    /// Calculate the minimal required version to hold the segments
+    /// with the required error correction level.
+    /// The height may be restricted, otherwise the version with
+    /// the smallest area is chosen.
+    pub fn minimal_for_segments(segments: &[Segment], e: ECLevel,
+                                height: Option<usize>) -> Option<RmqrVersion> {
+        RmqrVersion::minimal_by(e, height, |v| data::rmqr_segments_bit_len(segments, v))
+    }
+
+    /// Calculate the minimal required version to hold a number of bits
+    /// with the required error correction level.
+    /// The bit length may depend on the version, as char count lengths differs.
+    pub fn minimal_by<F>(e: ECLevel, height: Option<usize>, bit_len: F) -> Option<RmqrVersion>
+        where F: Fn(RmqrVersion) -> usize
+    {
+        if !RmqrVersion::supports_ecl(e) {
+            return None;
+        }
+        RmqrVersion::all()
+            .filter(|v| height.is_none_or(|h| h == v.height()))
+            .filter(|v| bit_len(*v) <= info::rmqr_total_bits(*v, e))
+            .min_by_key(|v| (v.width() * v.height(), v.height()))
+    }
+
+    /// Returns true if the error correction level is available, only M and H are.
+    pub fn supports_ecl(e: ECLevel) -> bool {
+        e == ECLevel::M || e == ECLevel::H
+    }
+
+    /// Returns the width of the rMQR code.
+    pub fn width(&self) -> usize {
+        RMQR_SIZES[self.0].1
+    }
+
+    /// Returns the height of the rMQR code.
+    pub fn height(&self) -> usize {
+        RMQR_SIZES[self.0].0
+    }
+
+    /// Returns the required len of the char count bit representation.
+    /// Modes without a char count, like ECI and FNC1, has a length of 0.
+    pub fn char_count_len(&self, mode: Mode) -> usize {
+        match mode {
+            Mode::Numeric => RMQR_CHAR_COUNT_LEN[self.0][0],
+            Mode::Alphanumeric => RMQR_CHAR_COUNT_LEN[self.0][1],
+            Mode::Byte => RMQR_CHAR_COUNT_LEN[self.0][2],
+            Mode::Kanji => RMQR_CHAR_COUNT_LEN[self.0][3],
+            _ => 0,
+        }
     }
 }
 
@@ -88,6 +294,15 @@ mod tests {
 
         assert_eq!(Version::new(1).char_count_len(Mode::Numeric), 10);
         assert_eq!(Version::new(40).char_count_len(Mode::Byte), 16);
+        assert_eq!(Version::new(10).char_count_len(Mode::Kanji), 10);
+    }
+
+    #[test]
+    fn capacity() {
+        assert_eq!(Version::new(1).capacity(Mode::Alphanumeric, ECLevel::Q), 16);
+        assert_eq!(Version::new(10).capacity(Mode::Numeric, ECLevel::M), 513);
+        assert_eq!(Version::new(40).capacity(Mode::Byte, ECLevel::L), 2953);
+        assert_eq!(Version::new(40).capacity(Mode::Kanji, ECLevel::H), 784);
     }
 
     #[test]
@@ -107,51 +322,59 @@ mod tests {
         // Go over largest capacity.
         long.push('X');
         assert_eq!(Version::minimal(&long, Mode::Byte, ECLevel::L),
-                   None)
+                   None);
+
+        // Kanji counts characters, not bytes.
+        assert_eq!(Version::minimal("日本語日本語日", Mode::Kanji, ECLevel::Q),
+                   Some(Version::new(1)));
+        assert_eq!(Version::minimal("日本語日本語日本", Mode::Kanji, ECLevel::Q),
+                   Some(Version::new(2)));
+    }
+
+    #[test]
+    fn micro_version() {
+        assert_eq!(MicroVersion::new(1).size(), 11);
+        assert_eq!(MicroVersion::new(4).size(), 17);
+
+        assert_eq!(MicroVersion::new(1).char_count_len(Mode::Numeric), 3);
+        assert_eq!(MicroVersion::new(2).char_count_len(Mode::Alphanumeric), 3);
+        assert_eq!(MicroVersion::new(3).char_count_len(Mode::Byte), 4);
+        assert_eq!(MicroVersion::new(4).char_count_len(Mode::Kanji), 4);
+        assert_eq!(MicroVersion::new(1).char_count_len(Mode::Byte), 0);
+
+        assert!(!MicroVersion::new(3).supports_ecl(ECLevel::Q));
+        assert!(MicroVersion::new(4).supports_ecl(ECLevel::Q));
+        assert!(!MicroVersion::new(4).supports_mode(Mode::Eci));
+    }
+
+    #[test]
+    fn rmqr_version() {
+        assert_eq!(RmqrVersion::new(7, 43), RmqrVersion(0));
+        assert_eq!(RmqrVersion::new(17, 139), RmqrVersion(31));
+        assert_eq!(RmqrVersion::new(11, 27).width(), 27);
+        assert_eq!(RmqrVersion::new(11, 27).height(), 11);
+        assert_eq!(RmqrVersion::new(7, 43).char_count_len(Mode::Numeric), 4);
+        assert_eq!(RmqrVersion::new(17, 139).char_count_len(Mode::Kanji), 7);
+        assert_eq!(RmqrVersion::all().count(), 32);
     }
 }
 
-// Capacities.
-// Version x ECLevel x Mode (numeric, alphanumeric, byte)
-static CAPACITIES: [[[usize; 3]; 4]; 40] = [
-    [[41, 25, 17], [34, 20, 14], [27, 16, 11], [17, 10, 7]],
-    [[77, 47, 32], [63, 38, 26], [48, 29, 20], [34, 20, 14]],
-    [[127, 77, 53], [101, 61, 42], [77, 47, 32], [58, 35, 24]],
-    [[187, 114, 78], [149, 90, 62], [111, 67, 46], [82, 50, 34]],
-    [[255, 154, 106], [202, 122, 84], [144, 87, 60], [106, 64, 44]],
-    [[322, 195, 134], [255, 154, 106], [178, 108, 74], [139, 84, 58]],
-    [[370, 224, 154], [293, 178, 122], [207, 125, 86], [154, 93, 64]],
-    [[461, 279, 192], [365, 221, 152], [259, 157, 108], [202, 122, 84]],
-    [[552, 335, 230], [432, 262, 180], [312, 189, 130], [235, 143, 98]],
-    [[652, 395, 271], [513, 311, 213], [364, 221, 151], [288, 174, 119]],
-    [[772, 468, 321], [604, 366, 251], [427, 259, 177], [331, 200, 137]],
-    [[883, 535, 367], [691, 419, 287], [489, 296, 203], [374, 227, 155]],
-    [[1022, 619, 425], [796, 483, 331], [580, 352, 241], [427, 259, 177]],
-    [[1101, 667, 458], [871, 528, 362], [621, 376, 258], [468, 283, 194]],
-    [[1250, 758, 520], [991, 600, 412], [703, 426, 292], [530, 321, 220]],
-    [[1408, 854, 586], [1082, 656, 450], [775, 470, 322], [602, 365, 250]],
-    [[1548, 938, 644], [1212, 734, 504], [876, 531, 364], [674, 408, 280]],
-    [[1725, 1046, 718], [1346, 816, 560], [948, 574, 394], [746, 452, 310]],
-    [[1903, 1153, 792], [1500, 909, 624], [1063, 644, 442], [813, 493, 338]],
-    [[2061, 1249, 858], [1600, 970, 666], [1159, 702, 482], [919, 557, 382]],
-    [[2232, 1352, 929], [1708, 1035, 711], [1224, 742, 509], [969, 587, 403]],
-    [[2409, 1460, 1003], [1872, 1134, 779], [1358, 823, 565], [1056, 640, 439]],
-    [[2620, 1588, 1091], [2059, 1248, 857], [1468, 890, 611], [1108, 672, 461]],
-    [[2812, 1704, 1171], [2188, 1326, 911], [1588, 963, 661], [1228, 744, 511]],
-    [[3057, 1853, 1273], [2395, 1451, 997], [1718, 1041, 715], [1286, 779, 535]],
-    [[3283, 1990, 1367], [2544, 1542, 1059], [1804, 1094, 751], [1425, 864, 593]],
-    [[3517, 2132, 1465], [2701, 1637, 1125], [1933, 1172, 805], [1501, 910, 625]],
-    [[3669, 2223, 1528], [2857, 1732, 1190], [2085, 1263, 868], [1581, 958, 658]],
-    [[3909, 2369, 1628], [3035, 1839, 1264], [2181, 1322, 908], [1677, 1016, 698]],
-    [[4158, 2520, 1732], [3289, 1994, 1370], [2358, 1429, 982], [1782, 1080, 742]],
-    [[4417, 2677, 1840], [3486, 2113, 1452], [2473, 1499, 1030], [1897, 1150, 790]],
-    [[4686, 2840, 1952], [3693, 2238, 1538], [2670, 1618, 1112], [2022, 1226, 842]],
-    [[4965, 3009, 2068], [3909, 2369, 1628], [2805, 1700, 1168], [2157, 1307, 898]],
-    [[5253, 3183, 2188], [4134, 2506, 1722], [2949, 1787, 1228], [2301, 1394, 958]],
-    [[5529, 3351, 2303], [4343, 2632, 1809], [3081, 1867, 1283], [2361, 1431, 983]],
-    [[5836, 3537, 2431], [4588, 2780, 1911], [3244, 1966, 1351], [2524, 1530, 1051]],
-    [[6153, 3729, 2563], [4775, 2894, 1989], [3417, 2071, 1423], [2625, 1591, 1093]],
-    [[6479, 3927, 2699], [5039, 3054, 2099], [3599, 2181, 1499], [2735, 1658, 1139]],
-    [[6743, 4087, 2809], [5313, 3220, 2213], [3791, 2298, 1579], [2927, 1774, 1219]],
-    [[7089, 4296, 2953], [5596, 3391, 2331], [3993, 2420, 1663], [3057, 1852, 1273]],
+// rMQR sizes as (height, width), indexed by the version indicator.
+static RMQR_SIZES: [(usize, usize); 32] = [
+    (7, 43), (7, 59), (7, 77), (7, 99), (7, 139),
+    (9, 43), (9, 59), (9, 77), (9, 99), (9, 139),
+    (11, 27), (11, 43), (11, 59), (11, 77), (11, 99), (11, 139),
+    (13, 27), (13, 43), (13, 59), (13, 77), (13, 99), (13, 139),
+    (15, 43), (15, 59), (15, 77), (15, 99), (15, 139),
+    (17, 43), (17, 59), (17, 77), (17, 99), (17, 139),
+];
+
+// rMQR char count lengths for numeric, alphanumeric, byte and kanji mode.
+static RMQR_CHAR_COUNT_LEN: [[usize; 4]; 32] = [
+    [4, 3, 3, 2], [5, 5, 4, 3], [6, 5, 5, 4], [7, 6, 5, 5], [7, 6, 6, 5],
+    [5, 5, 4, 3], [6, 5, 5, 4], [7, 6, 5, 5], [7, 6, 6, 5], [8, 7, 6, 6],
+    [4, 4, 3, 2], [6, 5, 5, 4], [7, 6, 5, 5], [7, 6, 6, 5], [8, 7, 6, 6], [8, 7, 7, 6],
+    [5, 5, 4, 3], [6, 6, 5, 5], [7, 6, 6, 5], [7, 7, 6, 6], [8, 7, 7, 6], [8, 8, 7, 7],
+    [7, 6, 6, 5], [7, 7, 6, 5], [8, 7, 7, 6], [8, 7, 7, 6], [9, 8, 7, 7],
+    [7, 6, 6, 5], [8, 7, 6, 6], [8, 7, 7, 6], [8, 8, 7, 6], [9, 8, 8, 7],
 ];